@@ -0,0 +1,130 @@
+//! Parses an HTML document and prints its DOM tree.
+//!
+//! Usage: dump_dom [--json] [--strip-whitespace] [--fragment <context>] [<file> | <http url>]
+//!
+//! The document is read from standard input when no file or URL is given. By default the tree is
+//! printed in the html5lib test format; `--json` prints it as JSON instead. With `--fragment`, the
+//! input is parsed as the contents of a `<context>` element. `--strip-whitespace` drops
+//! whitespace-only text between elements, which keeps snapshots of indented documents short.
+
+use std::cell::RefCell;
+use std::io::Read;
+use std::process::ExitCode;
+use std::rc::Rc;
+
+use saba_core::renderer::dom::dump::{dump_json, dump_tree};
+use saba_core::renderer::dom::node::{Element, Node, NodeKind};
+use saba_core::renderer::html::parser::{HtmlParser, WhitespaceMode};
+use saba_core::renderer::html::token::HtmlTokenizer;
+use saba_core::url::Url;
+use net_std::http::HttpClient;
+
+const USAGE: &str =
+    "usage: dump_dom [--json] [--strip-whitespace] [--fragment <context>] [<file> | <http url>]";
+
+struct Options {
+    json: bool,
+    whitespace_mode: WhitespaceMode,
+    fragment: Option<String>,
+    input: Option<String>,
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        json: false,
+        whitespace_mode: WhitespaceMode::Preserve,
+        fragment: None,
+        input: None,
+    };
+    let mut args = args;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => options.json = true,
+            "--strip-whitespace" => options.whitespace_mode = WhitespaceMode::StripInterElement,
+            "--fragment" => match args.next() {
+                Some(context) => options.fragment = Some(context),
+                None => return Err("--fragment needs a context element".to_string()),
+            },
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with("--") => return Err(format!("unknown option: {}", arg)),
+            _ if options.input.is_none() => options.input = Some(arg),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    Ok(options)
+}
+
+fn read_input(input: &Option<String>) -> Result<String, String> {
+    match input {
+        Some(url) if url.starts_with("http://") => {
+            let url = Url::new(url.clone()).parse()?;
+            let port = url
+                .port()
+                .parse::<u16>()
+                .map_err(|e| format!("invalid port {}: {}", url.port(), e))?;
+            let response = HttpClient::new()
+                .get(url.host(), port, url.path())
+                .map_err(|e| format!("failed to fetch {}: {}", url.host(), e))?;
+            Ok(response.body())
+        }
+        Some(path) => {
+            std::fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path, e))
+        }
+        None => {
+            let mut html = String::new();
+            std::io::stdin()
+                .read_to_string(&mut html)
+                .map_err(|e| format!("failed to read standard input: {}", e))?;
+            Ok(html)
+        }
+    }
+}
+
+fn run() -> Result<String, String> {
+    let options = parse_args(std::env::args().skip(1))?;
+    let html = read_input(&options.input)?;
+    let dump = if options.json { dump_json } else { dump_tree };
+
+    match options.fragment {
+        Some(context) => {
+            let context = Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(
+                &context,
+                Vec::new(),
+            )))));
+            let nodes = HtmlParser::parse_fragment_with_whitespace_mode(
+                &context,
+                html,
+                options.whitespace_mode,
+            );
+            if options.json {
+                let nodes: Vec<String> = nodes.iter().map(dump).collect();
+                Ok(format!("[{}]", nodes.join(",")))
+            } else {
+                Ok(nodes.iter().map(dump).collect())
+            }
+        }
+        None => {
+            let mut parser = HtmlParser::new(HtmlTokenizer::new(html));
+            parser.set_whitespace_mode(options.whitespace_mode);
+            let window = parser.construct_tree();
+            let document = window.borrow().document();
+            Ok(dump(&document))
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(output) => {
+            print!("{}", output);
+            if !output.ends_with('\n') {
+                println!();
+            }
+            ExitCode::SUCCESS
+        }
+        Err(message) => {
+            eprintln!("{}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
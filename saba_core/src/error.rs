@@ -5,5 +5,7 @@ pub enum Error {
   Network(String),
   UnexpectedInput(String),
   InvalidUrl(String),
+  HierarchyRequest(String),
+  NotFound(String),
   Other(String),
 }
\ No newline at end of file
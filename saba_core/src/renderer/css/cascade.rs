@@ -0,0 +1,186 @@
+use core::cell::RefCell;
+
+use alloc::{rc::Rc, string::ToString, vec::Vec};
+
+use crate::renderer::css::cssom::{CssParser, Declaration, StyleSheet};
+use crate::renderer::css::selector::Specificity;
+use crate::renderer::css::token::CssTokenizer;
+use crate::renderer::dom::node::Node;
+
+/// Where a declaration comes from. Inline styles are the declarations in a `style` attribute.
+// https://www.w3.org/TR/css-cascade-4/#cascading-origins
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+  UserAgent,
+  Author,
+  Inline,
+}
+
+// Important declarations reverse the order of origins, except that inline styles stay above other
+// author styles.
+// https://www.w3.org/TR/css-cascade-4/#cascade-origin
+fn precedence(origin: Origin, important: bool) -> u8 {
+  match (important, origin) {
+    (false, Origin::UserAgent) => 0,
+    (false, Origin::Author) => 1,
+    (false, Origin::Inline) => 2,
+    (true, Origin::Author) => 3,
+    (true, Origin::Inline) => 4,
+    (true, Origin::UserAgent) => 5,
+  }
+}
+
+/// Returns the declarations that apply to `node`, from the lowest precedence to the highest, so
+/// that the declaration applied last for each property is the one that wins. `sheets` are given
+/// in source order, and the inline style of `node` is added after them.
+// https://www.w3.org/TR/css-cascade-4/#cascade-sort
+pub fn cascade(node: &Rc<RefCell<Node>>, sheets: &[(Origin, &StyleSheet)]) -> Vec<Declaration> {
+  let mut matched = Vec::new();
+  for (origin, sheet) in sheets {
+    for rule in &sheet.rules {
+      // A rule with a selector list is as specific as its most specific matching selector.
+      let matching = rule.selectors.iter().filter(|s| s.matches(node));
+      let specificity = match matching.map(|s| s.specificity()).max() {
+        Some(specificity) => specificity,
+        None => continue,
+      };
+      for declaration in &rule.declarations {
+        matched.push((precedence(*origin, declaration.important), specificity, declaration.clone()));
+      }
+    }
+  }
+  for declaration in inline_style(node) {
+    let precedence = precedence(Origin::Inline, declaration.important);
+    matched.push((precedence, Specificity::default(), declaration));
+  }
+
+  // The sort is stable, so declarations that tie keep their source order.
+  matched.sort_by_key(|(precedence, specificity, _)| (*precedence, *specificity));
+  matched.into_iter().map(|(_, _, declaration)| declaration).collect()
+}
+
+/// Parses the stylesheet with the default presentation of HTML elements, which is applied with
+/// the user agent origin.
+pub fn user_agent_stylesheet() -> StyleSheet {
+  CssParser::new(CssTokenizer::new(include_str!("user_agent.css").to_string())).parse_stylesheet()
+}
+
+fn inline_style(node: &Rc<RefCell<Node>>) -> Vec<Declaration> {
+  let style = match node.borrow().get_element().and_then(|e| e.get_attribute("style")) {
+    Some(style) => style,
+    None => return Vec::new(),
+  };
+  CssParser::new(CssTokenizer::new(style)).parse_list_of_declarations()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::renderer::css::cssom::ComponentValue;
+  use crate::renderer::css::token::CssToken;
+  use crate::renderer::dom::query::get_element_by_id;
+  use crate::renderer::html::parser::HtmlParser;
+  use crate::renderer::html::token::HtmlTokenizer;
+  use crate::renderer::layout::computed_style::FontSize;
+  use alloc::string::String;
+
+  fn sheet(css: &str) -> StyleSheet {
+    CssParser::new(CssTokenizer::new(css.to_string())).parse_stylesheet()
+  }
+
+  // The color that wins for the element with the ID `t`.
+  fn color(html: &str, sheets: &[(Origin, &StyleSheet)]) -> String {
+    let window = HtmlParser::new(HtmlTokenizer::new(html.to_string())).construct_tree();
+    let document = window.borrow().document();
+    let node = get_element_by_id(&document, "t").expect("target should exist");
+    let declarations = cascade(&node, sheets);
+    match declarations.iter().rev().find(|d| d.property == "color").map(|d| d.value.as_slice()) {
+      Some([ComponentValue::PreservedToken(CssToken::Ident(color))]) => color.clone(),
+      _ => String::new(),
+    }
+  }
+
+  #[test]
+  fn test_source_order() {
+    let css = sheet(".a { color: red } .b { color: blue }");
+    assert_eq!("blue", color("<p id=t class='b a'>", &[(Origin::Author, &css)]));
+    let later = sheet(".a { color: green }");
+    let sheets = [(Origin::Author, &css), (Origin::Author, &later)];
+    assert_eq!("green", color("<p id=t class='a b'>", &sheets));
+  }
+
+  #[test]
+  fn test_specificity() {
+    let css = sheet("#t { color: red } p.a { color: green } .a { color: blue } p { color: gray }");
+    assert_eq!("red", color("<p id=t class=a>", &[(Origin::Author, &css)]));
+    let css = sheet("p.a { color: green } .a { color: blue } p { color: gray }");
+    assert_eq!("green", color("<p id=t class=a>", &[(Origin::Author, &css)]));
+    assert_eq!("gray", color("<p id=t>", &[(Origin::Author, &css)]));
+    // The most specific matching selector of a list counts.
+    let css = sheet("p > .a, #x b { color: red } b.a { color: green }");
+    assert_eq!("red", color("<p id=x><b id=t class=a></b>", &[(Origin::Author, &css)]));
+    assert_eq!("green", color("<div><b id=t class=a></b>", &[(Origin::Author, &css)]));
+  }
+
+  #[test]
+  fn test_important() {
+    let css = sheet("p { color: blue !important } #t { color: red }");
+    assert_eq!("blue", color("<p id=t>", &[(Origin::Author, &css)]));
+    let css = sheet("#t { color: red !important } p { color: blue !important }");
+    assert_eq!("red", color("<p id=t>", &[(Origin::Author, &css)]));
+  }
+
+  #[test]
+  fn test_origin() {
+    let ua = sheet("#t { color: red } p { color: green !important }");
+    let css = sheet("div, p { color: blue !important }");
+    let sheets = [(Origin::UserAgent, &ua), (Origin::Author, &css)];
+    assert_eq!("blue", color("<div id=t>", &sheets));
+    assert_eq!("green", color("<p id=t>", &sheets));
+    let sheets = [(Origin::Author, &css), (Origin::UserAgent, &ua)];
+    assert_eq!("blue", color("<div id=t>", &sheets));
+  }
+
+  #[test]
+  fn test_inline_style() {
+    let css = sheet("#t { color: red } p { color: blue !important }");
+    let sheets = [(Origin::Author, &css)];
+    assert_eq!("green", color("<div id=t style='color: green'>", &sheets));
+    assert_eq!("blue", color("<p id=t style='color: green'>", &sheets));
+    assert_eq!("green", color("<p id=t style='color: green !important'>", &sheets));
+    assert_eq!("red", color("<div id=t style='margin: 0; } color: green; x'>", &sheets));
+    assert_eq!("green", color("<div id=t style='color: green'>", &[]));
+  }
+
+  #[test]
+  fn test_user_agent_stylesheet() {
+    let ua = user_agent_stylesheet();
+    assert!(ua.warnings.is_empty(), "{:?}", ua.warnings);
+    let sheets = [(Origin::UserAgent, &ua)];
+    let display = |html: &str| {
+      let window = HtmlParser::new(HtmlTokenizer::new(html.to_string())).construct_tree();
+      let document = window.borrow().document();
+      let node = get_element_by_id(&document, "t").expect("target should exist");
+      let declarations = cascade(&node, &sheets);
+      match declarations.iter().rev().find(|d| d.property == "display").map(|d| d.value.as_slice()) {
+        Some([ComponentValue::PreservedToken(CssToken::Ident(display))]) => display.clone(),
+        _ => String::new(),
+      }
+    };
+    assert_eq!("block", display("<li id=t>"));
+    assert_eq!("none", display("<script id=t></script>"));
+    assert_eq!("", display("<span id=t>"));
+
+    // Layout drops font sizes it does not know, so the sheet only uses supported keywords.
+    for declaration in ua.rules.iter().flat_map(|rule| &rule.declarations) {
+      if declaration.property == "font-size" {
+        match declaration.value.as_slice() {
+          [ComponentValue::PreservedToken(CssToken::Ident(size))] => {
+            assert!(size.parse::<FontSize>().is_ok(), "unsupported font-size: {}", size)
+          }
+          value => panic!("unexpected font-size: {:?}", value),
+        }
+      }
+    }
+  }
+}
@@ -1,206 +1,358 @@
 use crate::renderer::css::token::CssTokenizer;
-use core::iter::Peekable;
+use alloc::format;
 use alloc::vec::Vec;
 use crate::alloc::string::ToString;
 use crate::alloc::string::String;
-use crate::renderer::css::token::CssToken;
+use crate::renderer::css::selector::{parse_selector_list_from_values, ComplexSelector};
+use crate::renderer::css::token::{CssToken, SourcePosition};
 
 #[derive(Debug, Clone)]
 pub struct CssParser {
-  t: Peekable<CssTokenizer>,
+  t: CssTokenizer,
+  /// A token read ahead by `peek` and the offset where it starts.
+  peeked: Option<(Option<CssToken>, usize)>,
+  warnings: Vec<CssWarning>,
 }
 
 impl CssParser {
   pub fn new(t: CssTokenizer) -> Self {
     Self {
-      t: t.peekable(),
+      t,
+      peeked: None,
+      warnings: Vec::new(),
     }
   }
 
   pub fn parse_stylesheet(&mut self) -> StyleSheet {
     let mut sheet = StyleSheet::new();
     sheet.set_rules(self.consume_list_of_rules());
+    sheet.set_warnings(core::mem::take(&mut self.warnings));
     sheet
   }
 
+  // https://www.w3.org/TR/css-syntax-3/#parse-list-of-declarations
+  pub fn parse_list_of_declarations(&mut self) -> Vec<Declaration> {
+    self.consume_list_of_declarations(false)
+  }
+
+  // https://www.w3.org/TR/css-syntax-3/#parse-list-of-component-values
+  pub fn parse_list_of_component_values(&mut self) -> Vec<ComponentValue> {
+    let mut values = Vec::new();
+    while self.peek().is_some() {
+      values.push(self.consume_component_value());
+    }
+    values
+  }
+
+  fn peek(&mut self) -> Option<&CssToken> {
+    if self.peeked.is_none() {
+      let token = self.t.next();
+      self.peeked = Some((token, self.t.token_start()));
+    }
+    self.peeked.as_ref().and_then(|(token, _)| token.as_ref())
+  }
+
+  fn next(&mut self) -> Option<CssToken> {
+    match self.peeked.take() {
+      Some((token, _)) => token,
+      None => self.t.next(),
+    }
+  }
+
+  /// The offset of the next token, or of the end of input.
+  fn next_offset(&mut self) -> usize {
+    self.peek();
+    self.peeked.as_ref().map_or(0, |(_, offset)| *offset)
+  }
+
+  fn warn(&mut self, offset: usize, message: String) {
+    let position = self.t.position(offset);
+    self.warnings.push(CssWarning::new(message, position));
+  }
+
+  // https://www.w3.org/TR/css-syntax-3/#consume-list-of-rules
   fn consume_list_of_rules(&mut self) -> Vec<QualifiedRule> {
     let mut rules = Vec::new();
 
     loop {
-      let token = match self.t.peek() {
+      let token = match self.peek() {
         Some(t) => t,
         None => return rules,
       };
       match token {
-        CssToken::AtKeyword(_keyword) => {
-          let _rule = self.consume_qualified_rule();
+        CssToken::Whitespace | CssToken::Cdo | CssToken::Cdc => {
+          self.next();
         }
+        CssToken::AtKeyword(_) => self.consume_at_rule(false),
         _ => {
-          let rule = self.consume_qualified_rule();
-          match rule {
-            Some(r) => rules.push(r),
-            None => return rules,
+          if let Some(rule) = self.consume_qualified_rule() {
+            rules.push(rule);
           }
         }
       }
     }
   }
 
-  fn consume_qualified_rule(&mut self) -> Option<QualifiedRule> {
-    let mut rule = QualifiedRule::new();
+  // At-rules are not supported yet, so they are consumed and dropped. Inside a declaration block,
+  // the `}` that closes the block also ends the at-rule.
+  // https://www.w3.org/TR/css-syntax-3/#consume-at-rule
+  fn consume_at_rule(&mut self, nested: bool) {
+    let offset = self.next_offset();
+    if let Some(CssToken::AtKeyword(name)) = self.next() {
+      self.warn(offset, format!("unsupported at-rule @{} is ignored", name));
+    }
     loop {
-      let token = match self.t.peek() {
-        Some(t) => t,
-        None => return None,
-      };
-
-      match token {
-        CssToken::OpenCurly => {
-          assert_eq!(self.t.next(), Some(CssToken::OpenCurly));
-          rule.set_declarations(self.consume_list_of_declarations());
-          return Some(rule);
+      match self.peek() {
+        None => return,
+        Some(CssToken::SemiColon) => {
+          self.next();
+          return;
         }
-        _ => {
-          rule.set_selector(self.consume_selector());
+        Some(CssToken::CloseCurly) if nested => return,
+        Some(CssToken::OpenCurly) => {
+          self.consume_component_value();
+          return;
+        }
+        Some(_) => {
+          self.consume_component_value();
         }
       }
     }
   }
 
-  fn consume_selector(&mut self) -> Selector {
-    let token = match self.t.next() {
-      Some(t) => t,
-      None => panic!("Unexpected end of input"),
-    };
-
-    match token {
-      CssToken::HashToken(value) => Selector::IdSelector(value[1..].to_string()),
-      CssToken::Delim(delim) => {
-        if delim == '.' {
-          return Selector::ClassSelector(self.consume_ident());
-
-        }
-        panic!("Unexpected token: {:?}", token);
-      }
-      CssToken::Ident(ident) => {
-        if self.t.peek() == Some(&CssToken::Colon) {
-          while self.t.peek() != Some(&CssToken::OpenCurly) {
-            self.t.next();
-          }
+  // https://www.w3.org/TR/css-syntax-3/#consume-qualified-rule
+  fn consume_qualified_rule(&mut self) -> Option<QualifiedRule> {
+    let offset = self.next_offset();
+    let mut prelude = Vec::new();
+    loop {
+      match self.peek() {
+        None => {
+          self.warn(offset, "unexpected end of input before the declaration block".to_string());
+          return None;
         }
-        Selector::TypeSelector(ident.to_string())
-      }
-      CssToken::AtKeyword(_keyword) => {
-        while self.t.peek() != Some(&CssToken::OpenCurly) {
-          self.t.next();
+        Some(CssToken::OpenCurly) => {
+          self.next();
+          let declarations = self.consume_list_of_declarations(true);
+          let selectors = match parse_selector_list_from_values(&prelude) {
+            Ok(selectors) => selectors,
+            Err(_) => {
+              self.warn(offset, "invalid selector, the rule is ignored".to_string());
+              return None;
+            }
+          };
+          let mut rule = QualifiedRule::new();
+          rule.set_selectors(selectors);
+          rule.set_declarations(declarations);
+          return Some(rule);
         }
-        Selector::UnknownSelector
-      }
-      _ => {
-        self.t.next();
-        Selector::UnknownSelector
+        Some(_) => prelude.push(self.consume_component_value()),
       }
     }
   }
 
-  fn consume_list_of_declarations(&mut self) -> Vec<Declaration> {
+  // Consumes declarations up to the end of input or, when `nested`, up to and including the `}`
+  // that closes the block.
+  // https://www.w3.org/TR/css-syntax-3/#consume-list-of-declarations
+  fn consume_list_of_declarations(&mut self, nested: bool) -> Vec<Declaration> {
     let mut declarations = Vec::new();
 
     loop {
-      let token = match self.t.peek() {
+      let token = match self.peek() {
         Some(t) => t,
-        None => return declarations,
+        None if !nested => return declarations,
+        None => {
+          let offset = self.next_offset();
+          self.warn(offset, "unexpected end of input in a declaration block".to_string());
+          return declarations;
+        }
       };
 
       match token {
-        CssToken::CloseCurly => {
-          assert_eq!(self.t.next(), Some(CssToken::CloseCurly));
+        CssToken::CloseCurly if nested => {
+          self.next();
           return declarations;
         }
-        CssToken::SemiColon => {
-          assert_eq!(self.t.next(), Some(CssToken::SemiColon));
+        CssToken::Whitespace | CssToken::SemiColon => {
+          self.next();
         }
-        CssToken::Ident(ref _ident) => {
+        CssToken::AtKeyword(_) => self.consume_at_rule(nested),
+        CssToken::Ident(_) => {
           if let Some(declaration) = self.consume_declaration() {
             declarations.push(declaration);
           }
         }
         _ => {
-          self.t.next();
+          let offset = self.next_offset();
+          self.warn(offset, "unexpected token, the declaration is ignored".to_string());
+          self.consume_component_value();
+          self.consume_declaration_value();
         }
       }
     }
   }
 
+  // https://www.w3.org/TR/css-syntax-3/#consume-declaration
   fn consume_declaration(&mut self) -> Option<Declaration> {
-    if self.t.peek().is_none() {
+    let offset = self.next_offset();
+    let mut declaration = Declaration::new();
+    match self.next() {
+      Some(CssToken::Ident(property)) => declaration.set_property(property),
+      _ => return None,
+    }
+    self.consume_whitespace();
+    if self.peek() != Some(&CssToken::Colon) {
+      self.warn(
+        offset,
+        format!("expected ':' after {}, the declaration is ignored", declaration.property),
+      );
+      self.consume_declaration_value();
       return None;
     }
+    self.next();
+    self.consume_whitespace();
+
+    let mut value = self.consume_declaration_value();
+    trim_trailing_whitespace(&mut value);
+
+    // The last two non-whitespace values being `!` and `important` make the declaration important.
+    let whitespace = ComponentValue::PreservedToken(CssToken::Whitespace);
+    let mut non_whitespace = value.iter().enumerate().rev().filter(|(_, v)| **v != whitespace);
+    if let (
+      Some((_, ComponentValue::PreservedToken(CssToken::Ident(ident)))),
+      Some((bang, ComponentValue::PreservedToken(CssToken::Delim('!')))),
+    ) = (non_whitespace.next(), non_whitespace.next())
+    {
+      if ident.eq_ignore_ascii_case("important") {
+        value.truncate(bang);
+        trim_trailing_whitespace(&mut value);
+        declaration.set_important(true);
+      }
+    }
+    declaration.set_value(value);
+    Some(declaration)
+  }
 
-    let mut declaration = Declaration::new();
-    declaration.set_property(self.consume_ident());
-    match self.t.next() {
-      Some(token) => match token {
-        CssToken::Colon => {}
-        _ => return None,
-      },
-      None => return None,
+  // Consumes component values up to, but not including, the `;` or `}` that ends a declaration.
+  fn consume_declaration_value(&mut self) -> Vec<ComponentValue> {
+    let mut values = Vec::new();
+    while let Some(token) = self.peek() {
+      if matches!(token, CssToken::SemiColon | CssToken::CloseCurly) {
+        break;
+      }
+      values.push(self.consume_component_value());
     }
+    values
+  }
 
-    declaration.set_value(self.consume_component_value());
-    Some(declaration)
+  // Callers peek first, so there is always a token to consume.
+  // https://www.w3.org/TR/css-syntax-3/#consume-component-value
+  fn consume_component_value(&mut self) -> ComponentValue {
+    match self.next().expect("should have a token") {
+      CssToken::OpenCurly => self.consume_simple_block(CssToken::OpenCurly, CssToken::CloseCurly),
+      CssToken::OpenSquare => self.consume_simple_block(CssToken::OpenSquare, CssToken::CloseSquare),
+      CssToken::OpenParenthesis => {
+        self.consume_simple_block(CssToken::OpenParenthesis, CssToken::CloseParenthesis)
+      }
+      CssToken::Function(name) => {
+        ComponentValue::Function(name, self.consume_values_until(&CssToken::CloseParenthesis))
+      }
+      token => ComponentValue::PreservedToken(token),
+    }
   }
 
-  fn consume_ident(&mut self) -> String {
-    let token = match self.t.next() {
-      Some(t) => t,
-      None => panic!("Unexpected end of input"),
-    };
+  // https://www.w3.org/TR/css-syntax-3/#consume-simple-block
+  fn consume_simple_block(&mut self, open: CssToken, close: CssToken) -> ComponentValue {
+    ComponentValue::SimpleBlock(open, self.consume_values_until(&close))
+  }
 
-    match token {
-      CssToken::Ident(ident) => ident.to_string(),
-      _ => panic!("Unexpected token: {:?}", token),
+  // Consumes component values up to and including `close`, or to the end of input.
+  fn consume_values_until(&mut self, close: &CssToken) -> Vec<ComponentValue> {
+    let mut values = Vec::new();
+    loop {
+      match self.peek() {
+        None => return values,
+        Some(token) if token == close => {
+          self.next();
+          return values;
+        }
+        Some(_) => values.push(self.consume_component_value()),
+      }
     }
   }
 
-  fn consume_component_value(&mut self) -> ComponentValue {
-    self.t.next().expect("should have a token")
+  fn consume_whitespace(&mut self) {
+    while self.peek() == Some(&CssToken::Whitespace) {
+      self.next();
+    }
+  }
+}
+
+fn trim_trailing_whitespace(values: &mut Vec<ComponentValue>) {
+  while values.last() == Some(&ComponentValue::PreservedToken(CssToken::Whitespace)) {
+    values.pop();
   }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct StyleSheet {
   pub rules: Vec<QualifiedRule>,
+  /// Parse errors that were recovered from by dropping a declaration or a rule.
+  pub warnings: Vec<CssWarning>,
 }
 
 impl StyleSheet {
   pub fn new() -> Self {
     Self {
       rules: Vec::new(),
+      warnings: Vec::new(),
     }
   }
 
   pub fn set_rules(&mut self, rules: Vec<QualifiedRule>) {
     self.rules = rules;
   }
+
+  pub fn set_warnings(&mut self, warnings: Vec<CssWarning>) {
+    self.warnings = warnings;
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CssWarning {
+  message: String,
+  position: SourcePosition,
+}
+
+impl CssWarning {
+  pub fn new(message: String, position: SourcePosition) -> Self {
+    Self { message, position }
+  }
+
+  pub fn message(&self) -> String {
+    self.message.clone()
+  }
+
+  pub fn position(&self) -> SourcePosition {
+    self.position
+  }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct QualifiedRule {
-  pub selector: Selector,
+  pub selectors: Vec<ComplexSelector>,
   pub declarations: Vec<Declaration>,
 }
 
 impl QualifiedRule {
   pub fn new() -> Self {
     Self {
-      selector: Selector::TypeSelector("".to_string()),
+      selectors: Vec::new(),
       declarations: Vec::new(),
     }
   }
 
-  pub fn set_selector(&mut self, selector: Selector) {
-    self.selector = selector;
+  pub fn set_selectors(&mut self, selectors: Vec<ComplexSelector>) {
+    self.selectors = selectors;
   }
 
   pub fn set_declarations(&mut self, declarations: Vec<Declaration>) {
@@ -208,25 +360,19 @@ impl QualifiedRule {
   }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub enum Selector {
-  TypeSelector(String),
-  ClassSelector(String),
-  IdSelector(String),
-  UnknownSelector,
-}
-
 #[derive(Debug, Clone, PartialEq)]
 pub struct Declaration {
   pub property: String,
-  pub value: ComponentValue,
+  pub value: Vec<ComponentValue>,
+  pub important: bool,
 }
 
 impl Declaration {
   pub fn new() -> Self {
     Self {
       property: String::new(),
-      value: ComponentValue::Ident(String::new()),
+      value: Vec::new(),
+      important: false,
     }
   }
 
@@ -234,16 +380,31 @@ impl Declaration {
     self.property = property;
   }
 
-  pub fn set_value(&mut self, value: ComponentValue) {
+  pub fn set_value(&mut self, value: Vec<ComponentValue>) {
     self.value = value;
   }
+
+  pub fn set_important(&mut self, important: bool) {
+    self.important = important;
+  }
 }
 
-pub type ComponentValue = CssToken;
+// https://www.w3.org/TR/css-syntax-3/#component-value
+#[derive(Debug, Clone, PartialEq)]
+pub enum ComponentValue {
+  PreservedToken(CssToken),
+  /// A function name and its arguments, without the closing parenthesis.
+  Function(String, Vec<ComponentValue>),
+  /// The token that opened the block and the values inside it.
+  SimpleBlock(CssToken, Vec<ComponentValue>),
+}
 
 #[cfg(test)]
 mod tests {
   use super::*;
+  use crate::renderer::css::selector::parse_selector_list;
+  use crate::renderer::css::token::NumericType;
+  use alloc::vec;
 
   #[test]
   fn test_empty() {
@@ -262,10 +423,162 @@ mod tests {
     let sheet = parser.parse_stylesheet();
     assert_eq!(sheet.rules.len(), 1);
     let rule = &sheet.rules[0];
-    assert_eq!(rule.selector, Selector::TypeSelector("h1".to_string()));
+    assert_eq!(rule.selectors, parse_selector_list("h1").unwrap());
     assert_eq!(rule.declarations.len(), 1);
     let declaration = &rule.declarations[0];
     assert_eq!(declaration.property, "color");
-    assert_eq!(declaration.value, CssToken::Ident("red".to_string()));
+    assert_eq!(
+      declaration.value,
+      vec![ComponentValue::PreservedToken(CssToken::Ident("red".to_string()))],
+    );
+    assert!(!declaration.important);
+  }
+
+  fn parse_declarations(css: &str) -> Vec<Declaration> {
+    let mut parser = CssParser::new(CssTokenizer::new(css.to_string()));
+    let sheet = parser.parse_stylesheet();
+    assert_eq!(sheet.rules.len(), 1);
+    sheet.rules[0].declarations.clone()
+  }
+
+  fn token(token: CssToken) -> ComponentValue {
+    ComponentValue::PreservedToken(token)
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn test_multiple_values() {
+    let declarations = parse_declarations("p { margin: 0 auto ; border : 1px solid red }");
+    assert_eq!(declarations.len(), 2);
+    assert_eq!(declarations[0].property, "margin");
+    assert_eq!(
+      declarations[0].value,
+      vec![
+        token(CssToken::Number(0.0, NumericType::Integer, None)),
+        token(CssToken::Whitespace),
+        token(CssToken::Ident("auto".to_string())),
+      ],
+    );
+    assert_eq!(declarations[1].property, "border");
+    assert_eq!(declarations[1].value.len(), 5);
+  }
+
+  #[test]
+  fn test_functions_and_blocks() {
+    let declarations = parse_declarations("p { color: rgb(1, 2, 3); x: [a {b;}] (c; d: e }");
+    assert_eq!(
+      declarations[0].value,
+      vec![ComponentValue::Function(
+        "rgb".to_string(),
+        vec![
+          token(CssToken::Number(1.0, NumericType::Integer, None)),
+          token(CssToken::Comma),
+          token(CssToken::Whitespace),
+          token(CssToken::Number(2.0, NumericType::Integer, None)),
+          token(CssToken::Comma),
+          token(CssToken::Whitespace),
+          token(CssToken::Number(3.0, NumericType::Integer, None)),
+        ],
+      )],
+    );
+    // Semicolons and braces inside blocks do not end the declaration, and an unclosed block
+    // runs to the end of input.
+    assert_eq!(declarations.len(), 2);
+    assert_eq!(
+      declarations[1].value,
+      vec![
+        ComponentValue::SimpleBlock(
+          CssToken::OpenSquare,
+          vec![
+            token(CssToken::Ident("a".to_string())),
+            token(CssToken::Whitespace),
+            ComponentValue::SimpleBlock(
+              CssToken::OpenCurly,
+              vec![token(CssToken::Ident("b".to_string())), token(CssToken::SemiColon)],
+            ),
+          ],
+        ),
+        token(CssToken::Whitespace),
+        ComponentValue::SimpleBlock(
+          CssToken::OpenParenthesis,
+          vec![
+            token(CssToken::Ident("c".to_string())),
+            token(CssToken::SemiColon),
+            token(CssToken::Whitespace),
+            token(CssToken::Ident("d".to_string())),
+            token(CssToken::Colon),
+            token(CssToken::Whitespace),
+            token(CssToken::Ident("e".to_string())),
+            token(CssToken::Whitespace),
+            token(CssToken::CloseCurly),
+          ],
+        ),
+      ],
+    );
+  }
+
+  #[test]
+  fn test_important() {
+    let declarations =
+      parse_declarations("p { color: red !important; margin: 0 ! IMPORTANT ; x: important; y: !a }");
+    assert!(declarations[0].important);
+    assert_eq!(declarations[0].value, vec![token(CssToken::Ident("red".to_string()))]);
+    assert!(declarations[1].important);
+    assert_eq!(declarations[1].value, vec![token(CssToken::Number(0.0, NumericType::Integer, None))]);
+    assert!(!declarations[2].important);
+    assert!(!declarations[3].important);
+    assert_eq!(declarations[3].value.len(), 2);
+  }
+
+  fn parse(css: &str) -> StyleSheet {
+    CssParser::new(CssTokenizer::new(css.to_string())).parse_stylesheet()
+  }
+
+  fn warning_positions(sheet: &StyleSheet) -> Vec<(usize, usize)> {
+    sheet.warnings.iter().map(|w| (w.position().line(), w.position().column())).collect()
+  }
+
+  #[test]
+  fn test_invalid_declarations() {
+    let sheet = parse("p {\n  color red;\n  42: 1; margin: 0;\n  @foo bar; display: block }");
+    let declarations = &sheet.rules[0].declarations;
+    assert_eq!(declarations.len(), 2);
+    assert_eq!(declarations[0].property, "margin");
+    assert_eq!(declarations[1].property, "display");
+    assert_eq!(warning_positions(&sheet), vec![(2, 3), (3, 3), (4, 3)]);
+    assert_eq!(sheet.warnings[0].message(), "expected ':' after color, the declaration is ignored");
+  }
+
+  #[test]
+  fn test_invalid_rules() {
+    let sheet = parse(". { color: red; } @media print { p { color: red } }\n#1 {} h1 { x: y } .a { x: {;} }");
+    assert_eq!(sheet.rules.len(), 2);
+    assert_eq!(sheet.rules[0].selectors, parse_selector_list("h1").unwrap());
+    assert_eq!(sheet.rules[1].selectors, parse_selector_list(".a").unwrap());
+    assert_eq!(sheet.rules[1].declarations.len(), 1);
+    assert_eq!(warning_positions(&sheet), vec![(1, 1), (1, 19), (2, 1)]);
+  }
+
+  #[test]
+  fn test_unexpected_end_of_input() {
+    for css in ["p", "p { color", "p { color:", "p { color: rgb(1", ".", "@media"] {
+      parse(css);
+    }
+    let sheet = parse("p { color: red");
+    assert_eq!(sheet.rules[0].declarations.len(), 1);
+    assert_eq!(warning_positions(&sheet), vec![(1, 15)]);
+    let sheet = parse("h1 {} p");
+    assert_eq!(sheet.rules.len(), 1);
+    assert_eq!(warning_positions(&sheet), vec![(1, 7)]);
+  }
+
+  #[test]
+  fn test_list_of_declarations() {
+    let mut parser =
+      CssParser::new(CssTokenizer::new("color: red; } x; margin: 0 !important".to_string()));
+    let declarations = parser.parse_list_of_declarations();
+    assert_eq!(declarations.len(), 2);
+    assert_eq!(declarations[0].property, "color");
+    assert_eq!(declarations[1].property, "margin");
+    assert!(declarations[1].important);
+  }
+}
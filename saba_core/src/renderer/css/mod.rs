@@ -1,2 +1,4 @@
 pub mod token;
-pub mod cssom;
\ No newline at end of file
+pub mod cssom;
+pub mod selector;
+pub mod cascade;
\ No newline at end of file
@@ -0,0 +1,882 @@
+use core::cell::RefCell;
+use core::iter::Peekable;
+
+use alloc::{format, rc::Rc, string::{String, ToString}, vec, vec::Vec};
+
+use crate::error::Error;
+use crate::renderer::css::cssom::ComponentValue;
+use crate::renderer::css::token::{CssToken, CssTokenizer, HashType, NumericType};
+use crate::renderer::dom::node::{Namespace, Node, NodeKind};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SimpleSelector {
+  Universal,
+  Type(String),
+  Class(String),
+  Id(String),
+  Attribute(AttributeSelector),
+  PseudoClass(PseudoClass),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PseudoClass {
+  Root,
+  Empty,
+  FirstChild,
+  LastChild,
+  NthChild(AnPlusB),
+  Not(Vec<ComplexSelector>),
+}
+
+/// The `An+B` argument of `:nth-child()`, which matches the elements at index `An+B` for some
+/// non-negative `n`, counting from 1.
+// https://www.w3.org/TR/css-syntax-3/#anb-microsyntax
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnPlusB {
+  a: i32,
+  b: i32,
+}
+
+impl AnPlusB {
+  pub fn new(a: i32, b: i32) -> Self {
+    Self { a, b }
+  }
+
+  pub fn a(&self) -> i32 {
+    self.a
+  }
+
+  pub fn b(&self) -> i32 {
+    self.b
+  }
+
+  pub fn matches(&self, index: i32) -> bool {
+    if self.a == 0 {
+      return index == self.b;
+    }
+    // Values out of the i32 range are clamped when parsed, so this is done in i64 to not overflow.
+    let n = i64::from(index) - i64::from(self.b);
+    let a = i64::from(self.a);
+    n % a == 0 && n / a >= 0
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeOperator {
+  Exists,
+  Equals,
+  Includes,
+  DashMatch,
+  Prefix,
+  Suffix,
+  Substring,
+}
+
+/// How an attribute value is compared. By default, HTML decides per attribute; the `i` and `s`
+/// flags force ASCII case-insensitive and case-sensitive comparison.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeCase {
+  Default,
+  Insensitive,
+  Sensitive,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttributeSelector {
+  name: String,
+  operator: AttributeOperator,
+  value: String,
+  case: AttributeCase,
+}
+
+impl AttributeSelector {
+  pub fn new(name: String, operator: AttributeOperator, value: String) -> Self {
+    Self { name, operator, value, case: AttributeCase::Default }
+  }
+
+  pub fn name(&self) -> String {
+    self.name.clone()
+  }
+
+  pub fn operator(&self) -> AttributeOperator {
+    self.operator
+  }
+
+  pub fn value(&self) -> String {
+    self.value.clone()
+  }
+
+  pub fn case(&self) -> AttributeCase {
+    self.case
+  }
+
+  pub fn set_case(&mut self, case: AttributeCase) {
+    self.case = case;
+  }
+
+  fn matches(&self, value: &str, case_insensitive: bool) -> bool {
+    if case_insensitive {
+      let expected = self.value.to_ascii_lowercase();
+      return self.matches_value(&value.to_ascii_lowercase(), &expected);
+    }
+    self.matches_value(value, &self.value)
+  }
+
+  fn matches_value(&self, value: &str, expected: &str) -> bool {
+    match self.operator {
+      AttributeOperator::Exists => true,
+      AttributeOperator::Equals => value == expected,
+      AttributeOperator::Includes => {
+        !expected.is_empty() && value.split_ascii_whitespace().any(|v| v == expected)
+      }
+      AttributeOperator::DashMatch => {
+        value == expected || value.strip_prefix(expected).is_some_and(|rest| rest.starts_with('-'))
+      }
+      AttributeOperator::Prefix => !expected.is_empty() && value.starts_with(expected),
+      AttributeOperator::Suffix => !expected.is_empty() && value.ends_with(expected),
+      AttributeOperator::Substring => !expected.is_empty() && value.contains(expected),
+    }
+  }
+}
+
+/// The numbers of ID selectors, of class-like selectors and of type selectors in a selector,
+/// compared in that order.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Specificity {
+  ids: u32,
+  classes: u32,
+  types: u32,
+}
+
+impl Specificity {
+  pub fn new(ids: u32, classes: u32, types: u32) -> Self {
+    Self { ids, classes, types }
+  }
+
+  pub fn ids(&self) -> u32 {
+    self.ids
+  }
+
+  pub fn classes(&self) -> u32 {
+    self.classes
+  }
+
+  pub fn types(&self) -> u32 {
+    self.types
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Combinator {
+  Descendant,
+  Child,
+  NextSibling,
+  SubsequentSibling,
+}
+
+/// A sequence of compound selectors joined by combinators. `combinators[i]` sits between
+/// `compounds[i]` and `compounds[i + 1]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComplexSelector {
+  compounds: Vec<Vec<SimpleSelector>>,
+  combinators: Vec<Combinator>,
+}
+
+impl ComplexSelector {
+  pub fn compounds(&self) -> Vec<Vec<SimpleSelector>> {
+    self.compounds.clone()
+  }
+
+  pub fn combinators(&self) -> Vec<Combinator> {
+    self.combinators.clone()
+  }
+
+  pub fn matches(&self, node: &Rc<RefCell<Node>>) -> bool {
+    self.matches_from(self.compounds.len() - 1, node)
+  }
+
+  // https://www.w3.org/TR/selectors-4/#specificity-rules
+  pub fn specificity(&self) -> Specificity {
+    let mut specificity = Specificity::default();
+    for simple in self.compounds.iter().flatten() {
+      match simple {
+        SimpleSelector::Universal => {}
+        SimpleSelector::Type(_) => specificity.types += 1,
+        SimpleSelector::Id(_) => specificity.ids += 1,
+        // `:not()` counts as its most specific argument.
+        SimpleSelector::PseudoClass(PseudoClass::Not(selectors)) => {
+          let argument = selectors.iter().map(|s| s.specificity()).max().unwrap_or_default();
+          specificity.ids += argument.ids;
+          specificity.classes += argument.classes;
+          specificity.types += argument.types;
+        }
+        SimpleSelector::Class(_) | SimpleSelector::Attribute(_) | SimpleSelector::PseudoClass(_) => {
+          specificity.classes += 1
+        }
+      }
+    }
+    specificity
+  }
+
+  // Matches right to left: the rightmost compound against `node`, then each combinator walks to
+  // the candidates for the compound on its left.
+  fn matches_from(&self, index: usize, node: &Rc<RefCell<Node>>) -> bool {
+    if !self.compounds[index].iter().all(|simple| matches_simple_selector(simple, node)) {
+      return false;
+    }
+    if index == 0 {
+      return true;
+    }
+
+    match self.combinators[index - 1] {
+      Combinator::Descendant => {
+        let mut ancestor = parent_element(node);
+        while let Some(a) = ancestor {
+          if self.matches_from(index - 1, &a) {
+            return true;
+          }
+          ancestor = parent_element(&a);
+        }
+        false
+      }
+      Combinator::Child => match parent_element(node) {
+        Some(parent) => self.matches_from(index - 1, &parent),
+        None => false,
+      },
+      Combinator::NextSibling => match previous_element_sibling(node) {
+        Some(sibling) => self.matches_from(index - 1, &sibling),
+        None => false,
+      },
+      Combinator::SubsequentSibling => {
+        let mut sibling = previous_element_sibling(node);
+        while let Some(s) = sibling {
+          if self.matches_from(index - 1, &s) {
+            return true;
+          }
+          sibling = previous_element_sibling(&s);
+        }
+        false
+      }
+    }
+  }
+}
+
+pub fn matches_selector_list(selectors: &[ComplexSelector], node: &Rc<RefCell<Node>>) -> bool {
+  selectors.iter().any(|selector| selector.matches(node))
+}
+
+pub fn matches_simple_selector(selector: &SimpleSelector, node: &Rc<RefCell<Node>>) -> bool {
+  let node_ref = node.borrow();
+  let element = match node_ref.kind {
+    NodeKind::Element(ref element) => element,
+    _ => return false,
+  };
+
+  match selector {
+    SimpleSelector::Universal => true,
+    SimpleSelector::Type(name) => match element.namespace() {
+      Namespace::Html => element.tag_name().eq_ignore_ascii_case(name),
+      _ => element.tag_name() == *name,
+    },
+    SimpleSelector::Class(name) => element.has_class(name),
+    SimpleSelector::Id(id) => element.attribute_map().value("id") == Some(id.as_str()),
+    SimpleSelector::Attribute(attribute) => {
+      let html = element.namespace() == Namespace::Html;
+      let name = if html { attribute.name.to_ascii_lowercase() } else { attribute.name.clone() };
+      let case_insensitive = match attribute.case {
+        AttributeCase::Default => html && CASE_INSENSITIVE_ATTRIBUTES.contains(&name.as_str()),
+        AttributeCase::Insensitive => true,
+        AttributeCase::Sensitive => false,
+      };
+      element.attribute_map().value(&name).is_some_and(|value| attribute.matches(value, case_insensitive))
+    }
+    SimpleSelector::PseudoClass(pseudo_class) => matches_pseudo_class(pseudo_class, node),
+  }
+}
+
+// Attributes of HTML elements whose values are compared ASCII case-insensitively.
+// https://html.spec.whatwg.org/multipage/semantics-other.html#case-sensitivity-of-selectors
+const CASE_INSENSITIVE_ATTRIBUTES: &[&str] = &[
+  "accept", "accept-charset", "align", "alink", "axis", "bgcolor", "charset", "checked", "clear",
+  "codetype", "color", "compact", "declare", "defer", "dir", "direction", "disabled", "enctype",
+  "face", "frame", "hreflang", "http-equiv", "lang", "language", "link", "media", "method",
+  "multiple", "nohref", "noresize", "noshade", "nowrap", "readonly", "rel", "rev", "rules", "scope",
+  "scrolling", "selected", "shape", "target", "text", "type", "valign", "valuetype", "vlink",
+];
+
+fn matches_pseudo_class(pseudo_class: &PseudoClass, node: &Rc<RefCell<Node>>) -> bool {
+  match pseudo_class {
+    PseudoClass::Root => node
+      .borrow()
+      .parent()
+      .upgrade()
+      .is_some_and(|parent| matches!(parent.borrow().kind, NodeKind::Document)),
+    // Comments do not count, but any text does, even whitespace.
+    PseudoClass::Empty => {
+      let mut child = node.borrow().first_child();
+      while let Some(c) = child {
+        match c.borrow().kind {
+          NodeKind::Element(_) => return false,
+          NodeKind::Text(ref text) if !text.is_empty() => return false,
+          _ => {}
+        }
+        let next = c.borrow().next_sibling();
+        child = next;
+      }
+      true
+    }
+    PseudoClass::FirstChild => previous_element_sibling(node).is_none(),
+    PseudoClass::LastChild => next_element_sibling(node).is_none(),
+    PseudoClass::NthChild(an_plus_b) => {
+      let mut index = 1;
+      let mut sibling = previous_element_sibling(node);
+      while let Some(s) = sibling {
+        index += 1;
+        sibling = previous_element_sibling(&s);
+      }
+      an_plus_b.matches(index)
+    }
+    PseudoClass::Not(selectors) => !matches_selector_list(selectors, node),
+  }
+}
+
+fn parent_element(node: &Rc<RefCell<Node>>) -> Option<Rc<RefCell<Node>>> {
+  let parent = node.borrow().parent().upgrade()?;
+  if matches!(parent.borrow().kind, NodeKind::Element(_)) {
+    return Some(parent);
+  }
+  None
+}
+
+fn previous_element_sibling(node: &Rc<RefCell<Node>>) -> Option<Rc<RefCell<Node>>> {
+  let mut sibling = node.borrow().previous_sibling().upgrade();
+  while let Some(s) = sibling {
+    if matches!(s.borrow().kind, NodeKind::Element(_)) {
+      return Some(s);
+    }
+    sibling = s.borrow().previous_sibling().upgrade();
+  }
+  None
+}
+
+fn next_element_sibling(node: &Rc<RefCell<Node>>) -> Option<Rc<RefCell<Node>>> {
+  let mut sibling = node.borrow().next_sibling();
+  while let Some(s) = sibling {
+    if matches!(s.borrow().kind, NodeKind::Element(_)) {
+      return Some(s);
+    }
+    sibling = s.borrow().next_sibling();
+  }
+  None
+}
+
+/// Parses a comma-separated selector list such as `ul > li.active a[href^=http], h1`.
+pub fn parse_selector_list(s: &str) -> Result<Vec<ComplexSelector>, Error> {
+  SelectorParser { input: CssTokenizer::new(s.to_string()).collect::<Vec<_>>().into_iter().peekable() }
+    .parse_selector_list()
+}
+
+/// Parses the prelude of a style rule as a selector list.
+pub fn parse_selector_list_from_values(values: &[ComponentValue]) -> Result<Vec<ComplexSelector>, Error> {
+  let mut tokens = Vec::new();
+  flatten(values, &mut tokens);
+  SelectorParser { input: tokens.into_iter().peekable() }.parse_selector_list()
+}
+
+// Turns component values back into tokens, closing every block and function.
+fn flatten(values: &[ComponentValue], tokens: &mut Vec<CssToken>) {
+  for value in values {
+    match value {
+      ComponentValue::PreservedToken(token) => tokens.push(token.clone()),
+      ComponentValue::Function(name, values) => {
+        tokens.push(CssToken::Function(name.clone()));
+        flatten(values, tokens);
+        tokens.push(CssToken::CloseParenthesis);
+      }
+      ComponentValue::SimpleBlock(open, values) => {
+        tokens.push(open.clone());
+        flatten(values, tokens);
+        tokens.push(match open {
+          CssToken::OpenSquare => CssToken::CloseSquare,
+          CssToken::OpenParenthesis => CssToken::CloseParenthesis,
+          _ => CssToken::CloseCurly,
+        });
+      }
+    }
+  }
+}
+
+// https://www.w3.org/TR/selectors-3/#w3cselgrammar
+struct SelectorParser {
+  input: Peekable<vec::IntoIter<CssToken>>,
+}
+
+impl SelectorParser {
+  fn parse_selector_list(&mut self) -> Result<Vec<ComplexSelector>, Error> {
+    let mut selectors = Vec::new();
+    loop {
+      selectors.push(self.parse_complex_selector()?);
+      match self.input.next() {
+        Some(CssToken::Comma) => continue,
+        None => return Ok(selectors),
+        Some(token) => return Err(unexpected(&token)),
+      }
+    }
+  }
+
+  fn parse_complex_selector(&mut self) -> Result<ComplexSelector, Error> {
+    self.skip_whitespace();
+    let mut selector = ComplexSelector {
+      compounds: alloc::vec![self.parse_compound_selector()?],
+      combinators: Vec::new(),
+    };
+
+    loop {
+      let had_whitespace = self.skip_whitespace();
+      let combinator = match self.input.peek() {
+        None | Some(CssToken::Comma) => return Ok(selector),
+        Some(CssToken::Delim('>')) => Combinator::Child,
+        Some(CssToken::Delim('+')) => Combinator::NextSibling,
+        Some(CssToken::Delim('~')) => Combinator::SubsequentSibling,
+        Some(_) if had_whitespace => Combinator::Descendant,
+        Some(token) => return Err(unexpected(token)),
+      };
+      if combinator != Combinator::Descendant {
+        self.input.next();
+        self.skip_whitespace();
+      }
+      selector.combinators.push(combinator);
+      selector.compounds.push(self.parse_compound_selector()?);
+    }
+  }
+
+  fn parse_compound_selector(&mut self) -> Result<Vec<SimpleSelector>, Error> {
+    let mut compound = Vec::new();
+    match self.input.peek() {
+      Some(CssToken::Delim('*')) => {
+        self.input.next();
+        compound.push(SimpleSelector::Universal);
+      }
+      Some(CssToken::Ident(name)) => {
+        compound.push(SimpleSelector::Type(name.clone()));
+        self.input.next();
+      }
+      _ => {}
+    }
+
+    loop {
+      let simple = match self.input.peek() {
+        Some(CssToken::HashToken(id, HashType::Id)) => SimpleSelector::Id(id.clone()),
+        Some(CssToken::Delim('.')) => {
+          self.input.next();
+          SimpleSelector::Class(self.parse_ident()?)
+        }
+        Some(CssToken::OpenSquare) => {
+          self.input.next();
+          SimpleSelector::Attribute(self.parse_attribute_selector()?)
+        }
+        Some(CssToken::Colon) => {
+          self.input.next();
+          SimpleSelector::PseudoClass(self.parse_pseudo_class()?)
+        }
+        _ => break,
+      };
+      if matches!(simple, SimpleSelector::Id(_)) {
+        self.input.next();
+      }
+      compound.push(simple);
+    }
+
+    if compound.is_empty() {
+      return Err(match self.input.peek() {
+        Some(token) => unexpected(token),
+        None => Error::UnexpectedInput("selector expected".to_string()),
+      });
+    }
+    Ok(compound)
+  }
+
+  fn parse_attribute_selector(&mut self) -> Result<AttributeSelector, Error> {
+    self.skip_whitespace();
+    let name = self.parse_ident()?;
+    self.skip_whitespace();
+
+    let operator = match self.input.next() {
+      Some(CssToken::CloseSquare) => {
+        return Ok(AttributeSelector::new(name, AttributeOperator::Exists, String::new()))
+      }
+      Some(CssToken::Delim('=')) => AttributeOperator::Equals,
+      Some(token) => {
+        let operator = match token {
+          CssToken::Delim('~') => AttributeOperator::Includes,
+          CssToken::Delim('|') => AttributeOperator::DashMatch,
+          CssToken::Delim('^') => AttributeOperator::Prefix,
+          CssToken::Delim('$') => AttributeOperator::Suffix,
+          CssToken::Delim('*') => AttributeOperator::Substring,
+          _ => return Err(unexpected(&token)),
+        };
+        match self.input.next() {
+          Some(CssToken::Delim('=')) => operator,
+          Some(token) => return Err(unexpected(&token)),
+          None => return Err(Error::UnexpectedInput("'=' expected".to_string())),
+        }
+      }
+      None => return Err(Error::UnexpectedInput("']' expected".to_string())),
+    };
+
+    self.skip_whitespace();
+    let value = match self.input.next() {
+      Some(CssToken::Ident(value) | CssToken::StringToken(value)) => value,
+      Some(token) => return Err(unexpected(&token)),
+      None => return Err(Error::UnexpectedInput("attribute value expected".to_string())),
+    };
+    let mut selector = AttributeSelector::new(name, operator, value);
+    self.skip_whitespace();
+    if let Some(CssToken::Ident(flag)) = self.input.peek() {
+      match flag.to_ascii_lowercase().as_str() {
+        "i" => selector.set_case(AttributeCase::Insensitive),
+        "s" => selector.set_case(AttributeCase::Sensitive),
+        _ => return Err(Error::UnexpectedInput(format!("unknown attribute flag: {}", flag))),
+      }
+      self.input.next();
+      self.skip_whitespace();
+    }
+    match self.input.next() {
+      Some(CssToken::CloseSquare) => Ok(selector),
+      Some(token) => Err(unexpected(&token)),
+      None => Err(Error::UnexpectedInput("']' expected".to_string())),
+    }
+  }
+
+  fn parse_pseudo_class(&mut self) -> Result<PseudoClass, Error> {
+    match self.input.next() {
+      Some(CssToken::Ident(name)) => match name.to_ascii_lowercase().as_str() {
+        "root" => Ok(PseudoClass::Root),
+        "empty" => Ok(PseudoClass::Empty),
+        "first-child" => Ok(PseudoClass::FirstChild),
+        "last-child" => Ok(PseudoClass::LastChild),
+        _ => Err(Error::UnexpectedInput(format!("unsupported pseudo-class: {}", name))),
+      },
+      Some(CssToken::Function(name)) => {
+        let arguments = self.parse_arguments()?;
+        match name.to_ascii_lowercase().as_str() {
+          "nth-child" => Ok(PseudoClass::NthChild(parse_an_plus_b(arguments)?)),
+          "not" => {
+            let mut parser = SelectorParser { input: arguments.into_iter().peekable() };
+            Ok(PseudoClass::Not(parser.parse_selector_list()?))
+          }
+          _ => Err(Error::UnexpectedInput(format!("unsupported pseudo-class: {}()", name))),
+        }
+      }
+      Some(token) => Err(unexpected(&token)),
+      None => Err(Error::UnexpectedInput("pseudo-class expected".to_string())),
+    }
+  }
+
+  // Consumes the arguments of a function up to and including its closing parenthesis.
+  fn parse_arguments(&mut self) -> Result<Vec<CssToken>, Error> {
+    let mut arguments = Vec::new();
+    let mut depth = 0;
+    loop {
+      match self.input.next() {
+        None => return Err(Error::UnexpectedInput("')' expected".to_string())),
+        Some(CssToken::CloseParenthesis) if depth == 0 => return Ok(arguments),
+        Some(token) => {
+          match token {
+            CssToken::Function(_) | CssToken::OpenParenthesis => depth += 1,
+            CssToken::CloseParenthesis => depth -= 1,
+            _ => {}
+          }
+          arguments.push(token);
+        }
+      }
+    }
+  }
+
+  fn parse_ident(&mut self) -> Result<String, Error> {
+    match self.input.next() {
+      Some(CssToken::Ident(ident)) => Ok(ident),
+      Some(token) => Err(unexpected(&token)),
+      None => Err(Error::UnexpectedInput("identifier expected".to_string())),
+    }
+  }
+
+  fn skip_whitespace(&mut self) -> bool {
+    let mut skipped = false;
+    while self.input.next_if_eq(&CssToken::Whitespace).is_some() {
+      skipped = true;
+    }
+    skipped
+  }
+}
+
+// https://www.w3.org/TR/css-syntax-3/#anb-microsyntax
+fn parse_an_plus_b(tokens: Vec<CssToken>) -> Result<AnPlusB, Error> {
+  let invalid = || Error::UnexpectedInput("invalid An+B expression".to_string());
+  let mut input = tokens.into_iter().peekable();
+  while input.next_if_eq(&CssToken::Whitespace).is_some() {}
+  let end = |mut input: Peekable<vec::IntoIter<CssToken>>, an_plus_b| {
+    while input.next_if_eq(&CssToken::Whitespace).is_some() {}
+    match input.next() {
+      Some(_) => Err(invalid()),
+      None => Ok(an_plus_b),
+    }
+  };
+
+  // `A` and what follows the `n`, such as "-1" in `2n-1` or "-" in `-n- 1`.
+  let (a, rest) = match input.next() {
+    Some(CssToken::Number(b, NumericType::Integer, _)) => return end(input, AnPlusB::new(0, b as i32)),
+    Some(CssToken::Dimension(a, NumericType::Integer, unit)) => (a as i32, unit.to_ascii_lowercase()),
+    Some(CssToken::Ident(ident)) => match ident.to_ascii_lowercase().as_str() {
+      "odd" => return end(input, AnPlusB::new(2, 1)),
+      "even" => return end(input, AnPlusB::new(2, 0)),
+      ident => match ident.strip_prefix('-') {
+        Some(rest) => (-1, rest.to_string()),
+        None => (1, ident.to_string()),
+      },
+    },
+    // `+n` has no whitespace between the sign and the `n`.
+    Some(CssToken::Delim('+')) => match input.next() {
+      Some(CssToken::Ident(ident)) => (1, ident.to_ascii_lowercase()),
+      _ => return Err(invalid()),
+    },
+    _ => return Err(invalid()),
+  };
+
+  let b = match rest.strip_prefix('n').ok_or_else(invalid)? {
+    "" => {
+      while input.next_if_eq(&CssToken::Whitespace).is_some() {}
+      match input.next() {
+        None => 0,
+        // The sign is either part of the number or a separate token, but it has to be there.
+        Some(CssToken::Number(b, NumericType::Integer, Some(_))) => b as i32,
+        Some(CssToken::Delim(sign @ ('+' | '-'))) => {
+          while input.next_if_eq(&CssToken::Whitespace).is_some() {}
+          match input.next() {
+            Some(CssToken::Number(b, NumericType::Integer, None)) => match sign {
+              '-' => -(b as i32),
+              _ => b as i32,
+            },
+            _ => return Err(invalid()),
+          }
+        }
+        _ => return Err(invalid()),
+      }
+    }
+    "-" => {
+      while input.next_if_eq(&CssToken::Whitespace).is_some() {}
+      match input.next() {
+        Some(CssToken::Number(b, NumericType::Integer, None)) => -(b as i32),
+        _ => return Err(invalid()),
+      }
+    }
+    rest => match rest.strip_prefix('-') {
+      Some(digits) if !digits.is_empty() && digits.bytes().all(|c| c.is_ascii_digit()) => {
+        -digits.parse::<i32>().map_err(|_| invalid())?
+      }
+      _ => return Err(invalid()),
+    },
+  };
+
+  end(input, AnPlusB::new(a, b))
+}
+
+fn unexpected(token: &CssToken) -> Error {
+  Error::UnexpectedInput(format!("unexpected token in selector: {:?}", token))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::renderer::dom::query::{descendants, get_element_by_id};
+  use crate::renderer::html::parser::HtmlParser;
+  use crate::renderer::html::token::HtmlTokenizer;
+
+  fn document(html: &str) -> Rc<RefCell<Node>> {
+    let t = HtmlTokenizer::new(html.to_string());
+    let window = HtmlParser::new(t).construct_tree();
+    let document = window.borrow().document();
+    document
+  }
+
+  fn matching_ids(html: &str, selectors: &str) -> Vec<String> {
+    let document = document(html);
+    let selectors = parse_selector_list(selectors).expect("selector should parse");
+    descendants(&document)
+      .filter(|node| matches_selector_list(&selectors, node))
+      .filter_map(|node| node.borrow().get_element().and_then(|e| e.id()))
+      .collect()
+  }
+
+  #[test]
+  fn test_parse() {
+    let selectors = parse_selector_list("ul > li.active a[href^=http], *").unwrap();
+    assert_eq!(2, selectors.len());
+    assert_eq!(
+      alloc::vec![Combinator::Child, Combinator::Descendant],
+      selectors[0].combinators(),
+    );
+    assert_eq!(
+      alloc::vec![
+        alloc::vec![SimpleSelector::Type("ul".to_string())],
+        alloc::vec![SimpleSelector::Type("li".to_string()), SimpleSelector::Class("active".to_string())],
+        alloc::vec![
+          SimpleSelector::Type("a".to_string()),
+          SimpleSelector::Attribute(AttributeSelector::new(
+            "href".to_string(),
+            AttributeOperator::Prefix,
+            "http".to_string(),
+          )),
+        ],
+      ],
+      selectors[0].compounds(),
+    );
+    assert_eq!(alloc::vec![alloc::vec![SimpleSelector::Universal]], selectors[1].compounds());
+  }
+
+  #[test]
+  fn test_parse_errors() {
+    for s in ["", "a,", "> a", "a >", "a:hover", "[href", "[href^x]", "a..b", "#", ".1x", "[a='b]"] {
+      assert!(parse_selector_list(s).is_err(), "{:?} should not parse", s);
+    }
+  }
+
+  #[test]
+  fn test_combinators() {
+    let html = "<div id=d><p id=p1><span id=s1></span></p><p id=p2></p><span id=s2></span></div>";
+    assert_eq!(alloc::vec!["s1", "s2"], matching_ids(html, "div span"));
+    assert_eq!(alloc::vec!["s2"], matching_ids(html, "div > span"));
+    assert_eq!(alloc::vec!["p2"], matching_ids(html, "p + p"));
+    assert_eq!(alloc::vec!["p2", "s2"], matching_ids(html, "#p1 ~ *"));
+    assert_eq!(alloc::vec!["s1"], matching_ids(html, "div > p span"));
+    assert_eq!(alloc::vec!["p1", "s2"], matching_ids(html, "#p1,#s2"));
+  }
+
+  #[test]
+  fn test_compound_and_class() {
+    let html = "<p id=a class='x y'></p><p id=b class=x></p><div id=c class='y x'></div>";
+    assert_eq!(alloc::vec!["a", "c"], matching_ids(html, ".x.y"));
+    assert_eq!(alloc::vec!["a", "b"], matching_ids(html, "P.x"));
+    assert_eq!(alloc::vec!["c"], matching_ids(html, "div#c.x"));
+  }
+
+  #[test]
+  fn test_attribute_operators() {
+    let html = "<a id=a href=http://x lang=en-US rel='nofollow noopener'></a><a id=b href=/x lang=en></a><a id=c>";
+    assert_eq!(alloc::vec!["a", "b"], matching_ids(html, "[href]"));
+    assert_eq!(alloc::vec!["b"], matching_ids(html, "[href='/x']"));
+    assert_eq!(alloc::vec!["a"], matching_ids(html, "[HREF^=http]"));
+    assert_eq!(alloc::vec!["a", "b"], matching_ids(html, "[href$=x]"));
+    assert_eq!(alloc::vec!["a"], matching_ids(html, "[href*=\"//\"]"));
+    assert_eq!(alloc::vec!["a"], matching_ids(html, "[rel~=noopener]"));
+    assert_eq!(alloc::vec!["a", "b"], matching_ids(html, "[lang|=en]"));
+    assert!(matching_ids(html, "[href^='']").is_empty());
+  }
+
+  #[test]
+  fn test_escapes_and_comments() {
+    let html = "<p id=a class='x:y'><b id=b></b></p>";
+    assert_eq!(alloc::vec!["b"], matching_ids(html, "#a/* comment */>b"));
+    assert_eq!(alloc::vec!["a"], matching_ids(html, ".x\\:y"));
+  }
+
+  #[test]
+  fn test_parse_from_values() {
+    use crate::renderer::css::cssom::CssParser;
+
+    let values = CssParser::new(CssTokenizer::new("p[title='a b'] ~ *".to_string()))
+      .parse_list_of_component_values();
+    let selectors = parse_selector_list_from_values(&values).unwrap();
+    assert_eq!(parse_selector_list("p[title='a b'] ~ *").unwrap(), selectors);
+
+    let document = document("<p id=a title='a b'></p><i id=b></i>");
+    let b = get_element_by_id(&document, "b").unwrap();
+    assert!(matches_selector_list(&selectors, &b));
+  }
+
+  #[test]
+  fn test_an_plus_b() {
+    let parse = |s: &str| match parse_selector_list(&format!(":nth-child({})", s)) {
+      Ok(selectors) => match &selectors[0].compounds()[0][0] {
+        SimpleSelector::PseudoClass(PseudoClass::NthChild(n)) => Some((n.a(), n.b())),
+        _ => None,
+      },
+      Err(_) => None,
+    };
+    assert_eq!(Some((2, 1)), parse("odd"));
+    assert_eq!(Some((2, 0)), parse(" EVEN "));
+    assert_eq!(Some((0, 5)), parse("5"));
+    assert_eq!(Some((0, -3)), parse("-3"));
+    assert_eq!(Some((1, 0)), parse("n"));
+    assert_eq!(Some((1, 0)), parse("+n"));
+    assert_eq!(Some((-1, 6)), parse("-n+6"));
+    assert_eq!(Some((2, 1)), parse("2n+1"));
+    assert_eq!(Some((2, -1)), parse("2n-1"));
+    assert_eq!(Some((3, -2)), parse("3n - 2"));
+    assert_eq!(Some((-1, -2)), parse("-n- 2"));
+    assert_eq!(Some((10, -9)), parse("10N-9"));
+    assert_eq!(Some((2, 1)), parse("2n +1"));
+    for s in ["", "n+", "2n + -1", "2n - +1", "2n 1", "n- -1", "+ n", "1.5n", "2x", "odd 1", "3n-a"] {
+      assert_eq!(None, parse(s), "{:?} should not parse", s);
+    }
+
+    assert!(AnPlusB::new(2, 1).matches(1));
+    assert!(!AnPlusB::new(2, 1).matches(2));
+    assert!(AnPlusB::new(-1, 3).matches(3));
+    assert!(!AnPlusB::new(-1, 3).matches(4));
+    assert!(AnPlusB::new(0, 2).matches(2));
+
+    assert_eq!(Some((0, i32::MIN)), parse("-99999999999"));
+    assert!(!AnPlusB::new(0, i32::MIN).matches(1));
+    assert!(!AnPlusB::new(-1, i32::MIN).matches(i32::MAX));
+    assert!(AnPlusB::new(i32::MIN, i32::MAX).matches(i32::MAX));
+    assert!(!AnPlusB::new(i32::MIN, i32::MIN).matches(1));
+  }
+
+  #[test]
+  fn test_structural_pseudo_classes() {
+    let html = "<ul id=u><li id=a></li><!--c--><li id=b>x</li><li id=c><!--c--></li><li id=d> </li></ul>";
+    assert_eq!(alloc::vec!["a"], matching_ids(html, "li:first-child"));
+    assert_eq!(alloc::vec!["d"], matching_ids(html, "li:LAST-CHILD"));
+    assert_eq!(alloc::vec!["a", "c"], matching_ids(html, "li:nth-child(odd)"));
+    assert_eq!(alloc::vec!["b", "c", "d"], matching_ids(html, "li:nth-child(n+2)"));
+    assert_eq!(alloc::vec!["a", "c"], matching_ids(html, "li:empty"));
+    assert_eq!(alloc::vec!["b", "c", "d"], matching_ids(html, "li:not(:first-child)"));
+    assert_eq!(alloc::vec!["b"], matching_ids(html, "li:not(#a, :empty, :last-child)"));
+    assert_eq!(alloc::vec!["u"], matching_ids(html, ":root ul"));
+
+    let document = document(html);
+    let selectors = parse_selector_list(":root").unwrap();
+    let root: Vec<_> = descendants(&document).filter(|n| matches_selector_list(&selectors, n)).collect();
+    assert_eq!(1, root.len());
+    assert_eq!(Some("html".to_string()), root[0].borrow().get_element().map(|e| e.tag_name()));
+
+    for s in [":hover", "::before", ":nth-child(", ":not()", ":first-child()"] {
+      assert!(parse_selector_list(s).is_err(), "{:?} should not parse", s);
+    }
+  }
+
+  #[test]
+  fn test_attribute_case() {
+    let html = "<input id=a type=TEXT lang=EN-us title=Hello><input id=b type=text title=hello>";
+    assert_eq!(alloc::vec!["a", "b"], matching_ids(html, "input[type=\"text\"]"));
+    assert_eq!(alloc::vec!["a", "b"], matching_ids(html, "[lang|=en], [type=text]"));
+    assert_eq!(alloc::vec!["b"], matching_ids(html, "[title=hello]"));
+    assert_eq!(alloc::vec!["a", "b"], matching_ids(html, "[title=hello i]"));
+    assert_eq!(alloc::vec!["b"], matching_ids(html, "[type='text' S]"));
+    assert_eq!(alloc::vec!["a", "b"], matching_ids(html, "INPUT[TYPE]"));
+    assert!(parse_selector_list("[title=hello x]").is_err());
+  }
+
+  #[test]
+  fn test_specificity() {
+    let specificity = |s: &str| parse_selector_list(s).unwrap()[0].specificity();
+    assert_eq!(Specificity::new(0, 0, 0), specificity("*"));
+    assert_eq!(Specificity::new(0, 0, 2), specificity("ul > li"));
+    assert_eq!(Specificity::new(0, 2, 1), specificity("a[href].x"));
+    assert_eq!(Specificity::new(1, 1, 1), specificity("#a li:first-child"));
+    assert_eq!(Specificity::new(1, 0, 1), specificity("p:not(.x, #y)"));
+    assert!(specificity("#a") > specificity(".a.b.c.d.e.f.g.h.i.j.k"));
+    assert!(specificity(".a") > specificity("html body div p"));
+  }
+}
@@ -1,184 +1,552 @@
 use alloc::{string::String, vec::Vec};
 
+// https://www.w3.org/TR/css-syntax-3/#tokenization
 #[derive(Debug, Clone, PartialEq)]
 pub enum CssToken {
-  HashToken(String),
+  /// The value of a hash token, without the `#`.
+  HashToken(String, HashType),
   Delim(char),
-  Number(f64),
+  /// A number with the sign character it was written with, if any. An+B tells `2n+1` from the
+  /// invalid `2n 1` by it.
+  Number(f64, NumericType, Option<char>),
+  Percentage(f64),
+  Dimension(f64, NumericType, String),
   Colon,
   SemiColon,
+  Comma,
   OpenParenthesis,
   CloseParenthesis,
+  OpenSquare,
+  CloseSquare,
   OpenCurly,
   CloseCurly,
   Ident(String),
+  /// A function name, including the opening parenthesis that follows it.
+  Function(String),
   StringToken(String),
+  BadString,
+  Url(String),
+  BadUrl,
   AtKeyword(String),
+  UnicodeRange(u32, u32),
+  Whitespace,
+  Cdo,
+  Cdc,
+}
+
+/// Whether a hash token could be an ID selector, that is whether its value is an identifier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashType {
+  Id,
+  Unrestricted,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumericType {
+  Integer,
+  Number,
+}
+
+/// A line and a column in a stylesheet, both counted from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourcePosition {
+  line: usize,
+  column: usize,
+}
+
+impl SourcePosition {
+  pub fn new(line: usize, column: usize) -> Self {
+    Self { line, column }
+  }
+
+  pub fn line(&self) -> usize {
+    self.line
+  }
+
+  pub fn column(&self) -> usize {
+    self.column
+  }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct CssTokenizer {
   pos: usize,
-  input: Vec<char>
+  input: Vec<char>,
+  token_start: usize,
 }
 
 impl CssTokenizer {
   pub fn new(css: String) -> Self {
     Self {
       pos: 0,
-      input: css.chars().collect()
+      input: preprocess(&css),
+      token_start: 0,
     }
   }
 
-  fn consume_string_token(&mut self) -> String {
-    let mut s = String::new();
+  /// The offset of the token last returned by `next`, or of the end of input once it returned
+  /// `None`.
+  pub fn token_start(&self) -> usize {
+    self.token_start
+  }
 
-    loop {
-      if self.pos >= self.input.len() {
-        return s;
+  /// Converts an offset into the input to a line and a column.
+  pub fn position(&self, offset: usize) -> SourcePosition {
+    let mut position = SourcePosition::new(1, 1);
+    for c in self.input.iter().take(offset) {
+      if *c == '\n' {
+        position.line += 1;
+        position.column = 1;
+      } else {
+        position.column += 1;
       }
+    }
+    position
+  }
 
+  fn peek(&self, offset: usize) -> Option<char> {
+    self.input.get(self.pos + offset).copied()
+  }
+
+  fn consume(&mut self) -> Option<char> {
+    let c = self.peek(0);
+    if c.is_some() {
       self.pos += 1;
-      let c = self.input[self.pos];
-      match c {
-        '"' | '\'' => break,
-        _ => s.push(c),
+    }
+    c
+  }
+
+  fn consume_comments(&mut self) {
+    while self.peek(0) == Some('/') && self.peek(1) == Some('*') {
+      self.pos += 2;
+      loop {
+        match self.consume() {
+          Some('*') if self.peek(0) == Some('/') => {
+            self.pos += 1;
+            break;
+          }
+          Some(_) => {}
+          None => return,
+        }
       }
     }
+  }
 
-    s
+  fn consume_whitespace(&mut self) {
+    while self.peek(0).is_some_and(is_whitespace) {
+      self.pos += 1;
+    }
   }
 
-  fn consume_numeric_token(&mut self) -> f64 {
-    let mut num = 0f64;
-    let mut floating = false;
-    let mut floating_digit = 1f64;
+  // https://www.w3.org/TR/css-syntax-3/#consume-string-token
+  fn consume_string_token(&mut self, ending: char) -> CssToken {
+    let mut s = String::new();
 
     loop {
-      if self.pos >= self.input.len() {
-        return num;
+      match self.consume() {
+        Some(c) if c == ending => return CssToken::StringToken(s),
+        None => return CssToken::StringToken(s),
+        Some('\n') => {
+          self.pos -= 1;
+          return CssToken::BadString;
+        }
+        Some('\\') => match self.peek(0) {
+          None => {}
+          Some('\n') => self.pos += 1,
+          Some(_) => s.push(self.consume_escaped_code_point()),
+        },
+        Some(c) => s.push(c),
       }
+    }
+  }
 
-      let c = self.input[self.pos];
+  // https://www.w3.org/TR/css-syntax-3/#consume-numeric-token
+  fn consume_numeric_token(&mut self) -> CssToken {
+    let sign = self.peek(0).filter(|c| matches!(c, '+' | '-'));
+    let (value, numeric_type) = self.consume_number();
+    if would_start_identifier(self.peek(0), self.peek(1), self.peek(2)) {
+      return CssToken::Dimension(value, numeric_type, self.consume_ident_sequence());
+    }
+    if self.peek(0) == Some('%') {
+      self.pos += 1;
+      return CssToken::Percentage(value);
+    }
+    CssToken::Number(value, numeric_type, sign)
+  }
 
-      match c {
-        '0'..='9' => {
-          if floating {
-            floating_digit *= 1f64 / 10f64;
-            num += (c.to_digit(10).unwrap() as f64) * floating_digit;
-          } else {
-            num = num * 10.0 + (c.to_digit(10).unwrap() as f64);
-          }
-          self.pos += 1;
-        }
-        '.' => {
-          floating = true;
-          self.pos += 1;
+  // https://www.w3.org/TR/css-syntax-3/#consume-number
+  fn consume_number(&mut self) -> (f64, NumericType) {
+    let mut repr = String::new();
+    let mut numeric_type = NumericType::Integer;
+
+    if let Some(c @ ('+' | '-')) = self.peek(0) {
+      repr.push(c);
+      self.pos += 1;
+    }
+    self.consume_digits(&mut repr);
+    if self.peek(0) == Some('.') && self.peek(1).is_some_and(|c| c.is_ascii_digit()) {
+      repr.push('.');
+      self.pos += 1;
+      self.consume_digits(&mut repr);
+      numeric_type = NumericType::Number;
+    }
+    if let Some(e @ ('e' | 'E')) = self.peek(0) {
+      let sign = matches!(self.peek(1), Some('+' | '-'));
+      let digit = if sign { self.peek(2) } else { self.peek(1) };
+      if digit.is_some_and(|c| c.is_ascii_digit()) {
+        repr.push(e);
+        self.pos += 1;
+        if sign {
+          repr.push(self.consume().unwrap_or('+'));
         }
-        _ => break,
+        self.consume_digits(&mut repr);
+        numeric_type = NumericType::Number;
       }
     }
 
-    num
+    (repr.parse().unwrap_or(0.0), numeric_type)
+  }
+
+  fn consume_digits(&mut self, repr: &mut String) {
+    while let Some(c) = self.peek(0).filter(|c| c.is_ascii_digit()) {
+      repr.push(c);
+      self.pos += 1;
+    }
   }
 
-  fn consume_ident_token(&mut self) -> String {
+  // https://www.w3.org/TR/css-syntax-3/#consume-name
+  fn consume_ident_sequence(&mut self) -> String {
     let mut s = String::new();
-    s.push(self.input[self.pos]);
 
     loop {
-      self.pos += 1;
-      let c = self.input[self.pos];
-      match c {
-        'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' => {
+      match self.peek(0) {
+        Some(c) if is_ident_code_point(c) => {
           s.push(c);
+          self.pos += 1;
         }
-        _ => {
-          break;
+        Some('\\') if is_valid_escape(self.peek(0), self.peek(1)) => {
+          self.pos += 1;
+          s.push(self.consume_escaped_code_point());
         }
+        _ => return s,
       }
     }
-    
-    s
   }
-}
 
-impl Iterator for CssTokenizer {
-  type Item = CssToken;
+  // https://www.w3.org/TR/css-syntax-3/#consume-an-ident-like-token
+  fn consume_ident_like_token(&mut self) -> CssToken {
+    let name = self.consume_ident_sequence();
+    if self.peek(0) != Some('(') {
+      return CssToken::Ident(name);
+    }
+
+    self.pos += 1;
+    if !name.eq_ignore_ascii_case("url") {
+      return CssToken::Function(name);
+    }
+    while self.peek(0).is_some_and(is_whitespace) && self.peek(1).is_some_and(is_whitespace) {
+      self.pos += 1;
+    }
+    let next = match self.peek(0) {
+      Some(c) if is_whitespace(c) => self.peek(1),
+      c => c,
+    };
+    if matches!(next, Some('"' | '\'')) {
+      return CssToken::Function(name);
+    }
+    self.consume_url_token()
+  }
+
+  // https://www.w3.org/TR/css-syntax-3/#consume-url-token
+  fn consume_url_token(&mut self) -> CssToken {
+    let mut url = String::new();
+    self.consume_whitespace();
 
-  fn next(&mut self) -> Option<Self::Item> {
     loop {
-      if self.pos >= self.input.len() {
-        return None;
-      }
-
-      let c = self.input[self.pos];
-
-      let token = match c {
-        '(' => CssToken::OpenParenthesis,
-        ')' => CssToken::CloseParenthesis,
-        '{' => CssToken::OpenCurly,
-        '}' => CssToken::CloseCurly,
-        ':' => CssToken::Colon,
-        ';' => CssToken::SemiColon,
-        '.' => CssToken::Delim('.'),
-        ',' => CssToken::Delim(','),
-        ' ' | '\n' => {
-          self.pos += 1;
-          continue;
+      match self.consume() {
+        Some(')') | None => return CssToken::Url(url),
+        Some(c) if is_whitespace(c) => {
+          self.consume_whitespace();
+          if matches!(self.peek(0), Some(')') | None) {
+            self.consume();
+            return CssToken::Url(url);
+          }
+          self.consume_remnants_of_bad_url();
+          return CssToken::BadUrl;
         }
-        '"' | '\'' => {
-          let s = self.consume_string_token();
-          CssToken::StringToken(s)
+        Some('"' | '\'' | '(') => {
+          self.consume_remnants_of_bad_url();
+          return CssToken::BadUrl;
         }
-        '0'..='9' => {
-          let t = CssToken::Number(self.consume_numeric_token());
-          self.pos -= 1;
-          t
+        Some(c) if is_non_printable(c) => {
+          self.consume_remnants_of_bad_url();
+          return CssToken::BadUrl;
         }
-        '#' => {
-          let value = self.consume_ident_token();
-          self.pos -= 1;
-          CssToken::HashToken(value)
+        Some('\\') => {
+          if is_valid_escape(Some('\\'), self.peek(0)) {
+            url.push(self.consume_escaped_code_point());
+          } else {
+            self.consume_remnants_of_bad_url();
+            return CssToken::BadUrl;
+          }
         }
-        '-' => {
-          let t = CssToken::Ident(self.consume_ident_token());
-          self.pos -= 1;
-          t
+        Some(c) => url.push(c),
+      }
+    }
+  }
+
+  fn consume_remnants_of_bad_url(&mut self) {
+    loop {
+      match self.consume() {
+        Some(')') | None => return,
+        Some('\\') if is_valid_escape(Some('\\'), self.peek(0)) => {
+          self.consume_escaped_code_point();
         }
-        '@' => {
-          if self.input[self.pos + 1].is_ascii_alphabetic()
-            && self.input[self.pos + 2].is_alphabetic()
-            && self.input[self.pos + 3].is_alphabetic()
-          {
-            self.pos += 1;
-            let t = CssToken::AtKeyword(self.consume_ident_token());
-            self.pos -= 1;
-            t
+        Some(_) => {}
+      }
+    }
+  }
+
+  // https://www.w3.org/TR/css-syntax-3/#consume-escaped-code-point
+  // The backslash has already been consumed.
+  fn consume_escaped_code_point(&mut self) -> char {
+    let c = match self.consume() {
+      Some(c) => c,
+      None => return '\u{FFFD}',
+    };
+    if !c.is_ascii_hexdigit() {
+      return c;
+    }
+
+    let mut hex = String::new();
+    hex.push(c);
+    while hex.len() < 6 {
+      match self.peek(0) {
+        Some(c) if c.is_ascii_hexdigit() => {
+          hex.push(c);
+          self.pos += 1;
+        }
+        _ => break,
+      }
+    }
+    if self.peek(0).is_some_and(is_whitespace) {
+      self.pos += 1;
+    }
+    match u32::from_str_radix(&hex, 16) {
+      Ok(0) | Err(_) => '\u{FFFD}',
+      Ok(code) => char::from_u32(code).unwrap_or('\u{FFFD}'),
+    }
+  }
+
+  // https://www.w3.org/TR/2014/CR-css-syntax-3-20140220/#consume-a-unicode-range-token
+  // The `U+` has already been consumed.
+  fn consume_unicode_range_token(&mut self) -> CssToken {
+    let mut digits = String::new();
+    while digits.len() < 6 {
+      match self.peek(0) {
+        Some(c) if c.is_ascii_hexdigit() => digits.push(c),
+        _ => break,
+      }
+      self.pos += 1;
+    }
+    while digits.len() < 6 && self.peek(0) == Some('?') {
+      digits.push('?');
+      self.pos += 1;
+    }
+
+    if digits.contains('?') {
+      let start = u32::from_str_radix(&digits.replace('?', "0"), 16).unwrap_or(0);
+      let end = u32::from_str_radix(&digits.replace('?', "F"), 16).unwrap_or(0);
+      return CssToken::UnicodeRange(start, end);
+    }
+
+    let start = u32::from_str_radix(&digits, 16).unwrap_or(0);
+    if self.peek(0) != Some('-') || !self.peek(1).is_some_and(|c| c.is_ascii_hexdigit()) {
+      return CssToken::UnicodeRange(start, start);
+    }
+    self.pos += 1;
+    let mut digits = String::new();
+    while digits.len() < 6 {
+      match self.peek(0) {
+        Some(c) if c.is_ascii_hexdigit() => digits.push(c),
+        _ => break,
+      }
+      self.pos += 1;
+    }
+    CssToken::UnicodeRange(start, u32::from_str_radix(&digits, 16).unwrap_or(0))
+  }
+}
+
+impl Iterator for CssTokenizer {
+  type Item = CssToken;
+
+  // https://www.w3.org/TR/css-syntax-3/#consume-token
+  fn next(&mut self) -> Option<Self::Item> {
+    self.consume_comments();
+    self.token_start = self.pos;
+
+    let c = self.consume()?;
+    let token = match c {
+      c if is_whitespace(c) => {
+        self.consume_whitespace();
+        CssToken::Whitespace
+      }
+      '"' | '\'' => self.consume_string_token(c),
+      '#' => {
+        if self.peek(0).is_some_and(is_ident_code_point)
+          || is_valid_escape(self.peek(0), self.peek(1))
+        {
+          let hash_type = if would_start_identifier(self.peek(0), self.peek(1), self.peek(2)) {
+            HashType::Id
           } else {
-            CssToken::Delim('@')
-          }
+            HashType::Unrestricted
+          };
+          CssToken::HashToken(self.consume_ident_sequence(), hash_type)
+        } else {
+          CssToken::Delim('#')
         }
-        'a'..='z' | 'A'..='Z' | '_' => {
-          let t = CssToken::Ident(self.consume_ident_token());
+      }
+      '(' => CssToken::OpenParenthesis,
+      ')' => CssToken::CloseParenthesis,
+      '[' => CssToken::OpenSquare,
+      ']' => CssToken::CloseSquare,
+      '{' => CssToken::OpenCurly,
+      '}' => CssToken::CloseCurly,
+      ',' => CssToken::Comma,
+      ':' => CssToken::Colon,
+      ';' => CssToken::SemiColon,
+      '+' | '.' if starts_number(Some(c), self.peek(0), self.peek(1)) => {
+        self.pos -= 1;
+        self.consume_numeric_token()
+      }
+      '-' => {
+        if starts_number(Some(c), self.peek(0), self.peek(1)) {
+          self.pos -= 1;
+          self.consume_numeric_token()
+        } else if self.peek(0) == Some('-') && self.peek(1) == Some('>') {
+          self.pos += 2;
+          CssToken::Cdc
+        } else if would_start_identifier(Some(c), self.peek(0), self.peek(1)) {
           self.pos -= 1;
-          t
+          self.consume_ident_like_token()
+        } else {
+          CssToken::Delim(c)
         }
-        _ => {
-          unimplemented!("CssTokenizer::next() not implemented for character: {}", c);
+      }
+      '<' if self.peek(0) == Some('!') && self.peek(1) == Some('-') && self.peek(2) == Some('-') => {
+        self.pos += 3;
+        CssToken::Cdo
+      }
+      '@' => {
+        if would_start_identifier(self.peek(0), self.peek(1), self.peek(2)) {
+          CssToken::AtKeyword(self.consume_ident_sequence())
+        } else {
+          CssToken::Delim('@')
         }
-      };
+      }
+      '\\' if is_valid_escape(Some(c), self.peek(0)) => {
+        self.pos -= 1;
+        self.consume_ident_like_token()
+      }
+      '0'..='9' => {
+        self.pos -= 1;
+        self.consume_numeric_token()
+      }
+      'U' | 'u'
+        if self.peek(0) == Some('+')
+          && self.peek(1).is_some_and(|c| c.is_ascii_hexdigit() || c == '?') =>
+      {
+        self.pos += 1;
+        self.consume_unicode_range_token()
+      }
+      c if is_ident_start_code_point(c) => {
+        self.pos -= 1;
+        self.consume_ident_like_token()
+      }
+      c => CssToken::Delim(c),
+    };
 
-      self.pos += 1;
-      return Some(token);
+    Some(token)
+  }
+}
+
+// https://www.w3.org/TR/css-syntax-3/#input-preprocessing
+fn preprocess(css: &str) -> Vec<char> {
+  let mut input = Vec::with_capacity(css.len());
+  let mut chars = css.chars().peekable();
+  while let Some(c) = chars.next() {
+    match c {
+      '\r' => {
+        if chars.peek() == Some(&'\n') {
+          chars.next();
+        }
+        input.push('\n');
+      }
+      '\u{C}' => input.push('\n'),
+      '\0' => input.push('\u{FFFD}'),
+      c => input.push(c),
     }
   }
+  input
+}
+
+fn is_whitespace(c: char) -> bool {
+  matches!(c, '\n' | '\t' | ' ')
+}
+
+fn is_ident_start_code_point(c: char) -> bool {
+  c.is_ascii_alphabetic() || c == '_' || !c.is_ascii()
+}
+
+fn is_ident_code_point(c: char) -> bool {
+  is_ident_start_code_point(c) || c.is_ascii_digit() || c == '-'
+}
+
+fn is_non_printable(c: char) -> bool {
+  matches!(c, '\0'..='\u{8}' | '\u{B}' | '\u{E}'..='\u{1F}' | '\u{7F}')
+}
+
+// https://www.w3.org/TR/css-syntax-3/#starts-with-a-valid-escape
+fn is_valid_escape(first: Option<char>, second: Option<char>) -> bool {
+  first == Some('\\') && second != Some('\n')
+}
+
+// https://www.w3.org/TR/css-syntax-3/#would-start-an-identifier
+fn would_start_identifier(first: Option<char>, second: Option<char>, third: Option<char>) -> bool {
+  match first {
+    Some('-') => {
+      second.is_some_and(|c| is_ident_start_code_point(c) || c == '-')
+        || is_valid_escape(second, third)
+    }
+    Some('\\') => is_valid_escape(first, second),
+    Some(c) => is_ident_start_code_point(c),
+    None => false,
+  }
+}
+
+// https://www.w3.org/TR/css-syntax-3/#starts-with-a-number
+fn starts_number(first: Option<char>, second: Option<char>, third: Option<char>) -> bool {
+  let is_digit = |c: Option<char>| c.is_some_and(|c| c.is_ascii_digit());
+  match first {
+    Some('+' | '-') => is_digit(second) || (second == Some('.') && is_digit(third)),
+    Some('.') => is_digit(second),
+    c => is_digit(c),
+  }
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
   use alloc::string::ToString;
+  use alloc::vec;
+
+  fn tokens(css: &str) -> Vec<CssToken> {
+    CssTokenizer::new(css.to_string()).collect()
+  }
+
+  fn ident(s: &str) -> CssToken {
+    CssToken::Ident(s.to_string())
+  }
 
   #[test]
   fn test_empty() {
@@ -192,11 +560,15 @@ mod tests {
     let style = "p { color: red; }".to_string();
     let mut t = CssTokenizer::new(style);
     assert_eq!(t.next(), Some(CssToken::Ident("p".to_string())));
+    assert_eq!(t.next(), Some(CssToken::Whitespace));
     assert_eq!(t.next(), Some(CssToken::OpenCurly));
+    assert_eq!(t.next(), Some(CssToken::Whitespace));
     assert_eq!(t.next(), Some(CssToken::Ident("color".to_string())));
     assert_eq!(t.next(), Some(CssToken::Colon));
+    assert_eq!(t.next(), Some(CssToken::Whitespace));
     assert_eq!(t.next(), Some(CssToken::Ident("red".to_string())));
     assert_eq!(t.next(), Some(CssToken::SemiColon));
+    assert_eq!(t.next(), Some(CssToken::Whitespace));
     assert_eq!(t.next(), Some(CssToken::CloseCurly));
     assert_eq!(t.next(), None);
   }
@@ -205,12 +577,16 @@ mod tests {
   fn test_id_selector() {
     let style = "#id { color: red; }".to_string();
     let mut t = CssTokenizer::new(style);
-    assert_eq!(t.next(), Some(CssToken::HashToken("#id".to_string())));
+    assert_eq!(t.next(), Some(CssToken::HashToken("id".to_string(), HashType::Id)));
+    assert_eq!(t.next(), Some(CssToken::Whitespace));
     assert_eq!(t.next(), Some(CssToken::OpenCurly));
+    assert_eq!(t.next(), Some(CssToken::Whitespace));
     assert_eq!(t.next(), Some(CssToken::Ident("color".to_string())));
     assert_eq!(t.next(), Some(CssToken::Colon));
+    assert_eq!(t.next(), Some(CssToken::Whitespace));
     assert_eq!(t.next(), Some(CssToken::Ident("red".to_string())));
     assert_eq!(t.next(), Some(CssToken::SemiColon));
+    assert_eq!(t.next(), Some(CssToken::Whitespace));
     assert_eq!(t.next(), Some(CssToken::CloseCurly));
     assert_eq!(t.next(), None);
   }
@@ -220,18 +596,27 @@ mod tests {
     let style = "p { color: red; } div { color: blue; }".to_string();
     let mut t = CssTokenizer::new(style);
     assert_eq!(t.next(), Some(CssToken::Ident("p".to_string())));
+    assert_eq!(t.next(), Some(CssToken::Whitespace));
     assert_eq!(t.next(), Some(CssToken::OpenCurly));
+    assert_eq!(t.next(), Some(CssToken::Whitespace));
     assert_eq!(t.next(), Some(CssToken::Ident("color".to_string())));
     assert_eq!(t.next(), Some(CssToken::Colon));
+    assert_eq!(t.next(), Some(CssToken::Whitespace));
     assert_eq!(t.next(), Some(CssToken::Ident("red".to_string())));
     assert_eq!(t.next(), Some(CssToken::SemiColon));
+    assert_eq!(t.next(), Some(CssToken::Whitespace));
     assert_eq!(t.next(), Some(CssToken::CloseCurly));
+    assert_eq!(t.next(), Some(CssToken::Whitespace));
     assert_eq!(t.next(), Some(CssToken::Ident("div".to_string())));
+    assert_eq!(t.next(), Some(CssToken::Whitespace));
     assert_eq!(t.next(), Some(CssToken::OpenCurly));
+    assert_eq!(t.next(), Some(CssToken::Whitespace));
     assert_eq!(t.next(), Some(CssToken::Ident("color".to_string())));
     assert_eq!(t.next(), Some(CssToken::Colon));
+    assert_eq!(t.next(), Some(CssToken::Whitespace));
     assert_eq!(t.next(), Some(CssToken::Ident("blue".to_string())));
     assert_eq!(t.next(), Some(CssToken::SemiColon));
+    assert_eq!(t.next(), Some(CssToken::Whitespace));
     assert_eq!(t.next(), Some(CssToken::CloseCurly));
     assert_eq!(t.next(), None);
   }
@@ -242,12 +627,146 @@ mod tests {
     let mut t = CssTokenizer::new(style);
     assert_eq!(t.next(), Some(CssToken::Delim('.')));
     assert_eq!(t.next(), Some(CssToken::Ident("class".to_string())));
+    assert_eq!(t.next(), Some(CssToken::Whitespace));
     assert_eq!(t.next(), Some(CssToken::OpenCurly));
+    assert_eq!(t.next(), Some(CssToken::Whitespace));
     assert_eq!(t.next(), Some(CssToken::Ident("color".to_string())));
     assert_eq!(t.next(), Some(CssToken::Colon));
+    assert_eq!(t.next(), Some(CssToken::Whitespace));
     assert_eq!(t.next(), Some(CssToken::Ident("red".to_string())));
     assert_eq!(t.next(), Some(CssToken::SemiColon));
+    assert_eq!(t.next(), Some(CssToken::Whitespace));
     assert_eq!(t.next(), Some(CssToken::CloseCurly));
     assert_eq!(t.next(), None);
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn test_hash_types() {
+    assert_eq!(
+      vec![
+        CssToken::HashToken("id".to_string(), HashType::Id),
+        CssToken::Whitespace,
+        CssToken::HashToken("1a".to_string(), HashType::Unrestricted),
+        CssToken::Whitespace,
+        CssToken::Delim('#'),
+      ],
+      tokens("#id #1a #"),
+    );
+  }
+
+  #[test]
+  fn test_comments() {
+    assert_eq!(vec![ident("a"), ident("b"), CssToken::Whitespace], tokens("/* x */a/**/b /* c"));
+  }
+
+  #[test]
+  fn test_numbers() {
+    assert_eq!(
+      vec![
+        CssToken::Dimension(10.0, NumericType::Integer, "px".to_string()),
+        CssToken::Whitespace,
+        CssToken::Percentage(50.0),
+        CssToken::Whitespace,
+        CssToken::Number(-0.5, NumericType::Number, Some('-')),
+        CssToken::Whitespace,
+        CssToken::Number(1e3, NumericType::Number, Some('+')),
+        CssToken::Whitespace,
+        CssToken::Number(1.0, NumericType::Integer, None),
+        CssToken::Delim('.'),
+        CssToken::Whitespace,
+        CssToken::Delim('+'),
+        ident("a"),
+      ],
+      tokens("10px 50% -.5 +1E+3 1. +a"),
+    );
+  }
+
+  #[test]
+  fn test_functions_and_urls() {
+    assert_eq!(
+      vec![
+        CssToken::Function("rgb".to_string()),
+        CssToken::Number(1.0, NumericType::Integer, None),
+        CssToken::Comma,
+        CssToken::Number(2.0, NumericType::Integer, None),
+        CssToken::CloseParenthesis,
+        CssToken::Whitespace,
+        CssToken::Url("a.png".to_string()),
+        CssToken::Whitespace,
+        CssToken::Function("url".to_string()),
+        CssToken::Whitespace,
+        CssToken::StringToken("b".to_string()),
+        CssToken::CloseParenthesis,
+        CssToken::Whitespace,
+        CssToken::BadUrl,
+        CssToken::Whitespace,
+        CssToken::OpenSquare,
+        ident("x"),
+        CssToken::CloseSquare,
+      ],
+      tokens("rgb(1,2) url( a.png ) url( 'b') url(a b) [x]"),
+    );
+  }
+
+  #[test]
+  fn test_strings_and_escapes() {
+    assert_eq!(
+      vec![
+        CssToken::StringToken("a\"b".to_string()),
+        CssToken::Whitespace,
+        CssToken::BadString,
+        CssToken::Whitespace,
+        ident("AB"),
+        CssToken::Delim('\\'),
+        CssToken::Whitespace,
+        ident("\u{FFFD}"),
+      ],
+      tokens("'a\\\"b' \"c\n\\41 \\42\r\n\\\n\\"),
+    );
+  }
+
+  #[test]
+  fn test_cdo_cdc_and_at_keywords() {
+    assert_eq!(
+      vec![
+        CssToken::Cdo,
+        CssToken::AtKeyword("media".to_string()),
+        CssToken::Whitespace,
+        CssToken::Delim('@'),
+        CssToken::Whitespace,
+        CssToken::Cdc,
+        ident("--x"),
+        CssToken::Delim('<'),
+      ],
+      tokens("<!--@media @ -->--x<"),
+    );
+  }
+
+  #[test]
+  fn test_unicode_range() {
+    assert_eq!(
+      vec![
+        CssToken::UnicodeRange(0x26, 0x26),
+        CssToken::Whitespace,
+        CssToken::UnicodeRange(0x0, 0x7F),
+        CssToken::Whitespace,
+        CssToken::UnicodeRange(0x400, 0x4FF),
+        CssToken::Whitespace,
+        ident("u"),
+        CssToken::Delim('+'),
+        ident("x"),
+      ],
+      tokens("U+26 u+0-7F U+4??  u+x"),
+    );
+  }
+
+  #[test]
+  fn test_token_position() {
+    let mut t = CssTokenizer::new("a {\r\n  /* b */ c: d }".to_string());
+    assert_eq!(Some(ident("a")), t.next());
+    assert_eq!(SourcePosition::new(1, 1), t.position(t.token_start()));
+    t.nth(3);
+    assert_eq!(Some(ident("c")), t.next());
+    assert_eq!(SourcePosition::new(2, 11), t.position(t.token_start()));
+  }
+}
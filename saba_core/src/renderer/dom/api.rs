@@ -1,31 +1,26 @@
 use core::cell::RefCell;
+use core::mem;
 
-use alloc::{rc::Rc, string::String, vec::Vec};
+use alloc::{rc::{Rc, Weak}, string::String, vec, vec::Vec};
 
-use super::node::{Element, ElementKind, Node, NodeKind};
+use super::mutation::{
+  mark_child_list_dirty, mark_layout_dirty, mark_style_dirty, queue_mutation_record,
+  MutationRecord, MutationType,
+};
+use super::node::{ElementKind, Namespace, Node, NodeKind};
+use super::query::{descendants, index_id, index_ids, unindex_id, unindex_ids};
+use crate::error::Error;
 use alloc::string::ToString;
 
 pub fn get_target_element_node(
   node: Option<Rc<RefCell<Node>>>,
   element_kind: ElementKind,
 ) -> Option<Rc<RefCell<Node>>> {
-  match node {
-    Some(n) => {
-      if n.borrow().kind() == NodeKind::Element(Element::new(&element_kind.to_string(), Vec::new())) {
-        return Some(n.clone());
-      }
-      let result1 = get_target_element_node(n.borrow().first_child(), element_kind);
-      let result2 = get_target_element_node(n.borrow().next_sibling(), element_kind);
-      if result1.is_none() && result2.is_none() {
-        return None;
-      }
-      if result1.is_none() {
-        return result2;
-      }
-      result1
-    }
-    None => None,
+  let node = node?;
+  if node.borrow().element_kind() == Some(element_kind) {
+    return Some(node);
   }
+  descendants(&node).find(|n| n.borrow().element_kind() == Some(element_kind))
 }
 
 pub fn get_style_content(root: Rc<RefCell<Node>>) -> String {
@@ -42,4 +37,600 @@ pub fn get_style_content(root: Rc<RefCell<Node>>) -> String {
     _ => return "".to_string(),
   };
   content
-}
\ No newline at end of file
+}
+
+pub fn create_document_fragment() -> Rc<RefCell<Node>> {
+  Rc::new(RefCell::new(Node::new(NodeKind::DocumentFragment)))
+}
+
+pub fn append_child(parent: &Rc<RefCell<Node>>, child: &Rc<RefCell<Node>>) -> Result<(), Error> {
+  insert_before(parent, child, None)
+}
+
+/// Inserts `child` into `parent` before `reference`, or at the end when it is None. The child is
+/// detached from its current parent first, and a DocumentFragment is replaced by its children.
+pub fn insert_before(
+  parent: &Rc<RefCell<Node>>,
+  child: &Rc<RefCell<Node>>,
+  reference: Option<&Rc<RefCell<Node>>>,
+) -> Result<(), Error> {
+  ensure_pre_insertion_validity(parent, child, reference)?;
+
+  // Inserting a node before itself means inserting it before its next sibling.
+  let next = child.borrow().next_sibling();
+  let reference = match reference {
+    Some(reference) if Rc::ptr_eq(reference, child) => next.as_ref(),
+    _ => reference,
+  };
+  insert_node(parent, child, reference);
+  Ok(())
+}
+
+pub fn remove_child(parent: &Rc<RefCell<Node>>, child: &Rc<RefCell<Node>>) -> Result<(), Error> {
+  if !is_child_of(child, parent) {
+    return Err(Error::NotFound("the node to be removed is not a child of this node".to_string()));
+  }
+  detach(child);
+  Ok(())
+}
+
+/// Replaces `old_child` of `parent` with `new_child`, which may be a DocumentFragment.
+pub fn replace_child(
+  parent: &Rc<RefCell<Node>>,
+  new_child: &Rc<RefCell<Node>>,
+  old_child: &Rc<RefCell<Node>>,
+) -> Result<(), Error> {
+  if !is_child_of(old_child, parent) {
+    return Err(Error::NotFound("the node to be replaced is not a child of this node".to_string()));
+  }
+  ensure_pre_insertion_validity(parent, new_child, None)?;
+  if Rc::ptr_eq(new_child, old_child) {
+    return Ok(());
+  }
+
+  let mut reference = old_child.borrow().next_sibling();
+  if reference.as_ref().is_some_and(|r| Rc::ptr_eq(r, new_child)) {
+    reference = new_child.borrow().next_sibling();
+  }
+  detach(old_child);
+  insert_node(parent, new_child, reference.as_ref());
+  Ok(())
+}
+
+fn ensure_pre_insertion_validity(
+  parent: &Rc<RefCell<Node>>,
+  child: &Rc<RefCell<Node>>,
+  reference: Option<&Rc<RefCell<Node>>>,
+) -> Result<(), Error> {
+  if matches!(
+    parent.borrow().kind,
+    NodeKind::Text(_) | NodeKind::Comment(_) | NodeKind::DocumentType { .. }
+  ) {
+    return Err(Error::HierarchyRequest(
+      "text, comment and doctype nodes cannot have children".to_string(),
+    ));
+  }
+  let mut ancestor = Some(parent.clone());
+  while let Some(a) = ancestor {
+    if Rc::ptr_eq(&a, child) {
+      return Err(Error::HierarchyRequest("a node cannot be inserted into itself".to_string()));
+    }
+    ancestor = a.borrow().parent().upgrade();
+  }
+  if let Some(reference) = reference {
+    if !is_child_of(reference, parent) {
+      return Err(Error::NotFound("the reference node is not a child of this node".to_string()));
+    }
+  }
+  match (&parent.borrow().kind, &child.borrow().kind) {
+    (_, NodeKind::Document) => {
+      Err(Error::HierarchyRequest("a document cannot be inserted".to_string()))
+    }
+    (NodeKind::Document, NodeKind::Text(_)) => {
+      Err(Error::HierarchyRequest("a document cannot have text children".to_string()))
+    }
+    (NodeKind::Element(_) | NodeKind::DocumentFragment, NodeKind::DocumentType { .. }) => {
+      Err(Error::HierarchyRequest("a doctype can only be a child of a document".to_string()))
+    }
+    _ => Ok(()),
+  }
+}
+
+fn is_child_of(child: &Rc<RefCell<Node>>, parent: &Rc<RefCell<Node>>) -> bool {
+  child.borrow().parent().upgrade().is_some_and(|p| Rc::ptr_eq(&p, parent))
+}
+
+fn insert_node(
+  parent: &Rc<RefCell<Node>>,
+  child: &Rc<RefCell<Node>>,
+  reference: Option<&Rc<RefCell<Node>>>,
+) {
+  let nodes = if child.borrow().kind == NodeKind::DocumentFragment {
+    let nodes = remove_children(child);
+    if nodes.is_empty() {
+      return;
+    }
+    queue_mutation_record(child, MutationType::ChildList, || {
+      MutationRecord::child_list(child.clone(), Vec::new(), nodes.clone(), None, None)
+    });
+    nodes
+  } else {
+    detach(child);
+    vec![child.clone()]
+  };
+
+  let previous = match reference {
+    Some(reference) => reference.borrow().previous_sibling().upgrade(),
+    None => parent.borrow().last_child().upgrade(),
+  };
+  for node in &nodes {
+    link(parent, node, reference);
+  }
+  queue_mutation_record(parent, MutationType::ChildList, || {
+    MutationRecord::child_list(parent.clone(), nodes.clone(), Vec::new(), previous, reference.cloned())
+  });
+}
+
+// Links a detached `child` into `parent` without queueing a mutation record.
+fn link(parent: &Rc<RefCell<Node>>, child: &Rc<RefCell<Node>>, reference: Option<&Rc<RefCell<Node>>>) {
+  let previous = match reference {
+    Some(reference) => reference.borrow().previous_sibling().upgrade(),
+    None => parent.borrow().last_child().upgrade(),
+  };
+  match previous {
+    Some(ref p) => {
+      p.borrow_mut().set_next_sibling(Some(child.clone()));
+      child.borrow_mut().set_previous_sibling(Rc::downgrade(p));
+    }
+    None => parent.borrow_mut().set_first_child(Some(child.clone())),
+  }
+  match reference {
+    Some(reference) => {
+      child.borrow_mut().set_next_sibling(Some(reference.clone()));
+      reference.borrow_mut().set_previous_sibling(Rc::downgrade(child));
+    }
+    None => parent.borrow_mut().set_last_child(Rc::downgrade(child)),
+  }
+  child.borrow_mut().set_parent(Rc::downgrade(parent));
+  index_ids(child);
+  mark_style_dirty(child);
+}
+
+pub fn detach(node: &Rc<RefCell<Node>>) {
+  let parent = match node.borrow().parent().upgrade() {
+    Some(parent) => parent,
+    None => return,
+  };
+  queue_mutation_record(&parent, MutationType::ChildList, || {
+    let previous = node.borrow().previous_sibling().upgrade();
+    let next = node.borrow().next_sibling();
+    MutationRecord::child_list(parent.clone(), Vec::new(), vec![node.clone()], previous, next)
+  });
+  unlink(&parent, node);
+  mark_child_list_dirty(&parent);
+}
+
+// Unlinks `node` from `parent` without queueing a mutation record.
+fn unlink(parent: &Rc<RefCell<Node>>, node: &Rc<RefCell<Node>>) {
+  unindex_ids(node);
+  let previous = node.borrow().previous_sibling().upgrade();
+  let next = node.borrow().next_sibling();
+
+  match previous {
+    Some(ref p) => p.borrow_mut().set_next_sibling(next.clone()),
+    None => parent.borrow_mut().set_first_child(next.clone()),
+  }
+  match next {
+    Some(ref n) => n.borrow_mut().set_previous_sibling(node.borrow().previous_sibling()),
+    None => parent.borrow_mut().set_last_child(node.borrow().previous_sibling()),
+  }
+
+  let mut node = node.borrow_mut();
+  node.set_parent(Weak::new());
+  node.set_previous_sibling(Weak::new());
+  node.set_next_sibling(None);
+}
+
+// Unlinks all children of `node` and returns them. The caller queues a single mutation record for
+// them.
+fn remove_children(node: &Rc<RefCell<Node>>) -> Vec<Rc<RefCell<Node>>> {
+  let mut removed = Vec::new();
+  loop {
+    let child = match node.borrow().first_child() {
+      Some(child) => child,
+      None => break,
+    };
+    unlink(node, &child);
+    removed.push(child);
+  }
+  if !removed.is_empty() {
+    mark_child_list_dirty(node);
+  }
+  removed
+}
+
+/// Copies `node` without its parent or siblings. With `deep`, its descendants and template
+/// contents are copied as well.
+pub fn clone_node(node: &Rc<RefCell<Node>>, deep: bool) -> Rc<RefCell<Node>> {
+  let copy = Rc::new(RefCell::new(Node::new(node.borrow().kind())));
+  if !deep {
+    return copy;
+  }
+
+  if let Some(contents) = node.borrow().template_contents() {
+    copy.borrow_mut().set_template_contents(Some(clone_node(&contents, true)));
+  }
+  let mut child = node.borrow().first_child();
+  while let Some(c) = child {
+    insert_node(&copy, &clone_node(&c, true), None);
+    child = c.borrow().next_sibling();
+  }
+  copy
+}
+
+/// Sets an attribute on an element. Names are lowercased on HTML elements, as with
+/// `Element.setAttribute`.
+pub fn set_attribute(node: &Rc<RefCell<Node>>, name: &str, value: &str) -> Result<(), Error> {
+  let name = attribute_name(node, name)?;
+  let old_value = attribute_value(node, &name);
+  queue_mutation_record(node, MutationType::Attributes, || {
+    MutationRecord::attributes(node.clone(), name.clone(), old_value)
+  });
+  let is_id = name == "id";
+  if is_id {
+    unindex_id(node);
+  }
+  if let NodeKind::Element(ref mut element) = node.borrow_mut().kind {
+    element.set_attribute(&name, value);
+  }
+  if is_id {
+    index_id(node);
+  }
+  mark_style_dirty(node);
+  Ok(())
+}
+
+pub fn remove_attribute(node: &Rc<RefCell<Node>>, name: &str) -> Result<(), Error> {
+  let name = attribute_name(node, name)?;
+  let old_value = match attribute_value(node, &name) {
+    Some(value) => value,
+    None => return Ok(()),
+  };
+  queue_mutation_record(node, MutationType::Attributes, || {
+    MutationRecord::attributes(node.clone(), name.clone(), Some(old_value))
+  });
+  let is_id = name == "id";
+  if is_id {
+    unindex_id(node);
+  }
+  if let NodeKind::Element(ref mut element) = node.borrow_mut().kind {
+    element.remove_attribute(&name);
+  }
+  mark_style_dirty(node);
+  Ok(())
+}
+
+fn attribute_value(node: &Rc<RefCell<Node>>, name: &str) -> Option<String> {
+  match node.borrow().kind {
+    NodeKind::Element(ref element) => {
+      element.get_attribute(name)
+    }
+    _ => None,
+  }
+}
+
+fn attribute_name(node: &Rc<RefCell<Node>>, name: &str) -> Result<String, Error> {
+  match node.borrow().kind {
+    NodeKind::Element(ref element) if element.namespace() == Namespace::Html => {
+      Ok(name.to_ascii_lowercase())
+    }
+    NodeKind::Element(_) => Ok(name.to_string()),
+    _ => Err(Error::HierarchyRequest("only elements have attributes".to_string())),
+  }
+}
+
+/// Returns the data of a text or comment node, or the concatenated text of all descendant text nodes of an
+/// element or fragment. A document has no text content.
+pub fn text_content(node: &Rc<RefCell<Node>>) -> Option<String> {
+  match node.borrow().kind {
+    NodeKind::Document | NodeKind::DocumentType { .. } => return None,
+    NodeKind::Text(ref s) | NodeKind::Comment(ref s) => return Some(s.clone()),
+    NodeKind::Element(_) | NodeKind::DocumentFragment => {}
+  }
+
+  let mut text = String::new();
+  for n in descendants(node) {
+    if let NodeKind::Text(ref s) = n.borrow().kind {
+      text.push_str(s);
+    }
+  }
+  Some(text)
+}
+
+/// Replaces the children of an element or fragment with a single text node, or none when `text`
+/// is empty. On a text or comment node, replaces its data.
+pub fn set_text_content(node: &Rc<RefCell<Node>>, text: &str) {
+  let old_data = match node.borrow_mut().kind {
+    NodeKind::Document | NodeKind::DocumentType { .. } => return,
+    NodeKind::Text(ref mut s) | NodeKind::Comment(ref mut s) => Some(mem::replace(s, text.to_string())),
+    NodeKind::Element(_) | NodeKind::DocumentFragment => None,
+  };
+  if let Some(old_data) = old_data {
+    queue_mutation_record(node, MutationType::CharacterData, || {
+      MutationRecord::character_data(node.clone(), old_data)
+    });
+    mark_layout_dirty(node);
+    return;
+  }
+
+  let removed = remove_children(node);
+  let mut added = Vec::new();
+  if !text.is_empty() {
+    let text = Rc::new(RefCell::new(Node::new(NodeKind::Text(text.to_string()))));
+    link(node, &text, None);
+    added.push(text);
+  }
+  if !removed.is_empty() || !added.is_empty() {
+    queue_mutation_record(node, MutationType::ChildList, || {
+      MutationRecord::child_list(node.clone(), added, removed, None, None)
+    });
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::renderer::dom::node::Element;
+  use crate::renderer::dom::query::get_element_by_id;
+
+  fn element(name: &str) -> Rc<RefCell<Node>> {
+    Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(name, Vec::new())))))
+  }
+
+  fn child_names(parent: &Rc<RefCell<Node>>) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut child = parent.borrow().first_child();
+    while let Some(c) = child {
+      let parent_of_child = c.borrow().parent().upgrade().expect("child should have a parent");
+      assert!(Rc::ptr_eq(parent, &parent_of_child));
+      names.push(c.borrow().get_element().map(|e| e.tag_name()).unwrap_or_default());
+      child = c.borrow().next_sibling();
+    }
+    names
+  }
+
+  #[test]
+  fn test_insert_document_fragment() {
+    let parent = element("div");
+    let last = element("p");
+    append_child(&parent, &last).unwrap();
+
+    let fragment = create_document_fragment();
+    append_child(&fragment, &element("a")).unwrap();
+    append_child(&fragment, &element("b")).unwrap();
+    insert_before(&parent, &fragment, Some(&last)).unwrap();
+
+    assert_eq!(alloc::vec!["a", "b", "p"], child_names(&parent));
+    assert!(fragment.borrow().first_child().is_none());
+    assert!(fragment.borrow().last_child().upgrade().is_none());
+
+    let b = parent.borrow().first_child().and_then(|a| a.borrow().next_sibling()).unwrap();
+    let a = b.borrow().previous_sibling().upgrade().unwrap();
+    assert!(a.borrow().previous_sibling().upgrade().is_none());
+    assert!(Rc::ptr_eq(&b, &last.borrow().previous_sibling().upgrade().unwrap()));
+  }
+
+  #[test]
+  fn test_append_moves_node() {
+    let first = element("div");
+    let second = element("div");
+    let child = element("span");
+    append_child(&first, &child).unwrap();
+    append_child(&second, &child).unwrap();
+
+    assert!(child_names(&first).is_empty());
+    assert!(first.borrow().last_child().upgrade().is_none());
+    assert_eq!(alloc::vec!["span"], child_names(&second));
+  }
+
+  fn text(data: &str) -> Rc<RefCell<Node>> {
+    Rc::new(RefCell::new(Node::new(NodeKind::Text(data.to_string()))))
+  }
+
+  // Checks every link under `node` in both directions.
+  fn assert_consistent(node: &Rc<RefCell<Node>>) {
+    let mut previous: Option<Rc<RefCell<Node>>> = None;
+    let mut child = node.borrow().first_child();
+    while let Some(c) = child {
+      assert!(is_child_of(&c, node));
+      match (c.borrow().previous_sibling().upgrade(), previous.as_ref()) {
+        (Some(p), Some(q)) => assert!(Rc::ptr_eq(&p, q)),
+        (None, None) => {}
+        _ => panic!("previous_sibling is inconsistent"),
+      }
+      assert_consistent(&c);
+      child = c.borrow().next_sibling();
+      previous = Some(c);
+    }
+    match (node.borrow().last_child().upgrade(), previous) {
+      (Some(l), Some(p)) => assert!(Rc::ptr_eq(&l, &p)),
+      (None, None) => {}
+      _ => panic!("last_child is inconsistent"),
+    }
+  }
+
+  #[test]
+  fn test_insert_before() {
+    let parent = element("div");
+    let a = element("a");
+    let b = element("b");
+    let c = element("c");
+    append_child(&parent, &c).unwrap();
+    insert_before(&parent, &a, Some(&c)).unwrap();
+    insert_before(&parent, &b, Some(&c)).unwrap();
+    assert_eq!(alloc::vec!["a", "b", "c"], child_names(&parent));
+    assert_consistent(&parent);
+
+    insert_before(&parent, &c, Some(&a)).unwrap();
+    assert_eq!(alloc::vec!["c", "a", "b"], child_names(&parent));
+    insert_before(&parent, &a, Some(&a)).unwrap();
+    assert_eq!(alloc::vec!["c", "a", "b"], child_names(&parent));
+    assert_consistent(&parent);
+  }
+
+  #[test]
+  fn test_insert_errors() {
+    let parent = element("div");
+    let child = element("p");
+    let stranger = element("span");
+    append_child(&parent, &child).unwrap();
+
+    assert!(matches!(append_child(&child, &parent), Err(Error::HierarchyRequest(_))));
+    assert!(matches!(append_child(&parent, &parent), Err(Error::HierarchyRequest(_))));
+    assert!(matches!(append_child(&text("x"), &element("a")), Err(Error::HierarchyRequest(_))));
+    assert!(matches!(
+      insert_before(&parent, &element("a"), Some(&stranger)),
+      Err(Error::NotFound(_)),
+    ));
+
+    let document = Rc::new(RefCell::new(Node::new(NodeKind::Document)));
+    assert!(matches!(append_child(&document, &text("x")), Err(Error::HierarchyRequest(_))));
+    assert!(matches!(append_child(&parent, &document), Err(Error::HierarchyRequest(_))));
+
+    assert_eq!(alloc::vec!["p"], child_names(&parent));
+    assert_consistent(&parent);
+  }
+
+  #[test]
+  fn test_remove_child() {
+    let parent = element("div");
+    let a = element("a");
+    let b = element("b");
+    let c = element("c");
+    for child in [&a, &b, &c] {
+      append_child(&parent, child).unwrap();
+    }
+
+    remove_child(&parent, &b).unwrap();
+    assert_eq!(alloc::vec!["a", "c"], child_names(&parent));
+    assert!(b.borrow().parent().upgrade().is_none());
+    assert!(b.borrow().next_sibling().is_none());
+    assert!(b.borrow().previous_sibling().upgrade().is_none());
+    assert!(matches!(remove_child(&parent, &b), Err(Error::NotFound(_))));
+
+    remove_child(&parent, &c).unwrap();
+    remove_child(&parent, &a).unwrap();
+    assert!(child_names(&parent).is_empty());
+    assert_consistent(&parent);
+  }
+
+  #[test]
+  fn test_replace_child() {
+    let parent = element("div");
+    let a = element("a");
+    let b = element("b");
+    let c = element("c");
+    for child in [&a, &b, &c] {
+      append_child(&parent, child).unwrap();
+    }
+
+    let x = element("x");
+    replace_child(&parent, &x, &b).unwrap();
+    assert_eq!(alloc::vec!["a", "x", "c"], child_names(&parent));
+    assert!(b.borrow().parent().upgrade().is_none());
+
+    replace_child(&parent, &c, &a).unwrap();
+    assert_eq!(alloc::vec!["c", "x"], child_names(&parent));
+    replace_child(&parent, &x, &c).unwrap();
+    assert_eq!(alloc::vec!["x"], child_names(&parent));
+
+    let fragment = create_document_fragment();
+    append_child(&fragment, &element("y")).unwrap();
+    append_child(&fragment, &element("z")).unwrap();
+    replace_child(&parent, &fragment, &x).unwrap();
+    assert_eq!(alloc::vec!["y", "z"], child_names(&parent));
+
+    assert!(matches!(replace_child(&parent, &element("q"), &x), Err(Error::NotFound(_))));
+    let y = parent.borrow().first_child().unwrap();
+    assert!(matches!(replace_child(&y, &parent, &y), Err(Error::NotFound(_))));
+    assert_consistent(&parent);
+  }
+
+  #[test]
+  fn test_clone_node() {
+    let parent = element("div");
+    let p = element("p");
+    append_child(&parent, &p).unwrap();
+    append_child(&p, &text("hello")).unwrap();
+    set_attribute(&p, "class", "x").unwrap();
+
+    let shallow = clone_node(&parent, false);
+    assert!(shallow.borrow().first_child().is_none());
+
+    let deep = clone_node(&parent, true);
+    assert_eq!(alloc::vec!["p"], child_names(&deep));
+    let copy = deep.borrow().first_child().unwrap();
+    assert!(!Rc::ptr_eq(&copy, &p));
+    assert_eq!(Some("hello".to_string()), text_content(&deep));
+    assert_eq!(p.borrow().kind(), copy.borrow().kind());
+    assert!(deep.borrow().parent().upgrade().is_none());
+    assert_consistent(&deep);
+
+    set_text_content(&copy, "changed");
+    assert_eq!(Some("hello".to_string()), text_content(&parent));
+  }
+
+  #[test]
+  fn test_attributes() {
+    let div = element("div");
+    set_attribute(&div, "Class", "a").unwrap();
+    set_attribute(&div, "class", "b").unwrap();
+    let attributes = div.borrow().get_element().unwrap().attributes();
+    assert_eq!(1, attributes.len());
+    assert_eq!("class", attributes[0].name());
+    assert_eq!("b", attributes[0].value());
+
+    remove_attribute(&div, "CLASS").unwrap();
+    assert!(div.borrow().get_element().unwrap().attributes().is_empty());
+    assert!(set_attribute(&text("x"), "id", "y").is_err());
+  }
+
+  #[test]
+  fn test_set_attribute_updates_id_index() {
+    let document = Rc::new(RefCell::new(Node::new(NodeKind::Document)));
+    let div = element("div");
+    append_child(&document, &div).unwrap();
+    crate::renderer::dom::query::enable_id_index(&document);
+
+    set_attribute(&div, "id", "a").unwrap();
+    assert!(get_element_by_id(&document, "a").is_some());
+    set_attribute(&div, "id", "b").unwrap();
+    assert!(get_element_by_id(&document, "a").is_none());
+    assert!(get_element_by_id(&document, "b").is_some());
+    remove_attribute(&div, "id").unwrap();
+    assert!(get_element_by_id(&document, "b").is_none());
+  }
+
+  #[test]
+  fn test_text_content() {
+    let div = element("div");
+    let p = element("p");
+    append_child(&div, &text("a")).unwrap();
+    append_child(&div, &p).unwrap();
+    append_child(&p, &text("b")).unwrap();
+    assert_eq!(Some("ab".to_string()), text_content(&div));
+    assert_eq!(Some("b".to_string()), text_content(&p.borrow().first_child().unwrap()));
+
+    set_text_content(&div, "c");
+    assert_eq!(Some("c".to_string()), text_content(&div));
+    assert!(p.borrow().parent().upgrade().is_none());
+    assert_consistent(&div);
+
+    set_text_content(&div, "");
+    assert!(div.borrow().first_child().is_none());
+    assert_consistent(&div);
+
+    let document = Rc::new(RefCell::new(Node::new(NodeKind::Document)));
+    assert_eq!(None, text_content(&document));
+  }
+}
@@ -0,0 +1,289 @@
+use core::cell::RefCell;
+
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::node::{Element, Namespace, Node, NodeKind};
+
+enum Step {
+  Node(Rc<RefCell<Node>>, usize),
+  Content(usize),
+}
+
+fn children(node: &Rc<RefCell<Node>>) -> Vec<Rc<RefCell<Node>>> {
+  let mut children = Vec::new();
+  let mut child = node.borrow().first_child();
+  while let Some(c) = child {
+    child = c.borrow().next_sibling();
+    children.push(c);
+  }
+  children
+}
+
+/// Dumps the tree in the format of the html5lib tree construction tests, one node per line:
+///
+/// ```text
+/// | <p>
+/// |   class="a"
+/// |   "text"
+/// ```
+///
+/// Attributes are sorted by name, and template contents are listed under a `content` line. The
+/// children of a document or fragment start at the outermost level.
+pub fn dump_tree(node: &Rc<RefCell<Node>>) -> String {
+  let mut out = String::new();
+  let mut stack = Vec::new();
+  match node.borrow().kind {
+    NodeKind::Document | NodeKind::DocumentFragment => {
+      stack.extend(children(node).into_iter().rev().map(|c| Step::Node(c, 0)));
+    }
+    _ => stack.push(Step::Node(node.clone(), 0)),
+  }
+
+  while let Some(step) = stack.pop() {
+    let (node, depth) = match step {
+      Step::Node(node, depth) => (node, depth),
+      Step::Content(depth) => {
+        out.push_str(&format!("| {}content\n", "  ".repeat(depth)));
+        continue;
+      }
+    };
+    let indent = "  ".repeat(depth);
+    match node.borrow().kind {
+      NodeKind::Element(ref element) => {
+        let prefix = match element.namespace() {
+          Namespace::Html => "",
+          Namespace::Svg => "svg ",
+          Namespace::MathMl => "math ",
+        };
+        out.push_str(&format!("| {}<{}{}>\n", indent, prefix, element.tag_name()));
+        for (name, value) in sorted_attributes(element) {
+          out.push_str(&format!("| {}  {}=\"{}\"\n", indent, name, value));
+        }
+      }
+      NodeKind::Text(ref s) => out.push_str(&format!("| {}\"{}\"\n", indent, s)),
+      NodeKind::Comment(ref s) => out.push_str(&format!("| {}<!-- {} -->\n", indent, s)),
+      NodeKind::DocumentType { ref name, ref public_id, ref system_id } => {
+        if public_id.is_empty() && system_id.is_empty() {
+          out.push_str(&format!("| {}<!DOCTYPE {}>\n", indent, name));
+        } else {
+          out.push_str(&format!(
+            "| {}<!DOCTYPE {} \"{}\" \"{}\">\n",
+            indent, name, public_id, system_id
+          ));
+        }
+      }
+      NodeKind::Document | NodeKind::DocumentFragment => {}
+    }
+
+    stack.extend(children(&node).into_iter().rev().map(|c| Step::Node(c, depth + 1)));
+    let contents = node.borrow().template_contents();
+    if let Some(contents) = contents {
+      stack.extend(children(&contents).into_iter().rev().map(|c| Step::Node(c, depth + 2)));
+      stack.push(Step::Content(depth + 1));
+    }
+  }
+  out
+}
+
+// Attributes in the xlink, xml and xmlns namespaces are written with a space between the prefix
+// and the local name.
+fn sorted_attributes(element: &Element) -> Vec<(String, String)> {
+  let mut attributes: Vec<(String, String)> = element
+    .attribute_map()
+    .iter()
+    .map(|attr| {
+      let name = match attr.namespace() {
+        Some(namespace) => format!("{} {}", namespace.prefix(), attr.name()),
+        None => attr.name(),
+      };
+      (name, attr.value())
+    })
+    .collect();
+  attributes.sort();
+  attributes
+}
+
+enum JsonStep {
+  Node(Rc<RefCell<Node>>),
+  Raw(&'static str),
+}
+
+/// Exports the tree as JSON. Every node is an object with a `type` (`document`,
+/// `document-fragment`, `doctype`, `element`, `text` or `comment`) and, depending on the type,
+/// `name`, `namespace`, `attributes`, `data`, `publicId`, `systemId`, `content` (the template
+/// contents) and `children`.
+pub fn dump_json(node: &Rc<RefCell<Node>>) -> String {
+  let mut out = String::new();
+  let mut stack = vec![JsonStep::Node(node.clone())];
+  while let Some(step) = stack.pop() {
+    let node = match step {
+      JsonStep::Node(node) => node,
+      JsonStep::Raw(s) => {
+        out.push_str(s);
+        continue;
+      }
+    };
+
+    let mut has_children = true;
+    out.push_str("{\"type\":");
+    match node.borrow().kind {
+      NodeKind::Document => out.push_str("\"document\""),
+      NodeKind::DocumentFragment => out.push_str("\"document-fragment\""),
+      NodeKind::DocumentType { ref name, ref public_id, ref system_id } => {
+        out.push_str("\"doctype\",\"name\":");
+        push_json_string(name, &mut out);
+        out.push_str(",\"publicId\":");
+        push_json_string(public_id, &mut out);
+        out.push_str(",\"systemId\":");
+        push_json_string(system_id, &mut out);
+        has_children = false;
+      }
+      NodeKind::Element(ref element) => {
+        out.push_str("\"element\",\"name\":");
+        push_json_string(&element.tag_name(), &mut out);
+        out.push_str(",\"namespace\":");
+        let namespace = match element.namespace() {
+          Namespace::Html => "html",
+          Namespace::Svg => "svg",
+          Namespace::MathMl => "math",
+        };
+        push_json_string(namespace, &mut out);
+        out.push_str(",\"attributes\":{");
+        for (i, attr) in element.attribute_map().iter().enumerate() {
+          if i > 0 {
+            out.push(',');
+          }
+          push_json_string(&attr.qualified_name(), &mut out);
+          out.push(':');
+          push_json_string(&attr.value(), &mut out);
+        }
+        out.push('}');
+      }
+      NodeKind::Text(ref s) => {
+        out.push_str("\"text\",\"data\":");
+        push_json_string(s, &mut out);
+        has_children = false;
+      }
+      NodeKind::Comment(ref s) => {
+        out.push_str("\"comment\",\"data\":");
+        push_json_string(s, &mut out);
+        has_children = false;
+      }
+    }
+
+    if !has_children {
+      out.push('}');
+      continue;
+    }
+
+    // Pushed in reverse, so that the contents come before the children.
+    stack.push(JsonStep::Raw("]}"));
+    for (i, child) in children(&node).into_iter().enumerate().rev() {
+      stack.push(JsonStep::Node(child));
+      if i > 0 {
+        stack.push(JsonStep::Raw(","));
+      }
+    }
+    stack.push(JsonStep::Raw(",\"children\":["));
+    let contents = node.borrow().template_contents();
+    if let Some(contents) = contents {
+      stack.push(JsonStep::Node(contents));
+      stack.push(JsonStep::Raw(",\"content\":"));
+    }
+  }
+  out
+}
+
+fn push_json_string(s: &str, out: &mut String) {
+  out.push('"');
+  for c in s.chars() {
+    match c {
+      '"' => out.push_str("\\\""),
+      '\\' => out.push_str("\\\\"),
+      '\n' => out.push_str("\\n"),
+      '\r' => out.push_str("\\r"),
+      '\t' => out.push_str("\\t"),
+      c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+      c => out.push(c),
+    }
+  }
+  out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use alloc::string::ToString;
+  use crate::renderer::html::parser::HtmlParser;
+  use crate::renderer::html::token::HtmlTokenizer;
+
+  fn parse(html: &str) -> Rc<RefCell<Node>> {
+    let window = HtmlParser::new(HtmlTokenizer::new(html.to_string())).construct_tree();
+    let document = window.borrow().document();
+    document
+  }
+
+  #[test]
+  fn test_dump_tree() {
+    let document = parse("<!DOCTYPE html><p id=b class=a>x<!--y--></p><svg xlink:href=z></svg>");
+    assert_eq!(
+      "| <!DOCTYPE html>\n\
+       | <html>\n\
+       |   <head>\n\
+       |   <body>\n\
+       |     <p>\n\
+       |       class=\"a\"\n\
+       |       id=\"b\"\n\
+       |       \"x\"\n\
+       |       <!-- y -->\n\
+       |     <svg svg>\n\
+       |       xlink href=\"z\"\n",
+      dump_tree(&document),
+    );
+  }
+
+  #[test]
+  fn test_dump_template() {
+    let document = parse("<template><b>a</b></template>");
+    let head = children(&children(&document)[0])[0].clone();
+    assert_eq!(
+      "| <head>\n|   <template>\n|     content\n|       <b>\n|         \"a\"\n",
+      dump_tree(&head),
+    );
+  }
+
+  #[test]
+  fn test_dump_json() {
+    let document = parse("<!DOCTYPE html><p class=a>\"x\"\n<!--y--></p>");
+    assert_eq!(
+      concat!(
+        r#"{"type":"document","children":["#,
+        r#"{"type":"doctype","name":"html","publicId":"","systemId":""},"#,
+        r#"{"type":"element","name":"html","namespace":"html","attributes":{},"children":["#,
+        r#"{"type":"element","name":"head","namespace":"html","attributes":{},"children":[]},"#,
+        r#"{"type":"element","name":"body","namespace":"html","attributes":{},"children":["#,
+        r#"{"type":"element","name":"p","namespace":"html","attributes":{"class":"a"},"children":["#,
+        r#"{"type":"text","data":"\"x\"\n"},{"type":"comment","data":"y"}]}]}]}]}"#,
+      ),
+      dump_json(&document),
+    );
+  }
+
+  #[test]
+  fn test_dump_json_template() {
+    let document = parse("<template>a</template>");
+    let template = children(&children(&children(&document)[0])[0])[0].clone();
+    assert_eq!(
+      concat!(
+        r#"{"type":"element","name":"template","namespace":"html","attributes":{},"#,
+        r#""content":{"type":"document-fragment","children":[{"type":"text","data":"a"}]},"#,
+        r#""children":[]}"#,
+      ),
+      dump_json(&template),
+    );
+  }
+}
@@ -0,0 +1,482 @@
+use core::cell::{Cell, RefCell};
+use core::fmt;
+
+use alloc::collections::BTreeMap;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::node::{Namespace, Node, NodeKind};
+
+pub type EventCallback = Rc<dyn Fn(&mut Event)>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventPhase {
+  None,
+  Capturing,
+  AtTarget,
+  Bubbling,
+}
+
+#[derive(Debug, Clone)]
+pub struct Event {
+  event_type: String,
+  bubbles: bool,
+  cancelable: bool,
+  target: Option<Rc<RefCell<Node>>>,
+  current_target: Option<Rc<RefCell<Node>>>,
+  phase: EventPhase,
+  stop_propagation: bool,
+  stop_immediate_propagation: bool,
+  canceled: bool,
+}
+
+impl Event {
+  pub fn new(event_type: &str, bubbles: bool, cancelable: bool) -> Self {
+    Self {
+      event_type: String::from(event_type),
+      bubbles,
+      cancelable,
+      target: None,
+      current_target: None,
+      phase: EventPhase::None,
+      stop_propagation: false,
+      stop_immediate_propagation: false,
+      canceled: false,
+    }
+  }
+
+  /// A `click` event, which bubbles and can be canceled.
+  pub fn click() -> Self {
+    Self::new("click", true, true)
+  }
+
+  pub fn event_type(&self) -> String {
+    self.event_type.clone()
+  }
+
+  pub fn bubbles(&self) -> bool {
+    self.bubbles
+  }
+
+  pub fn cancelable(&self) -> bool {
+    self.cancelable
+  }
+
+  pub fn target(&self) -> Option<Rc<RefCell<Node>>> {
+    self.target.clone()
+  }
+
+  pub fn current_target(&self) -> Option<Rc<RefCell<Node>>> {
+    self.current_target.clone()
+  }
+
+  pub fn phase(&self) -> EventPhase {
+    self.phase
+  }
+
+  pub fn stop_propagation(&mut self) {
+    self.stop_propagation = true;
+  }
+
+  /// Like `stop_propagation`, and also skips the remaining listeners on the current target.
+  pub fn stop_immediate_propagation(&mut self) {
+    self.stop_propagation = true;
+    self.stop_immediate_propagation = true;
+  }
+
+  /// Cancels the default action. Has no effect on events that are not cancelable.
+  pub fn prevent_default(&mut self) {
+    if self.cancelable {
+      self.canceled = true;
+    }
+  }
+
+  pub fn default_prevented(&self) -> bool {
+    self.canceled
+  }
+}
+
+pub struct EventListener {
+  callback: EventCallback,
+  capture: bool,
+  // Set when the listener is removed, so that a dispatch which already took a snapshot of the
+  // listeners does not call it anymore.
+  removed: Cell<bool>,
+}
+
+impl fmt::Debug for EventListener {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("EventListener").field("capture", &self.capture).finish()
+  }
+}
+
+/// The event listeners of a node, keyed by event type.
+#[derive(Debug, Clone, Default)]
+pub struct EventTarget {
+  listeners: BTreeMap<String, Vec<Rc<EventListener>>>,
+}
+
+impl EventTarget {
+  pub fn new() -> Self {
+    Self {
+      listeners: BTreeMap::new(),
+    }
+  }
+
+  /// Adds a listener unless the same callback is already registered for this type and phase.
+  pub fn add_event_listener(&mut self, event_type: &str, callback: EventCallback, capture: bool) {
+    let listeners = self.listeners.entry(String::from(event_type)).or_default();
+    if listeners.iter().any(|l| l.capture == capture && Rc::ptr_eq(&l.callback, &callback)) {
+      return;
+    }
+    listeners.push(Rc::new(EventListener {
+      callback,
+      capture,
+      removed: Cell::new(false),
+    }));
+  }
+
+  pub fn remove_event_listener(&mut self, event_type: &str, callback: &EventCallback, capture: bool) {
+    let listeners = match self.listeners.get_mut(event_type) {
+      Some(listeners) => listeners,
+      None => return,
+    };
+    listeners.retain(|l| {
+      let matched = l.capture == capture && Rc::ptr_eq(&l.callback, callback);
+      if matched {
+        l.removed.set(true);
+      }
+      !matched
+    });
+    if listeners.is_empty() {
+      self.listeners.remove(event_type);
+    }
+  }
+
+  pub fn has_event_listeners(&self, event_type: &str) -> bool {
+    self.listeners.contains_key(event_type)
+  }
+
+  fn listeners(&self, event_type: &str) -> Vec<Rc<EventListener>> {
+    self.listeners.get(event_type).cloned().unwrap_or_default()
+  }
+}
+
+pub fn add_event_listener(
+  node: &Rc<RefCell<Node>>,
+  event_type: &str,
+  callback: EventCallback,
+  capture: bool,
+) {
+  node.borrow_mut().event_target_mut().add_event_listener(event_type, callback, capture);
+}
+
+pub fn remove_event_listener(
+  node: &Rc<RefCell<Node>>,
+  event_type: &str,
+  callback: &EventCallback,
+  capture: bool,
+) {
+  node.borrow_mut().event_target_mut().remove_event_listener(event_type, callback, capture);
+}
+
+/// Dispatches `event` to `target`: capture listeners from the root down to the parent of the
+/// target, then the listeners on the target, then, if the event bubbles, non-capture listeners
+/// from the parent up to the root. Returns false if a listener canceled the event.
+pub fn dispatch_event(target: &Rc<RefCell<Node>>, event: &mut Event) -> bool {
+  let mut path = Vec::new();
+  let mut node = Some(target.clone());
+  while let Some(n) = node {
+    node = n.borrow().parent().upgrade();
+    path.push(n);
+  }
+
+  event.target = Some(target.clone());
+  event.stop_propagation = false;
+  event.stop_immediate_propagation = false;
+  event.canceled = false;
+
+  for node in path.iter().skip(1).rev() {
+    invoke(node, event, EventPhase::Capturing);
+    if event.stop_propagation {
+      break;
+    }
+  }
+  if !event.stop_propagation {
+    invoke(target, event, EventPhase::AtTarget);
+  }
+  if event.bubbles {
+    for node in path.iter().skip(1) {
+      if event.stop_propagation {
+        break;
+      }
+      invoke(node, event, EventPhase::Bubbling);
+    }
+  }
+
+  event.phase = EventPhase::None;
+  event.current_target = None;
+  !event.canceled
+}
+
+// Listeners are copied out of the node before they are called, so that a callback can change the
+// tree or the listeners. Listeners added during the dispatch are not called by it.
+fn invoke(node: &Rc<RefCell<Node>>, event: &mut Event, phase: EventPhase) {
+  let listeners = node.borrow().event_target().listeners(&event.event_type);
+  if listeners.is_empty() {
+    return;
+  }
+
+  event.phase = phase;
+  event.current_target = Some(node.clone());
+  // At the target, capture listeners run before the others.
+  let passes: &[bool] = match phase {
+    EventPhase::Capturing => &[true],
+    EventPhase::AtTarget => &[true, false],
+    _ => &[false],
+  };
+  for capture in passes {
+    for listener in listeners.iter().filter(|l| l.capture == *capture) {
+      if listener.removed.get() {
+        continue;
+      }
+      (listener.callback)(event);
+      if event.stop_immediate_propagation {
+        return;
+      }
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DefaultAction {
+  /// Navigate to the `href` of a link, as it is written in the attribute.
+  FollowLink(String),
+}
+
+/// Returns the default action of an event that was dispatched and not canceled, such as following
+/// the link that contains the target of a click.
+pub fn default_action(event: &Event) -> Option<DefaultAction> {
+  if event.canceled || event.event_type != "click" {
+    return None;
+  }
+
+  let mut node = event.target.clone();
+  while let Some(n) = node {
+    if let NodeKind::Element(ref element) = n.borrow().kind {
+      if element.namespace() == Namespace::Html && element.tag_name() == "a" {
+        if let Some(href) = element.get_attribute("href") {
+          return Some(DefaultAction::FollowLink(href));
+        }
+      }
+    }
+    node = n.borrow().parent().upgrade();
+  }
+  None
+}
+
+/// Dispatches a click on `target` and returns the default action unless a listener canceled it.
+pub fn click(target: &Rc<RefCell<Node>>) -> Option<DefaultAction> {
+  let mut event = Event::click();
+  dispatch_event(target, &mut event);
+  default_action(&event)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use alloc::format;
+  use alloc::string::ToString;
+  use alloc::vec;
+  use crate::renderer::dom::query::get_element_by_id;
+  use crate::renderer::html::parser::HtmlParser;
+  use crate::renderer::html::token::HtmlTokenizer;
+
+  fn parse(html: &str) -> Rc<RefCell<Node>> {
+    let window = HtmlParser::new(HtmlTokenizer::new(html.to_string())).construct_tree();
+    let document = window.borrow().document();
+    document
+  }
+
+  fn by_id(document: &Rc<RefCell<Node>>, id: &str) -> Rc<RefCell<Node>> {
+    get_element_by_id(document, id).expect("element should exist")
+  }
+
+  fn name(node: &Option<Rc<RefCell<Node>>>) -> String {
+    match node {
+      Some(n) => match n.borrow().kind {
+        NodeKind::Element(ref e) => e.tag_name(),
+        NodeKind::Document => "#document".to_string(),
+        _ => "?".to_string(),
+      },
+      None => "none".to_string(),
+    }
+  }
+
+  // Adds a listener that records "<label>:<current target>:<phase>" into `log`.
+  fn record(node: &Rc<RefCell<Node>>, log: &Rc<RefCell<Vec<String>>>, label: &str, capture: bool) -> EventCallback {
+    let log = log.clone();
+    let label = label.to_string();
+    let callback: EventCallback = Rc::new(move |event: &mut Event| {
+      log.borrow_mut().push(format!("{}:{}:{:?}", label, name(&event.current_target()), event.phase()));
+    });
+    add_event_listener(node, "click", callback.clone(), capture);
+    callback
+  }
+
+  #[test]
+  fn test_dispatch_phases() {
+    let document = parse("<div id=d><p id=p><b id=b>x</b></p></div>");
+    let div = by_id(&document, "d");
+    let b = by_id(&document, "b");
+    let log = Rc::new(RefCell::new(Vec::new()));
+    record(&document, &log, "document-bubble", false);
+    record(&document, &log, "document-capture", true);
+    record(&div, &log, "div-bubble", false);
+    record(&div, &log, "div-capture", true);
+    record(&b, &log, "b-bubble", false);
+    record(&b, &log, "b-capture", true);
+
+    let mut event = Event::click();
+    assert!(dispatch_event(&b, &mut event));
+    assert_eq!(
+      vec![
+        "document-capture:#document:Capturing",
+        "div-capture:div:Capturing",
+        "b-capture:b:AtTarget",
+        "b-bubble:b:AtTarget",
+        "div-bubble:div:Bubbling",
+        "document-bubble:#document:Bubbling",
+      ],
+      *log.borrow(),
+    );
+    assert_eq!("b", name(&event.target()));
+    assert_eq!(None, event.current_target());
+    assert_eq!(EventPhase::None, event.phase());
+  }
+
+  #[test]
+  fn test_non_bubbling_event() {
+    let document = parse("<p id=p>x</p>");
+    let p = by_id(&document, "p");
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let callback: EventCallback = {
+      let log = log.clone();
+      Rc::new(move |event: &mut Event| log.borrow_mut().push(format!("{:?}", event.phase())))
+    };
+    add_event_listener(&document, "focus", callback.clone(), true);
+    add_event_listener(&document, "focus", callback.clone(), false);
+    add_event_listener(&p, "focus", callback.clone(), false);
+    dispatch_event(&p, &mut Event::new("focus", false, false));
+    assert_eq!(vec!["Capturing", "AtTarget"], *log.borrow());
+  }
+
+  #[test]
+  fn test_stop_propagation() {
+    let document = parse("<div id=d><p id=p>x</p></div>");
+    let div = by_id(&document, "d");
+    let p = by_id(&document, "p");
+    let log = Rc::new(RefCell::new(Vec::new()));
+    record(&document, &log, "document", false);
+    let stop: EventCallback = Rc::new(|event: &mut Event| event.stop_propagation());
+    add_event_listener(&p, "click", stop.clone(), false);
+    record(&p, &log, "p", false);
+    record(&div, &log, "div", false);
+
+    dispatch_event(&p, &mut Event::click());
+    assert_eq!(vec!["p:p:AtTarget"], *log.borrow());
+
+    log.borrow_mut().clear();
+    remove_event_listener(&p, "click", &stop, false);
+    let stop_immediate: EventCallback = Rc::new(|event: &mut Event| event.stop_immediate_propagation());
+    add_event_listener(&div, "click", stop_immediate, true);
+    dispatch_event(&p, &mut Event::click());
+    assert!(log.borrow().is_empty());
+  }
+
+  #[test]
+  fn test_prevent_default() {
+    let document = parse("<a id=a href=/next><b id=b>x</b></a>");
+    let a = by_id(&document, "a");
+    let b = by_id(&document, "b");
+    assert_eq!(Some(DefaultAction::FollowLink("/next".to_string())), click(&b));
+
+    let prevent: EventCallback = Rc::new(|event: &mut Event| event.prevent_default());
+    add_event_listener(&a, "click", prevent.clone(), false);
+    let mut event = Event::click();
+    assert!(!dispatch_event(&b, &mut event));
+    assert!(event.default_prevented());
+    assert_eq!(None, default_action(&event));
+
+    // Events that are not cancelable ignore prevent_default.
+    add_event_listener(&a, "mouseover", prevent, false);
+    let mut event = Event::new("mouseover", true, false);
+    assert!(dispatch_event(&b, &mut event));
+    assert!(!event.default_prevented());
+  }
+
+  #[test]
+  fn test_default_action_needs_href() {
+    let document = parse("<a id=a>x</a><p id=p>y</p>");
+    assert_eq!(None, click(&by_id(&document, "a")));
+    assert_eq!(None, click(&by_id(&document, "p")));
+  }
+
+  #[test]
+  fn test_add_and_remove_listeners() {
+    let document = parse("<p id=p>x</p>");
+    let p = by_id(&document, "p");
+    let count = Rc::new(Cell::new(0));
+    let callback: EventCallback = {
+      let count = count.clone();
+      Rc::new(move |_: &mut Event| count.set(count.get() + 1))
+    };
+    add_event_listener(&p, "click", callback.clone(), false);
+    add_event_listener(&p, "click", callback.clone(), false);
+    add_event_listener(&p, "click", callback.clone(), true);
+    dispatch_event(&p, &mut Event::click());
+    assert_eq!(2, count.get());
+
+    remove_event_listener(&p, "click", &callback, true);
+    dispatch_event(&p, &mut Event::click());
+    assert_eq!(3, count.get());
+
+    remove_event_listener(&p, "click", &callback, false);
+    assert!(!p.borrow().event_target().has_event_listeners("click"));
+    dispatch_event(&p, &mut Event::click());
+    assert_eq!(3, count.get());
+  }
+
+  #[test]
+  fn test_listener_removed_during_dispatch() {
+    let document = parse("<p id=p>x</p>");
+    let p = by_id(&document, "p");
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let second: EventCallback = {
+      let log = log.clone();
+      Rc::new(move |_: &mut Event| log.borrow_mut().push("second"))
+    };
+    let first: EventCallback = {
+      let (p, log, second) = (p.clone(), log.clone(), second.clone());
+      Rc::new(move |_: &mut Event| {
+        log.borrow_mut().push("first");
+        remove_event_listener(&p, "click", &second, false);
+        add_event_listener(&p, "click", Rc::new(|_: &mut Event| panic!("added during dispatch")), false);
+      })
+    };
+    add_event_listener(&p, "click", first, false);
+    add_event_listener(&p, "click", second, false);
+    dispatch_event(&p, &mut Event::click());
+    assert_eq!(vec!["first"], *log.borrow());
+  }
+
+  #[test]
+  fn test_clone_does_not_copy_listeners() {
+    let document = parse("<p id=p>x</p>");
+    let p = by_id(&document, "p");
+    add_event_listener(&p, "click", Rc::new(|_: &mut Event| {}), false);
+    let copy = crate::renderer::dom::api::clone_node(&p, true);
+    assert!(!copy.borrow().event_target().has_event_listeners("click"));
+  }
+}
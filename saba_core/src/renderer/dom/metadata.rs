@@ -0,0 +1,233 @@
+use core::cell::RefCell;
+
+use alloc::{rc::Rc, string::{String, ToString}, vec::Vec};
+
+use super::node::{Element, ElementKind, Node, NodeKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkKind {
+  Stylesheet,
+  Icon,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkResource {
+  kind: LinkKind,
+  href: String,
+}
+
+impl LinkResource {
+  pub fn new(kind: LinkKind, href: String) -> Self {
+    Self { kind, href }
+  }
+
+  pub fn kind(&self) -> LinkKind {
+    self.kind
+  }
+
+  pub fn href(&self) -> String {
+    self.href.clone()
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentMetadata {
+  title: Option<String>,
+  charset: Option<String>,
+  viewport: Option<String>,
+  base_url: Option<String>,
+  links: Vec<LinkResource>,
+}
+
+impl DocumentMetadata {
+  pub fn new(document: &Rc<RefCell<Node>>) -> Self {
+    let mut metadata = Self {
+      title: None,
+      charset: None,
+      viewport: None,
+      base_url: None,
+      links: Vec::new(),
+    };
+
+    let mut stack = match document.borrow().first_child() {
+      Some(child) => alloc::vec![child],
+      None => Vec::new(),
+    };
+    while let Some(node) = stack.pop() {
+      if let Some(next) = node.borrow().next_sibling() {
+        stack.push(next);
+      }
+      if let Some(child) = node.borrow().first_child() {
+        stack.push(child);
+      }
+
+      let element = match node.borrow().kind() {
+        NodeKind::Element(element) => element,
+        _ => continue,
+      };
+      match element.kind() {
+        ElementKind::Title if metadata.title.is_none() => {
+          metadata.title = Some(collapse_whitespace(&child_text(&node)));
+        }
+        ElementKind::Meta => metadata.process_meta(&element),
+        ElementKind::Link => metadata.process_link(&element),
+        ElementKind::Base if metadata.base_url.is_none() => {
+          metadata.base_url = element.get_attribute("href");
+        }
+        _ => {}
+      }
+    }
+
+    metadata
+  }
+
+  fn process_meta(&mut self, element: &Element) {
+    if self.charset.is_none() {
+      if let Some(charset) = element.get_attribute("charset") {
+        self.charset = Some(charset.trim().to_ascii_lowercase());
+      } else if element.get_attribute("http-equiv")
+        .is_some_and(|v| v.eq_ignore_ascii_case("content-type"))
+      {
+        self.charset = element.get_attribute("content").and_then(|c| charset_from_content_type(&c));
+      }
+    }
+
+    if self.viewport.is_none()
+      && element.get_attribute("name").is_some_and(|v| v.eq_ignore_ascii_case("viewport"))
+    {
+      self.viewport = element.get_attribute("content");
+    }
+  }
+
+  fn process_link(&mut self, element: &Element) {
+    let href = match element.get_attribute("href") {
+      Some(href) => href,
+      None => return,
+    };
+    let rel = element.get_attribute("rel").unwrap_or_default();
+    for token in rel.split_ascii_whitespace() {
+      let kind = if token.eq_ignore_ascii_case("stylesheet") {
+        LinkKind::Stylesheet
+      } else if token.eq_ignore_ascii_case("icon") {
+        LinkKind::Icon
+      } else {
+        continue;
+      };
+      self.links.push(LinkResource::new(kind, href.clone()));
+    }
+  }
+
+  pub fn title(&self) -> Option<String> {
+    self.title.clone()
+  }
+
+  pub fn charset(&self) -> Option<String> {
+    self.charset.clone()
+  }
+
+  pub fn viewport(&self) -> Option<String> {
+    self.viewport.clone()
+  }
+
+  pub fn base_url(&self) -> Option<String> {
+    self.base_url.clone()
+  }
+
+  pub fn links(&self) -> Vec<LinkResource> {
+    self.links.clone()
+  }
+
+  pub fn stylesheets(&self) -> Vec<LinkResource> {
+    self.links.iter().filter(|l| l.kind() == LinkKind::Stylesheet).cloned().collect()
+  }
+
+  pub fn icons(&self) -> Vec<LinkResource> {
+    self.links.iter().filter(|l| l.kind() == LinkKind::Icon).cloned().collect()
+  }
+}
+
+fn child_text(node: &Rc<RefCell<Node>>) -> String {
+  let mut text = String::new();
+  let mut child = node.borrow().first_child();
+  while let Some(c) = child {
+    if let NodeKind::Text(ref s) = c.borrow().kind {
+      text.push_str(s);
+    }
+    child = c.borrow().next_sibling();
+  }
+  text
+}
+
+fn collapse_whitespace(s: &str) -> String {
+  s.split_ascii_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn charset_from_content_type(content: &str) -> Option<String> {
+  let lower = content.to_ascii_lowercase();
+  let start = lower.find("charset")? + "charset".len();
+  let rest = lower[start..].trim_start().strip_prefix('=')?.trim_start();
+  let value: String = rest
+    .trim_start_matches(['"', '\''])
+    .chars()
+    .take_while(|c| !c.is_ascii_whitespace() && *c != ';' && *c != '"' && *c != '\'')
+    .collect();
+  if value.is_empty() {
+    return None;
+  }
+  Some(value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::renderer::html::parser::HtmlParser;
+  use crate::renderer::html::token::HtmlTokenizer;
+
+  fn metadata(html: &str) -> DocumentMetadata {
+    let t = HtmlTokenizer::new(html.to_string());
+    let window = HtmlParser::new(t).construct_tree();
+    let document = window.borrow().document();
+    DocumentMetadata::new(&document)
+  }
+
+  #[test]
+  fn test_empty() {
+    let m = metadata("");
+    assert_eq!(None, m.title());
+    assert_eq!(None, m.charset());
+    assert!(m.links().is_empty());
+  }
+
+  #[test]
+  fn test_title() {
+    let m = metadata("<html><head><title>  Hello\n  world </title></head></html>");
+    assert_eq!(Some("Hello world".to_string()), m.title());
+  }
+
+  #[test]
+  fn test_meta() {
+    let m = metadata("<head><meta charset=UTF-8><meta name=viewport content=\"width=device-width\"></head>");
+    assert_eq!(Some("utf-8".to_string()), m.charset());
+    assert_eq!(Some("width=device-width".to_string()), m.viewport());
+
+    let m = metadata("<head><meta http-equiv=Content-Type content=\"text/html; charset=shift_jis\"></head>");
+    assert_eq!(Some("shift_jis".to_string()), m.charset());
+  }
+
+  #[test]
+  fn test_links_and_base() {
+    let m = metadata(
+      "<head><base href=http://example.com/><link rel=stylesheet href=a.css><link rel=\"shortcut icon\" href=f.ico><link rel=preload href=x.js></head>",
+    );
+    assert_eq!(Some("http://example.com/".to_string()), m.base_url());
+    assert_eq!(
+      alloc::vec![
+        LinkResource::new(LinkKind::Stylesheet, "a.css".to_string()),
+        LinkResource::new(LinkKind::Icon, "f.ico".to_string()),
+      ],
+      m.links(),
+    );
+    assert_eq!(1, m.stylesheets().len());
+    assert_eq!(1, m.icons().len());
+  }
+}
@@ -1,2 +1,7 @@
 pub mod node;
-pub mod api;
\ No newline at end of file
+pub mod api;
+pub mod dump;
+pub mod event;
+pub mod metadata;
+pub mod mutation;
+pub mod query;
\ No newline at end of file
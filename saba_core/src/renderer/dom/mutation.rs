@@ -0,0 +1,469 @@
+use core::cell::RefCell;
+use core::mem;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::rc::{Rc, Weak};
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::node::Node;
+
+// https://dom.spec.whatwg.org/#interface-mutationrecord
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MutationType {
+  ChildList,
+  Attributes,
+  CharacterData,
+}
+
+/// A change to the tree. Child list records carry the added and removed nodes and the siblings
+/// around them, attribute records the attribute name, and attribute and character data records
+/// the old value when the observer asked for it.
+#[derive(Debug, Clone)]
+pub struct MutationRecord {
+  mutation_type: MutationType,
+  target: Rc<RefCell<Node>>,
+  added_nodes: Vec<Rc<RefCell<Node>>>,
+  removed_nodes: Vec<Rc<RefCell<Node>>>,
+  previous_sibling: Option<Rc<RefCell<Node>>>,
+  next_sibling: Option<Rc<RefCell<Node>>>,
+  attribute_name: Option<String>,
+  old_value: Option<String>,
+}
+
+impl MutationRecord {
+  pub fn child_list(
+    target: Rc<RefCell<Node>>,
+    added_nodes: Vec<Rc<RefCell<Node>>>,
+    removed_nodes: Vec<Rc<RefCell<Node>>>,
+    previous_sibling: Option<Rc<RefCell<Node>>>,
+    next_sibling: Option<Rc<RefCell<Node>>>,
+  ) -> Self {
+    Self {
+      mutation_type: MutationType::ChildList,
+      target,
+      added_nodes,
+      removed_nodes,
+      previous_sibling,
+      next_sibling,
+      attribute_name: None,
+      old_value: None,
+    }
+  }
+
+  pub fn attributes(target: Rc<RefCell<Node>>, name: String, old_value: Option<String>) -> Self {
+    Self {
+      mutation_type: MutationType::Attributes,
+      target,
+      added_nodes: Vec::new(),
+      removed_nodes: Vec::new(),
+      previous_sibling: None,
+      next_sibling: None,
+      attribute_name: Some(name),
+      old_value,
+    }
+  }
+
+  pub fn character_data(target: Rc<RefCell<Node>>, old_value: String) -> Self {
+    Self {
+      mutation_type: MutationType::CharacterData,
+      target,
+      added_nodes: Vec::new(),
+      removed_nodes: Vec::new(),
+      previous_sibling: None,
+      next_sibling: None,
+      attribute_name: None,
+      old_value: Some(old_value),
+    }
+  }
+
+  pub fn mutation_type(&self) -> MutationType {
+    self.mutation_type
+  }
+
+  pub fn target(&self) -> Rc<RefCell<Node>> {
+    self.target.clone()
+  }
+
+  pub fn added_nodes(&self) -> Vec<Rc<RefCell<Node>>> {
+    self.added_nodes.clone()
+  }
+
+  pub fn removed_nodes(&self) -> Vec<Rc<RefCell<Node>>> {
+    self.removed_nodes.clone()
+  }
+
+  pub fn previous_sibling(&self) -> Option<Rc<RefCell<Node>>> {
+    self.previous_sibling.clone()
+  }
+
+  pub fn next_sibling(&self) -> Option<Rc<RefCell<Node>>> {
+    self.next_sibling.clone()
+  }
+
+  pub fn attribute_name(&self) -> Option<String> {
+    self.attribute_name.clone()
+  }
+
+  pub fn old_value(&self) -> Option<String> {
+    self.old_value.clone()
+  }
+}
+
+// https://dom.spec.whatwg.org/#dictdef-mutationobserverinit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MutationObserverInit {
+  pub child_list: bool,
+  pub attributes: bool,
+  pub character_data: bool,
+  pub subtree: bool,
+  pub attribute_old_value: bool,
+  pub character_data_old_value: bool,
+}
+
+type RecordQueue = Rc<RefCell<Vec<MutationRecord>>>;
+
+// The number of observer registrations on any node, added by `observe` and removed by `disconnect`.
+// Registrations that outlive their node or observer are not subtracted, so this is an upper bound.
+// While it is zero, mutations skip looking for observers.
+static REGISTRATIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// An observer registered on a node. The observer owns its record queue, so a registration
+/// outliving the observer is skipped.
+#[derive(Debug, Clone)]
+pub struct RegisteredObserver {
+  records: Weak<RefCell<Vec<MutationRecord>>>,
+  options: MutationObserverInit,
+}
+
+/// Collects mutation records for the nodes it observes until they are taken. There is no event
+/// loop to deliver them, so callers poll with `take_records`.
+#[derive(Debug, Clone, Default)]
+pub struct MutationObserver {
+  records: RecordQueue,
+  targets: Rc<RefCell<Vec<Weak<RefCell<Node>>>>>,
+}
+
+impl MutationObserver {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Starts observing `target`, or replaces the options if it is observed already. Asking for an
+  /// old value implies observing that type of mutation.
+  pub fn observe(&self, target: &Rc<RefCell<Node>>, options: MutationObserverInit) {
+    let mut options = options;
+    options.attributes |= options.attribute_old_value;
+    options.character_data |= options.character_data_old_value;
+
+    let mut node = target.borrow_mut();
+    let registered = node.registered_observers_mut();
+    match registered.iter_mut().find(|r| Weak::ptr_eq(&r.records, &Rc::downgrade(&self.records))) {
+      Some(r) => r.options = options,
+      None => {
+        registered.push(RegisteredObserver {
+          records: Rc::downgrade(&self.records),
+          options,
+        });
+        self.targets.borrow_mut().push(Rc::downgrade(target));
+        REGISTRATIONS.fetch_add(1, Ordering::Relaxed);
+      }
+    }
+  }
+
+  /// Stops observing every node and drops the pending records.
+  pub fn disconnect(&self) {
+    for target in self.targets.borrow_mut().drain(..) {
+      if let Some(target) = target.upgrade() {
+        let mut target = target.borrow_mut();
+        let registered = target.registered_observers_mut();
+        let len = registered.len();
+        registered.retain(|r| !Weak::ptr_eq(&r.records, &Rc::downgrade(&self.records)));
+        REGISTRATIONS.fetch_sub(len - registered.len(), Ordering::Relaxed);
+      }
+    }
+    self.records.borrow_mut().clear();
+  }
+
+  pub fn take_records(&self) -> Vec<MutationRecord> {
+    mem::take(&mut *self.records.borrow_mut())
+  }
+}
+
+/// Queues the record built by `record` for every observer of `target`, or of an ancestor with
+/// `subtree`, that is interested in `mutation_type`. The record is only built when there is one.
+// https://dom.spec.whatwg.org/#queueing-a-mutation-record
+pub fn queue_mutation_record(
+  target: &Rc<RefCell<Node>>,
+  mutation_type: MutationType,
+  record: impl FnOnce() -> MutationRecord,
+) {
+  if REGISTRATIONS.load(Ordering::Relaxed) == 0 {
+    return;
+  }
+
+  // Each interested observer with whether it wants the old value.
+  let mut interested: Vec<(RecordQueue, bool)> = Vec::new();
+  let mut node = Some(target.clone());
+  while let Some(n) = node {
+    for registered in n.borrow().registered_observers() {
+      let options = registered.options;
+      if !Rc::ptr_eq(&n, target) && !options.subtree {
+        continue;
+      }
+      let (observed, old_value) = match mutation_type {
+        MutationType::ChildList => (options.child_list, false),
+        MutationType::Attributes => (options.attributes, options.attribute_old_value),
+        MutationType::CharacterData => (options.character_data, options.character_data_old_value),
+      };
+      if !observed {
+        continue;
+      }
+      if let Some(records) = registered.records.upgrade() {
+        match interested.iter_mut().find(|(r, _)| Rc::ptr_eq(r, &records)) {
+          Some(entry) => entry.1 |= old_value,
+          None => interested.push((records, old_value)),
+        }
+      }
+    }
+    node = n.borrow().parent().upgrade();
+  }
+
+  if interested.is_empty() {
+    return;
+  }
+  let record = record();
+  for (records, old_value) in interested {
+    let mut record = record.clone();
+    if !old_value {
+      record.old_value = None;
+    }
+    records.borrow_mut().push(record);
+  }
+}
+
+/// What has to be recomputed for a node since the flags were last cleared.
+///
+/// `style` means the node's own style changed, which rebuilds its layout objects. `child_style`
+/// means a child was added, removed or restyled, so the layout objects of the children are
+/// rebuilt. `descendant_style` is set on the ancestors of such a node, so a walk can skip clean
+/// subtrees, and `layout` is set on the node and its ancestors when sizes or positions may change.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DirtyFlags {
+  pub style: bool,
+  pub child_style: bool,
+  pub descendant_style: bool,
+  pub layout: bool,
+}
+
+impl DirtyFlags {
+  pub fn is_dirty(&self) -> bool {
+    self.style || self.child_style || self.descendant_style || self.layout
+  }
+}
+
+pub fn mark_style_dirty(node: &Rc<RefCell<Node>>) {
+  node.borrow_mut().dirty_flags_mut().style = true;
+  let parent = node.borrow().parent().upgrade();
+  match parent {
+    Some(parent) => mark_child_list_dirty(&parent),
+    None => mark_layout_dirty(node),
+  }
+}
+
+/// Marks the children of `node` for rebuilding after one of them was added, removed or restyled.
+pub fn mark_child_list_dirty(node: &Rc<RefCell<Node>>) {
+  node.borrow_mut().dirty_flags_mut().child_style = true;
+  let mut ancestor = Some(node.clone());
+  while let Some(a) = ancestor {
+    a.borrow_mut().dirty_flags_mut().descendant_style = true;
+    ancestor = a.borrow().parent().upgrade();
+  }
+  mark_layout_dirty(node);
+}
+
+pub fn mark_layout_dirty(node: &Rc<RefCell<Node>>) {
+  let mut node = Some(node.clone());
+  while let Some(n) = node {
+    n.borrow_mut().dirty_flags_mut().layout = true;
+    node = n.borrow().parent().upgrade();
+  }
+}
+
+/// Clears the flags of `node` and of its descendants, skipping subtrees under clean nodes.
+pub fn clear_dirty_flags(node: &Rc<RefCell<Node>>) {
+  let mut stack = vec![node.clone()];
+  while let Some(n) = stack.pop() {
+    let flags = mem::take(n.borrow_mut().dirty_flags_mut());
+    if !flags.is_dirty() {
+      continue;
+    }
+    let mut child = n.borrow().first_child();
+    while let Some(c) = child {
+      child = c.borrow().next_sibling();
+      stack.push(c);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::renderer::dom::api::{
+    append_child, insert_before, remove_child, remove_attribute, replace_child,
+    create_document_fragment, set_attribute, set_text_content,
+  };
+  use crate::renderer::dom::node::{Element, NodeKind};
+  use alloc::string::ToString;
+
+  fn element(name: &str) -> Rc<RefCell<Node>> {
+    Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(name, Vec::new())))))
+  }
+
+  fn text(data: &str) -> Rc<RefCell<Node>> {
+    Rc::new(RefCell::new(Node::new(NodeKind::Text(data.to_string()))))
+  }
+
+  fn all() -> MutationObserverInit {
+    MutationObserverInit {
+      child_list: true,
+      attributes: true,
+      character_data: true,
+      subtree: true,
+      attribute_old_value: true,
+      character_data_old_value: true,
+    }
+  }
+
+  fn same(a: &[Rc<RefCell<Node>>], b: &[&Rc<RefCell<Node>>]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(x, y)| Rc::ptr_eq(x, y))
+  }
+
+  #[test]
+  fn test_child_list_records() {
+    let parent = element("div");
+    let a = element("a");
+    let b = element("b");
+    let observer = MutationObserver::new();
+    observer.observe(&parent, MutationObserverInit { child_list: true, ..Default::default() });
+
+    append_child(&parent, &b).unwrap();
+    insert_before(&parent, &a, Some(&b)).unwrap();
+    remove_child(&parent, &b).unwrap();
+
+    let records = observer.take_records();
+    assert_eq!(3, records.len());
+    assert!(records.iter().all(|r| r.mutation_type() == MutationType::ChildList));
+    assert!(records.iter().all(|r| Rc::ptr_eq(&r.target(), &parent)));
+    assert!(same(&records[0].added_nodes(), &[&b]));
+    assert!(records[0].previous_sibling().is_none() && records[0].next_sibling().is_none());
+    assert!(same(&records[1].added_nodes(), &[&a]));
+    assert!(Rc::ptr_eq(&records[1].next_sibling().unwrap(), &b));
+    assert!(same(&records[2].removed_nodes(), &[&b]));
+    assert!(Rc::ptr_eq(&records[2].previous_sibling().unwrap(), &a));
+    assert!(observer.take_records().is_empty());
+  }
+
+  #[test]
+  fn test_fragment_and_replace_records() {
+    let parent = element("div");
+    let old = element("p");
+    append_child(&parent, &old).unwrap();
+    let fragment = create_document_fragment();
+    let a = element("a");
+    let b = element("b");
+    append_child(&fragment, &a).unwrap();
+    append_child(&fragment, &b).unwrap();
+
+    let observer = MutationObserver::new();
+    observer.observe(&parent, MutationObserverInit { child_list: true, ..Default::default() });
+    observer.observe(&fragment, MutationObserverInit { child_list: true, ..Default::default() });
+    replace_child(&parent, &fragment, &old).unwrap();
+
+    let records = observer.take_records();
+    assert_eq!(3, records.len());
+    assert!(same(&records[0].removed_nodes(), &[&old]));
+    assert!(Rc::ptr_eq(&records[1].target(), &fragment));
+    assert!(same(&records[1].removed_nodes(), &[&a, &b]));
+    assert!(same(&records[2].added_nodes(), &[&a, &b]));
+  }
+
+  #[test]
+  fn test_attribute_and_character_data_records() {
+    let parent = element("div");
+    let child = element("p");
+    let data = text("a");
+    append_child(&parent, &child).unwrap();
+    append_child(&child, &data).unwrap();
+
+    let observer = MutationObserver::new();
+    observer.observe(&parent, all());
+    set_attribute(&child, "class", "x").unwrap();
+    set_attribute(&child, "class", "y").unwrap();
+    remove_attribute(&child, "class").unwrap();
+    set_text_content(&data, "b");
+
+    let records = observer.take_records();
+    assert_eq!(4, records.len());
+    assert_eq!(MutationType::Attributes, records[0].mutation_type());
+    assert_eq!(Some("class".to_string()), records[0].attribute_name());
+    assert_eq!(None, records[0].old_value());
+    assert_eq!(Some("x".to_string()), records[1].old_value());
+    assert_eq!(Some("y".to_string()), records[2].old_value());
+    assert_eq!(MutationType::CharacterData, records[3].mutation_type());
+    assert!(Rc::ptr_eq(&records[3].target(), &data));
+    assert_eq!(Some("a".to_string()), records[3].old_value());
+  }
+
+  #[test]
+  fn test_observer_options() {
+    let parent = element("div");
+    let child = element("p");
+    append_child(&parent, &child).unwrap();
+
+    let observer = MutationObserver::new();
+    observer.observe(&parent, MutationObserverInit { attributes: true, ..Default::default() });
+    set_attribute(&child, "id", "x").unwrap();
+    append_child(&parent, &text("a")).unwrap();
+    set_attribute(&parent, "id", "y").unwrap();
+    let records = observer.take_records();
+    assert_eq!(1, records.len());
+    assert!(Rc::ptr_eq(&records[0].target(), &parent));
+    assert_eq!(None, records[0].old_value());
+
+    observer.disconnect();
+    set_attribute(&parent, "id", "z").unwrap();
+    assert!(observer.take_records().is_empty());
+    assert!(parent.borrow().registered_observers().is_empty());
+  }
+
+  #[test]
+  fn test_dirty_flags() {
+    let document = Rc::new(RefCell::new(Node::new(NodeKind::Document)));
+    let body = element("body");
+    let p = element("p");
+    let data = text("a");
+    append_child(&document, &body).unwrap();
+    append_child(&body, &p).unwrap();
+    append_child(&p, &data).unwrap();
+    assert!(body.borrow().dirty_flags().child_style);
+    assert!(document.borrow().dirty_flags().descendant_style);
+
+    clear_dirty_flags(&document);
+    for node in [&document, &body, &p, &data] {
+      assert!(!node.borrow().dirty_flags().is_dirty());
+    }
+
+    set_text_content(&data, "b");
+    assert!(data.borrow().dirty_flags().layout);
+    assert!(document.borrow().dirty_flags().layout);
+    assert!(!document.borrow().dirty_flags().descendant_style);
+
+    set_attribute(&p, "class", "x").unwrap();
+    assert!(p.borrow().dirty_flags().style);
+    assert!(body.borrow().dirty_flags().child_style);
+    assert!(!p.borrow().dirty_flags().child_style);
+    assert!(document.borrow().dirty_flags().descendant_style);
+  }
+}
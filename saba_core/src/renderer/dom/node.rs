@@ -5,9 +5,14 @@ use core::fmt::Display;
 use core::fmt::Formatter;
 use core::str::FromStr;
 use alloc::string::String;
-use crate::renderer::html::attribute::Attribute;
+use crate::renderer::dom::event::EventTarget;
+use crate::renderer::dom::mutation::{DirtyFlags, RegisteredObserver};
+use crate::renderer::dom::query::IdIndex;
+use crate::renderer::html::attribute::{Attribute, AttributeMap};
+use alloc::collections::BTreeSet;
 use alloc::vec::Vec;
 use alloc::format;
+use alloc::string::ToString;
 
 #[derive(Debug, Clone)]
 pub struct Node {
@@ -18,6 +23,31 @@ pub struct Node {
   last_child: Weak<RefCell<Node>>,
   previous_sibling: Weak<RefCell<Node>>,
   next_sibling: Option<Rc<RefCell<Node>>>,
+  template_contents: Option<Rc<RefCell<Node>>>,
+  id_index: Option<IdIndex>,
+  event_target: EventTarget,
+  registered_observers: Vec<RegisteredObserver>,
+  dirty_flags: DirtyFlags,
+}
+
+// The default drop glue recurses through first_child and next_sibling, which overflows the stack
+// on long sibling lists or deep trees. Nodes that are not shared elsewhere are unlinked onto a
+// stack and dropped one at a time instead.
+impl Drop for Node {
+  fn drop(&mut self) {
+    let mut stack = Vec::new();
+    stack.extend(self.first_child.take());
+    stack.extend(self.next_sibling.take());
+    stack.extend(self.template_contents.take());
+    while let Some(node) = stack.pop() {
+      if let Ok(node) = Rc::try_unwrap(node) {
+        let mut node = node.into_inner();
+        stack.extend(node.first_child.take());
+        stack.extend(node.next_sibling.take());
+        stack.extend(node.template_contents.take());
+      }
+    }
+  }
 }
 
 impl PartialEq for Node {
@@ -36,6 +66,11 @@ impl Node {
       last_child: Weak::new(),
       previous_sibling: Weak::new(),
       next_sibling: None,
+      template_contents: None,
+      id_index: None,
+      event_target: EventTarget::new(),
+      registered_observers: Vec::new(),
+      dirty_flags: DirtyFlags::default(),
     }
   }
 
@@ -79,6 +114,50 @@ impl Node {
     self.next_sibling.as_ref().cloned()
   }
 
+  pub fn set_template_contents(&mut self, template_contents: Option<Rc<RefCell<Node>>>) {
+    self.template_contents = template_contents;
+  }
+
+  pub fn template_contents(&self) -> Option<Rc<RefCell<Node>>> {
+    self.template_contents.as_ref().cloned()
+  }
+
+  pub fn set_id_index(&mut self, id_index: Option<IdIndex>) {
+    self.id_index = id_index;
+  }
+
+  pub fn id_index(&self) -> Option<&IdIndex> {
+    self.id_index.as_ref()
+  }
+
+  pub fn id_index_mut(&mut self) -> Option<&mut IdIndex> {
+    self.id_index.as_mut()
+  }
+
+  pub fn event_target(&self) -> &EventTarget {
+    &self.event_target
+  }
+
+  pub fn event_target_mut(&mut self) -> &mut EventTarget {
+    &mut self.event_target
+  }
+
+  pub fn registered_observers(&self) -> &[RegisteredObserver] {
+    &self.registered_observers
+  }
+
+  pub fn registered_observers_mut(&mut self) -> &mut Vec<RegisteredObserver> {
+    &mut self.registered_observers
+  }
+
+  pub fn dirty_flags(&self) -> DirtyFlags {
+    self.dirty_flags
+  }
+
+  pub fn dirty_flags_mut(&mut self) -> &mut DirtyFlags {
+    &mut self.dirty_flags
+  }
+
   pub fn set_window(&mut self, window: Weak<RefCell<Window>>) {
     self.window = window;
   }
@@ -89,15 +168,15 @@ impl Node {
 
   pub fn get_element(&self) -> Option<Element> {
     match self.kind {
-      NodeKind::Document | NodeKind::Text(_) => None,
       NodeKind::Element(ref element) => Some(element.clone()),
+      _ => None,
     }
   }
 
   pub fn element_kind(&self) -> Option<ElementKind> {
     match self.kind {
-      NodeKind::Document | NodeKind::Text(_) => None,
       NodeKind::Element(ref element) => Some(element.kind()),
+      _ => None,
     }
   }
 }
@@ -105,19 +184,34 @@ impl Node {
 #[derive(Debug, Clone, Eq)]
 pub enum NodeKind {
   Document,
+  DocumentFragment,
+  DocumentType {
+    name: String,
+    public_id: String,
+    system_id: String,
+  },
   Element(Element),
   Text(String),
+  Comment(String),
 }
 
 impl PartialEq for NodeKind {
   fn eq(&self, other: &Self) -> bool {
     match &self {
       NodeKind::Document => matches!(other, NodeKind::Document),
+      NodeKind::DocumentFragment => matches!(other, NodeKind::DocumentFragment),
       NodeKind::Element(e1) => match &other {
         NodeKind::Element(e2) => e1 == e2,
         _ => false,
       }
-      NodeKind::Text(_) => matches!(other, NodeKind::Text(_))
+      NodeKind::DocumentType { name: n1, public_id: p1, system_id: s1 } => match &other {
+        NodeKind::DocumentType { name: n2, public_id: p2, system_id: s2 } => {
+          n1 == n2 && p1 == p2 && s1 == s2
+        }
+        _ => false,
+      }
+      NodeKind::Text(_) => matches!(other, NodeKind::Text(_)),
+      NodeKind::Comment(_) => matches!(other, NodeKind::Comment(_)),
     }
   }
 }
@@ -145,30 +239,101 @@ impl Window {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Element {
   kind: ElementKind,
-  attributes: Vec<Attribute>,
+  namespace: Namespace,
+  tag_name: String,
+  attributes: AttributeMap,
 }
 
 impl Element {
   pub fn new(element_name: &str, attributes: Vec<Attribute>) -> Self {
+    Self::new_with_namespace(element_name, attributes, Namespace::Html)
+  }
+
+  pub fn new_with_namespace(element_name: &str, attributes: Vec<Attribute>, namespace: Namespace) -> Self {
+    let kind = match (namespace, element_name) {
+      (Namespace::Html, _) => ElementKind::from_str(element_name).unwrap_or(ElementKind::Unknown),
+      (Namespace::Svg, "svg") => ElementKind::Svg,
+      (Namespace::MathMl, "math") => ElementKind::Math,
+      _ => ElementKind::Unknown,
+    };
     Self {
-      kind: ElementKind::from_str(element_name).expect("failed to parse ElementKind"),
-      attributes,
+      kind,
+      namespace,
+      tag_name: element_name.to_string(),
+      attributes: AttributeMap::from_attributes(attributes),
     }
   }
 
   pub fn kind(&self) -> ElementKind {
-    self.kind.clone()
+    self.kind
   }
 
-  pub fn is_block_element(&self) -> bool {
-    match self.kind {
-      ElementKind::Body | ElementKind::P | ElementKind::H1 | ElementKind::H2 => true,
-      _ => false,
-    }
+  pub fn namespace(&self) -> Namespace {
+    self.namespace
+  }
+
+  pub fn tag_name(&self) -> String {
+    self.tag_name.clone()
   }
 
   pub fn attributes(&self) -> Vec<Attribute> {
-    self.attributes.clone()
+    self.attributes.to_vec()
+  }
+
+  pub fn attribute_map(&self) -> &AttributeMap {
+    &self.attributes
+  }
+
+  pub fn get_attribute(&self, name: &str) -> Option<String> {
+    self.attributes.get(name)
+  }
+
+  pub fn has_attribute(&self, name: &str) -> bool {
+    self.attributes.contains(name)
+  }
+
+  pub fn set_attribute(&mut self, name: &str, value: &str) {
+    self.attributes.set(name, value);
+  }
+
+  pub fn remove_attribute(&mut self, name: &str) -> bool {
+    self.attributes.remove(name)
+  }
+
+  pub fn id(&self) -> Option<String> {
+    self.get_attribute("id")
+  }
+
+  fn classes(&self) -> impl Iterator<Item = &str> {
+    self.attributes.value("class").unwrap_or_default().split_ascii_whitespace()
+  }
+
+  /// The space-separated tokens of the `class` attribute, without duplicates.
+  pub fn class_list(&self) -> Vec<String> {
+    let mut seen = BTreeSet::new();
+    self.classes().filter(|class| seen.insert(*class)).map(String::from).collect()
+  }
+
+  /// Whether `name` is one of the tokens of the `class` attribute.
+  pub fn has_class(&self, name: &str) -> bool {
+    self.classes().any(|class| class == name)
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Namespace {
+  Html,
+  Svg,
+  MathMl,
+}
+
+impl Namespace {
+  pub fn url(&self) -> &'static str {
+    match self {
+      Namespace::Html => "http://www.w3.org/1999/xhtml",
+      Namespace::Svg => "http://www.w3.org/2000/svg",
+      Namespace::MathMl => "http://www.w3.org/1998/Math/MathML",
+    }
   }
 }
 
@@ -176,6 +341,10 @@ impl Element {
 pub enum ElementKind {
   Html,
   Head,
+  Title,
+  Meta,
+  Link,
+  Base,
   Style,
   Script,
   Body,
@@ -183,6 +352,10 @@ pub enum ElementKind {
   H1,
   H2,
   A,
+  Template,
+  Svg,
+  Math,
+  Unknown,
 }
 
 impl FromStr for ElementKind {
@@ -192,6 +365,10 @@ impl FromStr for ElementKind {
     match s {
       "html" => Ok(ElementKind::Html),
       "head" => Ok(ElementKind::Head),
+      "title" => Ok(ElementKind::Title),
+      "meta" => Ok(ElementKind::Meta),
+      "link" => Ok(ElementKind::Link),
+      "base" => Ok(ElementKind::Base),
       "style" => Ok(ElementKind::Style),
       "script" => Ok(ElementKind::Script),
       "body" => Ok(ElementKind::Body),
@@ -199,6 +376,7 @@ impl FromStr for ElementKind {
       "h1" => Ok(ElementKind::H1),
       "h2" => Ok(ElementKind::H2),
       "a" => Ok(ElementKind::A),
+      "template" => Ok(ElementKind::Template),
       _ => Err(format!("failed to parse ElementKind: {:?}", s)),
     }
   }
@@ -209,6 +387,10 @@ impl Display for ElementKind {
     let s = match self {
       ElementKind::Html => "html",
       ElementKind::Head => "head",
+      ElementKind::Title => "title",
+      ElementKind::Meta => "meta",
+      ElementKind::Link => "link",
+      ElementKind::Base => "base",
       ElementKind::Style => "style",
       ElementKind::Script => "script",
       ElementKind::Body => "body",
@@ -216,7 +398,38 @@ impl Display for ElementKind {
       ElementKind::H1 => "h1",
       ElementKind::H2 => "h2",
       ElementKind::A => "a",
+      ElementKind::Template => "template",
+      ElementKind::Svg => "svg",
+      ElementKind::Math => "math",
+      ElementKind::Unknown => "unknown",
     };
     write!(f, "{}", s)
   }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use alloc::vec;
+
+  #[test]
+  fn test_element_attributes() {
+    let mut element = Element::new("p", Vec::new());
+    assert!(!element.has_attribute("class"));
+    assert_eq!(None, element.id());
+    assert!(element.class_list().is_empty());
+
+    element.set_attribute("class", " a\tb  a\nc ");
+    element.set_attribute("id", "x");
+    assert!(element.has_attribute("class"));
+    assert_eq!(Some("x".to_string()), element.get_attribute("id"));
+    assert_eq!(Some("x".to_string()), element.id());
+    assert_eq!(vec!["a", "b", "c"], element.class_list());
+    assert!(element.has_class("b"));
+    assert!(!element.has_class("d"));
+
+    assert!(element.remove_attribute("class"));
+    assert!(element.class_list().is_empty());
+    assert!(!element.has_class("a"));
+  }
+}
@@ -0,0 +1,336 @@
+use core::cell::RefCell;
+
+use alloc::{collections::BTreeMap, rc::{Rc, Weak}, string::{String, ToString}, vec::Vec};
+
+use super::node::{Namespace, Node, NodeKind};
+use crate::error::Error;
+use crate::renderer::css::selector::{matches_selector_list, parse_selector_list};
+
+/// Iterates over the descendants of a node in tree order, not including the node itself.
+#[derive(Debug, Clone)]
+pub struct Descendants {
+  stack: Vec<Rc<RefCell<Node>>>,
+}
+
+impl Iterator for Descendants {
+  type Item = Rc<RefCell<Node>>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let node = self.stack.pop()?;
+    if let Some(next) = node.borrow().next_sibling() {
+      self.stack.push(next);
+    }
+    if let Some(child) = node.borrow().first_child() {
+      self.stack.push(child);
+    }
+    Some(node)
+  }
+}
+
+pub fn descendants(root: &Rc<RefCell<Node>>) -> Descendants {
+  Descendants {
+    stack: root.borrow().first_child().into_iter().collect(),
+  }
+}
+
+pub fn elements(root: &Rc<RefCell<Node>>) -> impl Iterator<Item = Rc<RefCell<Node>>> {
+  descendants(root).filter(|node| matches!(node.borrow().kind, NodeKind::Element(_)))
+}
+
+pub fn get_elements_by_tag_name(
+  root: &Rc<RefCell<Node>>,
+  name: &str,
+) -> impl Iterator<Item = Rc<RefCell<Node>>> {
+  let name = name.to_string();
+  let lower = name.to_ascii_lowercase();
+  elements(root).filter(move |node| match node.borrow().kind {
+    NodeKind::Element(ref element) => {
+      // HTML elements are matched case-insensitively, foreign elements exactly.
+      name == "*"
+        || match element.namespace() {
+          Namespace::Html => element.tag_name() == lower,
+          _ => element.tag_name() == name,
+        }
+    }
+    _ => false,
+  })
+}
+
+pub fn get_elements_by_class_name(
+  root: &Rc<RefCell<Node>>,
+  names: &str,
+) -> impl Iterator<Item = Rc<RefCell<Node>>> {
+  let names: Vec<String> = names.split_ascii_whitespace().map(String::from).collect();
+  elements(root).filter(move |node| match node.borrow().kind {
+    NodeKind::Element(ref element) => {
+      !names.is_empty() && names.iter().all(|name| element.has_class(name))
+    }
+    _ => false,
+  })
+}
+
+/// Matches elements that have an attribute called `name`, and whose value equals `value` when one
+/// is given.
+pub fn get_elements_by_attribute(
+  root: &Rc<RefCell<Node>>,
+  name: &str,
+  value: Option<&str>,
+) -> impl Iterator<Item = Rc<RefCell<Node>>> {
+  let name = name.to_string();
+  let value = value.map(String::from);
+  elements(root).filter(move |node| match node.borrow().kind {
+    NodeKind::Element(ref element) => match element.get_attribute(&name) {
+      Some(v) => match value {
+        Some(ref value) => *value == v,
+        None => true,
+      },
+      None => false,
+    },
+    _ => false,
+  })
+}
+
+/// Returns the first element in tree order whose id is `id`. The id index is used when `root` is a
+/// document that has one.
+pub fn get_element_by_id(root: &Rc<RefCell<Node>>, id: &str) -> Option<Rc<RefCell<Node>>> {
+  if let Some(index) = root.borrow().id_index() {
+    let nodes = index.get(id);
+    match nodes.len() {
+      0 => return None,
+      1 => return nodes.into_iter().next(),
+      // Several elements share the id, so tree order has to decide.
+      _ => {}
+    }
+  }
+  elements(root).find(|node| element_id(node).is_some_and(|v| v == id))
+}
+
+/// Returns the elements under `root` that match the selector list `selectors`, in tree order.
+/// Combinators may reach ancestors of `root`, as with `Element.querySelectorAll`.
+pub fn query_selector_all(
+  root: &Rc<RefCell<Node>>,
+  selectors: &str,
+) -> Result<impl Iterator<Item = Rc<RefCell<Node>>>, Error> {
+  let selectors = parse_selector_list(selectors)?;
+  Ok(elements(root).filter(move |node| matches_selector_list(&selectors, node)))
+}
+
+pub fn query_selector(root: &Rc<RefCell<Node>>, selectors: &str) -> Result<Option<Rc<RefCell<Node>>>, Error> {
+  Ok(query_selector_all(root, selectors)?.next())
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct IdIndex {
+  ids: BTreeMap<String, Vec<Weak<RefCell<Node>>>>,
+}
+
+impl IdIndex {
+  pub fn get(&self, id: &str) -> Vec<Rc<RefCell<Node>>> {
+    match self.ids.get(id) {
+      Some(nodes) => nodes.iter().filter_map(|node| node.upgrade()).collect(),
+      None => Vec::new(),
+    }
+  }
+
+  fn insert(&mut self, id: String, node: &Rc<RefCell<Node>>) {
+    self.ids.entry(id).or_default().push(Rc::downgrade(node));
+  }
+
+  fn remove(&mut self, id: &str, node: &Rc<RefCell<Node>>) {
+    if let Some(nodes) = self.ids.get_mut(id) {
+      nodes.retain(|n| n.upgrade().is_some_and(|n| !Rc::ptr_eq(&n, node)));
+      if nodes.is_empty() {
+        self.ids.remove(id);
+      }
+    }
+  }
+}
+
+/// Builds an id index for `document`, which is kept up to date as nodes are inserted and detached
+/// through `dom::api`.
+pub fn enable_id_index(document: &Rc<RefCell<Node>>) {
+  let mut index = IdIndex::default();
+  for node in descendants(document) {
+    if let Some(id) = element_id(&node) {
+      index.insert(id, &node);
+    }
+  }
+  document.borrow_mut().set_id_index(Some(index));
+}
+
+pub fn disable_id_index(document: &Rc<RefCell<Node>>) {
+  document.borrow_mut().set_id_index(None);
+}
+
+/// Adds `node` and its descendants to the id index of the document they are connected to.
+pub fn index_ids(node: &Rc<RefCell<Node>>) {
+  update_id_index(node, true, |index, id, node| index.insert(id, node));
+}
+
+/// Removes `node` and its descendants from the id index of the document they are connected to.
+pub fn unindex_ids(node: &Rc<RefCell<Node>>) {
+  update_id_index(node, true, |index, id, node| index.remove(&id, node));
+}
+
+/// Like `index_ids`, but for `node` alone. Used when its id attribute changes.
+pub fn index_id(node: &Rc<RefCell<Node>>) {
+  update_id_index(node, false, |index, id, node| index.insert(id, node));
+}
+
+pub fn unindex_id(node: &Rc<RefCell<Node>>) {
+  update_id_index(node, false, |index, id, node| index.remove(&id, node));
+}
+
+fn update_id_index(
+  node: &Rc<RefCell<Node>>,
+  deep: bool,
+  update: impl Fn(&mut IdIndex, String, &Rc<RefCell<Node>>),
+) {
+  let root = root_of(node);
+  if root.borrow().id_index().is_none() {
+    return;
+  }
+
+  let mut nodes = alloc::vec![node.clone()];
+  if deep {
+    nodes.extend(descendants(node));
+  }
+  let entries: Vec<(String, Rc<RefCell<Node>>)> =
+    nodes.into_iter().filter_map(|n| element_id(&n).map(|id| (id, n))).collect();
+  let mut root = root.borrow_mut();
+  if let Some(index) = root.id_index_mut() {
+    for (id, n) in entries {
+      update(index, id, &n);
+    }
+  }
+}
+
+fn root_of(node: &Rc<RefCell<Node>>) -> Rc<RefCell<Node>> {
+  let mut root = node.clone();
+  loop {
+    let parent = match root.borrow().parent().upgrade() {
+      Some(parent) => parent,
+      None => break,
+    };
+    root = parent;
+  }
+  root
+}
+
+fn element_id(node: &Rc<RefCell<Node>>) -> Option<String> {
+  match node.borrow().kind {
+    NodeKind::Element(ref element) => element.id().filter(|id| !id.is_empty()),
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::renderer::dom::api::{append_child, detach};
+  use crate::renderer::html::parser::HtmlParser;
+  use crate::renderer::html::token::HtmlTokenizer;
+
+  fn document(html: &str) -> Rc<RefCell<Node>> {
+    let t = HtmlTokenizer::new(html.to_string());
+    let window = HtmlParser::new(t).construct_tree();
+    let document = window.borrow().document();
+    document
+  }
+
+  fn ids(nodes: impl Iterator<Item = Rc<RefCell<Node>>>) -> Vec<String> {
+    nodes.map(|node| element_id(&node).unwrap_or_default()).collect()
+  }
+
+  #[test]
+  fn test_descendants_in_tree_order() {
+    let document = document("<div id=a><p id=b><span id=c></span></p><p id=d></p></div><p id=e>");
+    assert_eq!(
+      alloc::vec!["", "", "", "a", "b", "c", "d", "e"],
+      ids(elements(&document)),
+    );
+  }
+
+  #[test]
+  fn test_get_elements_by_tag_name() {
+    let document = document("<p id=a><svg><foreignObject id=b></foreignObject></svg><P id=c>");
+    assert_eq!(alloc::vec!["a", "c"], ids(get_elements_by_tag_name(&document, "P")));
+    assert_eq!(alloc::vec!["b"], ids(get_elements_by_tag_name(&document, "foreignObject")));
+    assert!(get_elements_by_tag_name(&document, "foreignobject").next().is_none());
+    assert_eq!(7, get_elements_by_tag_name(&document, "*").count());
+  }
+
+  #[test]
+  fn test_get_elements_by_class_name() {
+    let document = document("<p id=a class='x y'></p><p id=b class=x></p><p id=c class=xy>");
+    assert_eq!(alloc::vec!["a", "b"], ids(get_elements_by_class_name(&document, "x")));
+    assert_eq!(alloc::vec!["a"], ids(get_elements_by_class_name(&document, " y\tx ")));
+    assert!(get_elements_by_class_name(&document, "").next().is_none());
+  }
+
+  #[test]
+  fn test_get_elements_by_attribute() {
+    let document = document("<a id=a href=x></a><a id=b href=y></a><a id=c>");
+    assert_eq!(alloc::vec!["a", "b"], ids(get_elements_by_attribute(&document, "href", None)));
+    assert_eq!(alloc::vec!["b"], ids(get_elements_by_attribute(&document, "href", Some("y"))));
+  }
+
+  #[test]
+  fn test_get_element_by_id() {
+    let document = document("<div><p id=x class=first></p></div><p id=x class=second><p id=y>");
+    for _ in 0..2 {
+      let x = get_element_by_id(&document, "x").expect("x should be found");
+      assert_eq!(Some("first".to_string()), x.borrow().get_element().unwrap().get_attribute("class"));
+      assert!(get_element_by_id(&document, "y").is_some());
+      assert!(get_element_by_id(&document, "z").is_none());
+      enable_id_index(&document);
+    }
+  }
+
+  #[test]
+  fn test_id_index_follows_mutations() {
+    let document = document("<div id=a><p id=b></p></div><div id=c></div>");
+    enable_id_index(&document);
+    let a = get_element_by_id(&document, "a").unwrap();
+    let c = get_element_by_id(&document, "c").unwrap();
+
+    detach(&a);
+    assert!(get_element_by_id(&document, "a").is_none());
+    assert!(get_element_by_id(&document, "b").is_none());
+    assert_eq!(0, document.borrow().id_index().unwrap().get("b").len());
+
+    append_child(&c, &a).unwrap();
+    let b = get_element_by_id(&document, "b").expect("b should be indexed again");
+    assert!(Rc::ptr_eq(&a, &b.borrow().parent().upgrade().unwrap()));
+
+    disable_id_index(&document);
+    assert!(get_element_by_id(&document, "b").is_some());
+  }
+
+  #[test]
+  fn test_query_selector() {
+    let document = document(
+      "<ul id=nav><li id=a class=active><a id=x href=http://a></a><a id=y href=/b></a></li>\
+       <li id=b><a id=z href=https://c></a></li></ul>",
+    );
+    assert_eq!(
+      alloc::vec!["x"],
+      ids(query_selector_all(&document, "ul > li.active a[href^=http]").unwrap()),
+    );
+    assert_eq!(alloc::vec!["x", "z"], ids(query_selector_all(&document, "a[href^=http]").unwrap()));
+    assert_eq!(alloc::vec!["a", "b", "z"], ids(query_selector_all(&document, "li#b a, li").unwrap()));
+
+    let first = query_selector(&document, "li a").unwrap().expect("a link should match");
+    assert_eq!(Some("x".to_string()), element_id(&first));
+    assert!(query_selector(&document, "ol").unwrap().is_none());
+    assert!(query_selector(&document, "li >").is_err());
+  }
+
+  #[test]
+  fn test_query_selector_scope() {
+    let document = document("<div id=outer><p id=a></p><div id=inner><p id=b></p></div></div>");
+    let inner = get_element_by_id(&document, "inner").unwrap();
+    assert_eq!(alloc::vec!["b"], ids(query_selector_all(&inner, "#outer p").unwrap()));
+    assert!(query_selector(&inner, "div").unwrap().is_none());
+  }
+}
@@ -1,9 +1,32 @@
-use alloc::string::String;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// The namespace of an attribute on a foreign element, set by the tree builder for names such
+/// as `xlink:href`. Other attributes have no namespace.
+// https://html.spec.whatwg.org/multipage/parsing.html#adjust-foreign-attributes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeNamespace {
+  XLink,
+  Xml,
+  Xmlns,
+}
+
+impl AttributeNamespace {
+  pub fn prefix(&self) -> &'static str {
+    match self {
+      AttributeNamespace::XLink => "xlink",
+      AttributeNamespace::Xml => "xml",
+      AttributeNamespace::Xmlns => "xmlns",
+    }
+  }
+}
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Attribute {
   name: String,
   value: String,
+  namespace: Option<AttributeNamespace>,
 }
 
 impl Attribute {
@@ -11,6 +34,7 @@ impl Attribute {
     Self {
       name: String::new(),
       value: String::new(),
+      namespace: None,
     }
   }
 
@@ -22,6 +46,14 @@ impl Attribute {
     }
   }
 
+  pub fn set_name(&mut self, name: String) {
+    self.name = name;
+  }
+
+  pub fn set_value(&mut self, value: String) {
+    self.value = value;
+  }
+
   pub fn name(&self) -> String{
     self.name.clone()
   }
@@ -29,4 +61,164 @@ impl Attribute {
   pub fn value(&self) -> String {
     self.value.clone()
   }
-}
\ No newline at end of file
+
+  pub fn set_namespace(&mut self, namespace: Option<AttributeNamespace>) {
+    self.namespace = namespace;
+  }
+
+  pub fn namespace(&self) -> Option<AttributeNamespace> {
+    self.namespace
+  }
+
+  /// The name as written in markup, with the prefix of its namespace. The `xmlns` attribute
+  /// itself has no prefix.
+  // https://html.spec.whatwg.org/multipage/parsing.html#serialising-html-fragments
+  pub fn qualified_name(&self) -> String {
+    match self.prefix() {
+      Some(prefix) => format!("{}:{}", prefix, self.name),
+      None => self.name.clone(),
+    }
+  }
+
+  fn prefix(&self) -> Option<&'static str> {
+    match self.namespace {
+      Some(AttributeNamespace::Xmlns) if self.name == "xmlns" => None,
+      Some(namespace) => Some(namespace.prefix()),
+      None => None,
+    }
+  }
+
+  fn has_qualified_name(&self, name: &str) -> bool {
+    match self.prefix() {
+      Some(prefix) => name
+        .strip_prefix(prefix)
+        .and_then(|rest| rest.strip_prefix(':'))
+        .is_some_and(|local| local == self.name),
+      None => self.name == name,
+    }
+  }
+}
+
+/// The attributes of an element in the order they were added. Names are unique: when a list with
+/// duplicates is given, the first attribute with a name wins, as in the tokenizer.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AttributeMap {
+  attributes: Vec<Attribute>,
+}
+
+impl AttributeMap {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn from_attributes(attributes: Vec<Attribute>) -> Self {
+    let mut map = Self::new();
+    for attr in attributes {
+      if !map.contains(&attr.qualified_name()) {
+        map.attributes.push(attr);
+      }
+    }
+    map
+  }
+
+  pub fn get(&self, name: &str) -> Option<String> {
+    self.find(name).map(|attr| attr.value())
+  }
+
+  /// Like `get`, but borrows the value instead of cloning it.
+  pub fn value(&self, name: &str) -> Option<&str> {
+    self.find(name).map(|attr| attr.value.as_str())
+  }
+
+  pub fn contains(&self, name: &str) -> bool {
+    self.find(name).is_some()
+  }
+
+  pub fn set(&mut self, name: &str, value: &str) {
+    match self.attributes.iter_mut().find(|attr| attr.has_qualified_name(name)) {
+      Some(attr) => attr.set_value(value.to_string()),
+      None => {
+        let mut attr = Attribute::new();
+        attr.set_name(name.to_string());
+        attr.set_value(value.to_string());
+        self.attributes.push(attr);
+      }
+    }
+  }
+
+  pub fn remove(&mut self, name: &str) -> bool {
+    let len = self.attributes.len();
+    self.attributes.retain(|attr| !attr.has_qualified_name(name));
+    self.attributes.len() != len
+  }
+
+  pub fn len(&self) -> usize {
+    self.attributes.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.attributes.is_empty()
+  }
+
+  pub fn to_vec(&self) -> Vec<Attribute> {
+    self.attributes.clone()
+  }
+
+  pub fn iter(&self) -> core::slice::Iter<'_, Attribute> {
+    self.attributes.iter()
+  }
+
+  fn find(&self, name: &str) -> Option<&Attribute> {
+    self.attributes.iter().find(|attr| attr.has_qualified_name(name))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn attribute(name: &str, value: &str) -> Attribute {
+    let mut attr = Attribute::new();
+    attr.set_name(name.to_string());
+    attr.set_value(value.to_string());
+    attr
+  }
+
+  #[test]
+  fn test_attribute_map() {
+    let mut map = AttributeMap::from_attributes(alloc::vec![
+      attribute("id", "a"),
+      attribute("class", "b"),
+      attribute("id", "c"),
+    ]);
+    assert_eq!(2, map.len());
+    assert_eq!(Some("a".to_string()), map.get("id"));
+    assert_eq!(Some("b"), map.value("class"));
+    assert_eq!(None, map.value("title"));
+    assert!(map.contains("class"));
+    assert!(!map.contains("title"));
+
+    map.set("title", "d");
+    map.set("id", "e");
+    assert_eq!(alloc::vec![attribute("id", "e"), attribute("class", "b"), attribute("title", "d")], map.to_vec());
+    assert!(map.remove("class"));
+    assert!(!map.remove("class"));
+    assert_eq!(None, map.get("class"));
+  }
+
+  #[test]
+  fn test_qualified_name() {
+    let mut href = attribute("href", "x");
+    href.set_namespace(Some(AttributeNamespace::XLink));
+    let mut xmlns = attribute("xmlns", "y");
+    xmlns.set_namespace(Some(AttributeNamespace::Xmlns));
+    assert_eq!("xlink:href", href.qualified_name());
+    assert_eq!("xmlns", xmlns.qualified_name());
+
+    let map = AttributeMap::from_attributes(alloc::vec![href, attribute("href", "z"), xmlns]);
+    assert_eq!(3, map.len());
+    assert_eq!(Some("x".to_string()), map.get("xlink:href"));
+    assert_eq!(Some("z".to_string()), map.get("href"));
+    assert_eq!(Some("y".to_string()), map.get("xmlns"));
+  }
+}
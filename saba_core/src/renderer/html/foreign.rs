@@ -0,0 +1,197 @@
+use alloc::string::{String, ToString};
+use crate::renderer::html::attribute::{Attribute, AttributeNamespace};
+
+const SVG_TAG_NAMES: &[(&str, &str)] = &[
+  ("altglyph", "altGlyph"),
+  ("altglyphdef", "altGlyphDef"),
+  ("altglyphitem", "altGlyphItem"),
+  ("animatecolor", "animateColor"),
+  ("animatemotion", "animateMotion"),
+  ("animatetransform", "animateTransform"),
+  ("clippath", "clipPath"),
+  ("feblend", "feBlend"),
+  ("fecolormatrix", "feColorMatrix"),
+  ("fecomponenttransfer", "feComponentTransfer"),
+  ("fecomposite", "feComposite"),
+  ("feconvolvematrix", "feConvolveMatrix"),
+  ("fediffuselighting", "feDiffuseLighting"),
+  ("fedisplacementmap", "feDisplacementMap"),
+  ("fedistantlight", "feDistantLight"),
+  ("fedropshadow", "feDropShadow"),
+  ("feflood", "feFlood"),
+  ("fefunca", "feFuncA"),
+  ("fefuncb", "feFuncB"),
+  ("fefuncg", "feFuncG"),
+  ("fefuncr", "feFuncR"),
+  ("fegaussianblur", "feGaussianBlur"),
+  ("feimage", "feImage"),
+  ("femerge", "feMerge"),
+  ("femergenode", "feMergeNode"),
+  ("femorphology", "feMorphology"),
+  ("feoffset", "feOffset"),
+  ("fepointlight", "fePointLight"),
+  ("fespecularlighting", "feSpecularLighting"),
+  ("fespotlight", "feSpotLight"),
+  ("fetile", "feTile"),
+  ("feturbulence", "feTurbulence"),
+  ("foreignobject", "foreignObject"),
+  ("glyphref", "glyphRef"),
+  ("lineargradient", "linearGradient"),
+  ("radialgradient", "radialGradient"),
+  ("textpath", "textPath"),
+];
+
+const SVG_ATTRIBUTE_NAMES: &[(&str, &str)] = &[
+  ("attributename", "attributeName"),
+  ("attributetype", "attributeType"),
+  ("basefrequency", "baseFrequency"),
+  ("baseprofile", "baseProfile"),
+  ("calcmode", "calcMode"),
+  ("clippathunits", "clipPathUnits"),
+  ("diffuseconstant", "diffuseConstant"),
+  ("edgemode", "edgeMode"),
+  ("filterunits", "filterUnits"),
+  ("glyphref", "glyphRef"),
+  ("gradienttransform", "gradientTransform"),
+  ("gradientunits", "gradientUnits"),
+  ("kernelmatrix", "kernelMatrix"),
+  ("kernelunitlength", "kernelUnitLength"),
+  ("keypoints", "keyPoints"),
+  ("keysplines", "keySplines"),
+  ("keytimes", "keyTimes"),
+  ("lengthadjust", "lengthAdjust"),
+  ("limitingconeangle", "limitingConeAngle"),
+  ("markerheight", "markerHeight"),
+  ("markerunits", "markerUnits"),
+  ("markerwidth", "markerWidth"),
+  ("maskcontentunits", "maskContentUnits"),
+  ("maskunits", "maskUnits"),
+  ("numoctaves", "numOctaves"),
+  ("pathlength", "pathLength"),
+  ("patterncontentunits", "patternContentUnits"),
+  ("patterntransform", "patternTransform"),
+  ("patternunits", "patternUnits"),
+  ("pointsatx", "pointsAtX"),
+  ("pointsaty", "pointsAtY"),
+  ("pointsatz", "pointsAtZ"),
+  ("preservealpha", "preserveAlpha"),
+  ("preserveaspectratio", "preserveAspectRatio"),
+  ("primitiveunits", "primitiveUnits"),
+  ("refx", "refX"),
+  ("refy", "refY"),
+  ("repeatcount", "repeatCount"),
+  ("repeatdur", "repeatDur"),
+  ("requiredextensions", "requiredExtensions"),
+  ("requiredfeatures", "requiredFeatures"),
+  ("specularconstant", "specularConstant"),
+  ("specularexponent", "specularExponent"),
+  ("spreadmethod", "spreadMethod"),
+  ("startoffset", "startOffset"),
+  ("stddeviation", "stdDeviation"),
+  ("stitchtiles", "stitchTiles"),
+  ("surfacescale", "surfaceScale"),
+  ("systemlanguage", "systemLanguage"),
+  ("tablevalues", "tableValues"),
+  ("targetx", "targetX"),
+  ("targety", "targetY"),
+  ("textlength", "textLength"),
+  ("viewbox", "viewBox"),
+  ("viewtarget", "viewTarget"),
+  ("xchannelselector", "xChannelSelector"),
+  ("ychannelselector", "yChannelSelector"),
+  ("zoomandpan", "zoomAndPan"),
+];
+
+const MATHML_ATTRIBUTE_NAMES: &[(&str, &str)] = &[("definitionurl", "definitionURL")];
+
+fn lookup(table: &'static [(&'static str, &'static str)], name: &str) -> Option<&'static str> {
+  table.iter().find(|(lower, _)| *lower == name).map(|(_, adjusted)| *adjusted)
+}
+
+fn adjust_attributes(table: &'static [(&'static str, &'static str)], attributes: &mut [Attribute]) {
+  for attr in attributes.iter_mut() {
+    if let Some(name) = lookup(table, &attr.name()) {
+      attr.set_name(name.to_string());
+    }
+  }
+}
+
+pub fn adjust_svg_tag_name(tag: &str) -> String {
+  lookup(SVG_TAG_NAMES, tag).unwrap_or(tag).to_string()
+}
+
+pub fn adjust_svg_attributes(attributes: &mut [Attribute]) {
+  adjust_attributes(SVG_ATTRIBUTE_NAMES, attributes);
+}
+
+pub fn adjust_mathml_attributes(attributes: &mut [Attribute]) {
+  adjust_attributes(MATHML_ATTRIBUTE_NAMES, attributes);
+}
+
+// https://html.spec.whatwg.org/multipage/parsing.html#adjust-foreign-attributes
+pub fn adjust_foreign_attributes(attributes: &mut [Attribute]) {
+  for attr in attributes.iter_mut() {
+    let name = attr.name();
+    let (namespace, local) = match name.as_str() {
+      "xlink:actuate" | "xlink:arcrole" | "xlink:href" | "xlink:role" | "xlink:show"
+      | "xlink:title" | "xlink:type" => (AttributeNamespace::XLink, &name[6..]),
+      "xml:lang" | "xml:space" => (AttributeNamespace::Xml, &name[4..]),
+      "xmlns" => (AttributeNamespace::Xmlns, name.as_str()),
+      "xmlns:xlink" => (AttributeNamespace::Xmlns, &name[6..]),
+      _ => continue,
+    };
+    attr.set_name(local.to_string());
+    attr.set_namespace(Some(namespace));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use alloc::vec::Vec;
+
+  fn attribute(name: &str) -> Attribute {
+    let mut attr = Attribute::new();
+    for c in name.chars() {
+      attr.add_char(c, true);
+    }
+    attr
+  }
+
+  #[test]
+  fn test_svg_tag_name() {
+    assert_eq!("foreignObject", adjust_svg_tag_name("foreignobject"));
+    assert_eq!("linearGradient", adjust_svg_tag_name("lineargradient"));
+    assert_eq!("path", adjust_svg_tag_name("path"));
+  }
+
+  #[test]
+  fn test_attributes() {
+    let mut attrs = alloc::vec![attribute("viewbox"), attribute("fill")];
+    adjust_svg_attributes(&mut attrs);
+    assert_eq!(
+      alloc::vec!["viewBox".to_string(), "fill".to_string()],
+      attrs.iter().map(|a| a.name()).collect::<Vec<_>>(),
+    );
+
+    let mut attrs = alloc::vec![attribute("definitionurl")];
+    adjust_mathml_attributes(&mut attrs);
+    assert_eq!("definitionURL", attrs[0].name());
+  }
+
+  #[test]
+  fn test_foreign_attributes() {
+    let mut attrs = ["xlink:href", "xml:lang", "xmlns:xlink", "xmlns", "xlink:foo"].map(attribute);
+    adjust_foreign_attributes(&mut attrs);
+    assert_eq!(
+      alloc::vec![
+        (Some(AttributeNamespace::XLink), "href".to_string()),
+        (Some(AttributeNamespace::Xml), "lang".to_string()),
+        (Some(AttributeNamespace::Xmlns), "xlink".to_string()),
+        (Some(AttributeNamespace::Xmlns), "xmlns".to_string()),
+        (None, "xlink:foo".to_string()),
+      ],
+      attrs.iter().map(|a| (a.namespace(), a.name())).collect::<Vec<_>>(),
+    );
+  }
+}
@@ -1,3 +1,5 @@
 pub mod token;
 pub mod attribute;
-pub mod parser;
\ No newline at end of file
+pub mod parser;
+pub mod foreign;
+pub mod serializer;
\ No newline at end of file
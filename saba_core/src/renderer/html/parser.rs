@@ -1,23 +1,109 @@
-use crate::renderer::dom::node::ElementKind;
+use crate::renderer::dom::api::append_child;
+use crate::renderer::dom::api::create_document_fragment;
+use crate::renderer::dom::api::detach;
+use crate::renderer::dom::api::insert_before;
+use crate::renderer::dom::mutation::mark_layout_dirty;
 use crate::renderer::dom::node::Node;
 use crate::renderer::dom::node::NodeKind;
 use crate::renderer::dom::node::Window;
 use crate::renderer::html::token::HtmlTokenizer;
 use crate::renderer::html::token::HtmlToken;
+use crate::renderer::html::token::State;
 use crate::renderer::dom::node::Element;
+use crate::renderer::dom::node::Namespace;
+use crate::renderer::html::foreign::adjust_foreign_attributes;
+use crate::renderer::html::foreign::adjust_mathml_attributes;
+use crate::renderer::html::foreign::adjust_svg_attributes;
+use crate::renderer::html::foreign::adjust_svg_tag_name;
 use crate::renderer::html::attribute::Attribute;
 use alloc::rc::Rc;
 use alloc::vec::Vec;
 use core::cell::RefCell;
-use core::str::FromStr;
 use alloc::string::String;
 
+const DEFAULT_SCOPE: &[&str] = &[
+  "applet", "caption", "html", "table", "td", "th", "marquee", "object", "template",
+];
+
+const LIST_ITEM_SCOPE: &[&str] = &[
+  "applet", "caption", "html", "table", "td", "th", "marquee", "object", "template", "ol", "ul",
+];
+
+const BUTTON_SCOPE: &[&str] = &[
+  "applet", "caption", "html", "table", "td", "th", "marquee", "object", "template", "button",
+];
+
+const TABLE_SCOPE: &[&str] = &["html", "table", "template"];
+
+const IMPLIED_END_TAGS: &[&str] = &[
+  "dd", "dt", "li", "optgroup", "option", "p", "rb", "rp", "rt", "rtc",
+];
+
+const THOROUGHLY_IMPLIED_END_TAGS: &[&str] = &[
+  "caption", "colgroup", "dd", "dt", "li", "optgroup", "option", "p", "rb", "rp", "rt", "rtc",
+  "tbody", "td", "tfoot", "th", "thead", "tr",
+];
+
+const HEADINGS: &[&str] = &["h1", "h2", "h3", "h4", "h5", "h6"];
+
+const SPECIAL_ELEMENTS: &[&str] = &[
+  "address", "applet", "area", "article", "aside", "base", "basefont", "bgsound", "blockquote",
+  "body", "br", "button", "caption", "center", "col", "colgroup", "dd", "details", "dir", "div",
+  "dl", "dt", "embed", "fieldset", "figcaption", "figure", "footer", "form", "frame", "frameset",
+  "h1", "h2", "h3", "h4", "h5", "h6", "head", "header", "hgroup", "hr", "html", "iframe", "img",
+  "input", "keygen", "li", "link", "listing", "main", "marquee", "menu", "meta", "nav",
+  "noembed", "noframes", "noscript", "object", "ol", "p", "param", "plaintext", "pre", "script",
+  "search", "section", "select", "source", "style", "summary", "table", "tbody", "td",
+  "template", "textarea", "tfoot", "th", "thead", "title", "tr", "track", "ul", "wbr", "xmp",
+];
+
+const WHITESPACE_SIGNIFICANT_ELEMENTS: &[&str] = &[
+  "iframe", "listing", "noembed", "noframes", "plaintext", "pre", "script", "style", "textarea",
+  "title", "xmp",
+];
+
+const MATHML_TEXT_INTEGRATION_POINTS: &[&str] = &["mi", "mo", "mn", "ms", "mtext"];
+
+const MATHML_SCOPE: &[&str] = &["mi", "mo", "mn", "ms", "mtext", "annotation-xml"];
+
+const SVG_SCOPE: &[&str] = &["foreignObject", "desc", "title"];
+
+const FOREIGN_BREAKOUT_TAGS: &[&str] = &[
+  "b", "big", "blockquote", "body", "br", "center", "code", "dd", "div", "dl", "dt", "em", "embed",
+  "h1", "h2", "h3", "h4", "h5", "h6", "head", "hr", "i", "img", "li", "listing", "menu", "meta",
+  "nobr", "ol", "p", "pre", "ruby", "s", "small", "span", "strong", "strike", "sub", "sup",
+  "table", "tt", "u", "ul", "var",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WhitespaceMode {
+  Preserve,
+  StripInterElement,
+}
+
+#[derive(Debug, Clone)]
+enum ActiveFormattingElement {
+  Element(Rc<RefCell<Node>>),
+  Marker,
+}
+
 #[derive(Debug, Clone)]
 pub struct HtmlParser {
   window: Rc<RefCell<Window>>,
   mode: InsertionMode,
   original_insertion_mode: InsertionMode,
+  template_insertion_modes: Vec<InsertionMode>,
   stack_of_open_elements: Vec<Rc<RefCell<Node>>>,
+  active_formatting_elements: Vec<ActiveFormattingElement>,
+  head_element: Option<Rc<RefCell<Node>>>,
+  form_element: Option<Rc<RefCell<Node>>>,
+  foster_parenting: bool,
+  pending_table_characters: String,
+  context_element: Option<Rc<RefCell<Node>>>,
+  stopped: bool,
+  ignore_line_feed: bool,
+  whitespace_mode: WhitespaceMode,
+  whitespace_text: Option<Rc<RefCell<Node>>>,
   t: HtmlTokenizer,
 }
 
@@ -27,374 +113,1655 @@ impl HtmlParser {
       window: Rc::new(RefCell::new(Window::new())),
       mode: InsertionMode::Initial,
       original_insertion_mode: InsertionMode::Initial,
+      template_insertion_modes: Vec::new(),
       stack_of_open_elements: Vec::new(),
+      active_formatting_elements: Vec::new(),
+      head_element: None,
+      form_element: None,
+      foster_parenting: false,
+      pending_table_characters: String::new(),
+      context_element: None,
+      stopped: false,
+      ignore_line_feed: false,
+      whitespace_mode: WhitespaceMode::Preserve,
+      whitespace_text: None,
       t,
     }
   }
 
+  /// With `StripInterElement`, text nodes that consist only of whitespace are dropped unless they
+  /// are inside an element where whitespace is significant, such as `pre` or `textarea`.
+  pub fn set_whitespace_mode(&mut self, mode: WhitespaceMode) {
+    self.whitespace_mode = mode;
+  }
+
+  pub fn parse_fragment(context_element: &Rc<RefCell<Node>>, html: String) -> Vec<Rc<RefCell<Node>>> {
+    Self::parse_fragment_with_whitespace_mode(context_element, html, WhitespaceMode::Preserve)
+  }
+
+  pub fn parse_fragment_with_whitespace_mode(
+    context_element: &Rc<RefCell<Node>>,
+    html: String,
+    whitespace_mode: WhitespaceMode,
+  ) -> Vec<Rc<RefCell<Node>>> {
+    let mut t = HtmlTokenizer::new(html);
+    let context_tag = match context_element.borrow().get_element() {
+      Some(element) if element.namespace() == Namespace::Html => element.tag_name(),
+      _ => String::new(),
+    };
+    match context_tag.as_str() {
+      "title" | "textarea" => t.switch_to(State::Rcdata),
+      "style" | "xmp" | "iframe" | "noembed" | "noframes" => t.switch_to(State::Rawtext),
+      "script" => t.switch_to(State::ScriptData),
+      "plaintext" => t.switch_to(State::Plaintext),
+      _ => {}
+    }
+
+    let mut parser = HtmlParser::new(t);
+    parser.context_element = Some(context_element.clone());
+    parser.whitespace_mode = whitespace_mode;
+    let root = parser.insert_element("html", Vec::new());
+    if context_tag == "template" {
+      parser.template_insertion_modes.push(InsertionMode::InTemplate);
+    }
+    parser.reset_insertion_mode_appropriately();
+
+    let mut node = Some(context_element.clone());
+    while let Some(n) = node {
+      if is_element_named(&n, &["form"]) {
+        parser.form_element = Some(n);
+        break;
+      }
+      node = n.borrow().parent().upgrade();
+    }
+
+    parser.construct_tree();
+
+    let mut children = Vec::new();
+    loop {
+      let child = match root.borrow().first_child() {
+        Some(child) => child,
+        None => break,
+      };
+      detach(&child);
+      children.push(child);
+    }
+    children
+  }
+
   pub fn construct_tree(&mut self) -> Rc<RefCell<Window>> {
-    let mut token = self.t.next();
+    self.finish()
+  }
 
-    while token.is_some() {
-      match self.mode {
-        InsertionMode::Initial => {
-          if let Some(HtmlToken::Char(_)) = token {
-            token = self.t.next();
-            continue;
+  pub fn window(&self) -> Rc<RefCell<Window>> {
+    self.window.clone()
+  }
+
+  /// Tokenizes and tree-builds as much of `chunk` as possible. A token cut off at the end of the
+  /// chunk is kept in the tokenizer until the next call, so the tree built so far can be laid out.
+  pub fn feed(&mut self, chunk: &str) {
+    self.t.feed(chunk);
+    self.run();
+  }
+
+  pub fn finish(&mut self) -> Rc<RefCell<Window>> {
+    if !self.t.is_finished() {
+      self.t.finish();
+    }
+    self.run();
+    self.window.clone()
+  }
+
+  fn run(&mut self) {
+    while !self.stopped {
+      let cdata_allowed = self
+        .adjusted_current_node()
+        .is_some_and(|node| !is_in_namespace(&node, Namespace::Html));
+      self.t.set_cdata_allowed(cdata_allowed);
+
+      let token = match self.t.next() {
+        Some(token) => token,
+        None if self.t.is_finished() => HtmlToken::Eof,
+        None => return,
+      };
+      // A newline immediately after a pre, listing or textarea start tag is ignored.
+      if core::mem::take(&mut self.ignore_line_feed) && token == HtmlToken::Char('\n') {
+        continue;
+      }
+      if self.is_foreign_content(&token) {
+        self.process_foreign_content(&token);
+      } else {
+        self.process_token(self.mode, &token);
+      }
+      if token == HtmlToken::Eof {
+        self.discard_whitespace_text();
+        self.stopped = true;
+      }
+    }
+  }
+
+  fn is_foreign_content(&self, token: &HtmlToken) -> bool {
+    let node = match self.adjusted_current_node() {
+      Some(node) => node,
+      None => return false,
+    };
+    if is_in_namespace(&node, Namespace::Html) {
+      return false;
+    }
+
+    match token {
+      HtmlToken::StartTag { ref tag, .. } => {
+        if is_mathml_text_integration_point(&node) && tag != "mglyph" && tag != "malignmark" {
+          return false;
+        }
+        if is_element_in_namespace(&node, Namespace::MathMl, &["annotation-xml"]) && tag == "svg" {
+          return false;
+        }
+        !is_html_integration_point(&node)
+      }
+      HtmlToken::Char(_) => !is_mathml_text_integration_point(&node) && !is_html_integration_point(&node),
+      HtmlToken::EndTag { .. } => true,
+      HtmlToken::Comment(_) | HtmlToken::Doctype { .. } | HtmlToken::Eof => false,
+    }
+  }
+
+  fn process_foreign_content(&mut self, token: &HtmlToken) {
+    match token {
+      HtmlToken::Char(c) => {
+        self.insert_char(if *c == '\0' { '\u{FFFD}' } else { *c });
+      }
+      HtmlToken::StartTag { ref tag, self_closing, ref attributes } => {
+        let breakout = FOREIGN_BREAKOUT_TAGS.contains(&tag.as_str())
+          || (tag == "font"
+            && attributes.iter().any(|a| matches!(a.name().as_str(), "color" | "face" | "size")));
+        if breakout {
+          self.pop_until_html_content();
+          self.process_token(self.mode, token);
+          return;
+        }
+
+        let namespace = match self.adjusted_current_node().and_then(|n| n.borrow().get_element()) {
+          Some(element) => element.namespace(),
+          None => Namespace::Html,
+        };
+        self.insert_foreign_element(tag, attributes.to_vec(), namespace);
+        if *self_closing {
+          self.stack_of_open_elements.pop();
+        }
+      }
+      HtmlToken::EndTag { ref tag } => {
+        if tag == "br" || tag == "p" {
+          self.pop_until_html_content();
+          self.process_token(self.mode, token);
+          return;
+        }
+
+        let mut i = self.stack_of_open_elements.len() - 1;
+        loop {
+          if i == 0 {
+            return;
+          }
+          let name = match self.stack_of_open_elements[i].borrow().get_element() {
+            Some(element) => element.tag_name().to_ascii_lowercase(),
+            None => String::new(),
+          };
+          if name == *tag {
+            self.stack_of_open_elements.truncate(i);
+            return;
           }
 
-          self.mode = InsertionMode::BeforeHtml;
-          continue;
+          i -= 1;
+          if is_in_namespace(&self.stack_of_open_elements[i], Namespace::Html) {
+            self.process_token(self.mode, token);
+            return;
+          }
         }
-        InsertionMode::BeforeHtml => {
-          match token {
-            Some(HtmlToken::Char(c)) => {
-              if c == ' ' || c == '\n' {
-                token = self.t.next();
-                continue;
-              }
+      }
+      HtmlToken::Comment(_) | HtmlToken::Doctype { .. } | HtmlToken::Eof => {
+        self.process_token(self.mode, token)
+      }
+    }
+  }
+
+  fn process_token(&mut self, mode: InsertionMode, token: &HtmlToken) {
+    // Comments and doctypes are handled the same way in almost every insertion mode. In table
+    // text mode they first flush the pending characters, which the mode itself takes care of.
+    if mode != InsertionMode::InTableText {
+      match token {
+        HtmlToken::Comment(ref data) => {
+          let parent = match mode {
+            InsertionMode::Initial | InsertionMode::BeforeHtml | InsertionMode::AfterAfterBody => {
+              Some(self.window.borrow().document())
             }
-            Some(HtmlToken::StartTag {
-              ref tag,
-              self_closing: _,
-              ref attributes,
-            }) => {
-              if tag == "html" {
-                self.insert_element(tag, attributes.to_vec());
-                self.mode = InsertionMode::BeforeHead;
-                token = self.t.next();
-                continue;
-              }
+            InsertionMode::AfterBody => self.stack_of_open_elements.first().cloned(),
+            _ => None,
+          };
+          self.insert_comment(data, parent);
+          return;
+        }
+        HtmlToken::Doctype { ref name, ref public_id, ref system_id, .. } => {
+          if mode == InsertionMode::Initial {
+            let doctype = NodeKind::DocumentType {
+              name: name.clone().unwrap_or_default(),
+              public_id: public_id.clone().unwrap_or_default(),
+              system_id: system_id.clone().unwrap_or_default(),
+            };
+            let document = self.window.borrow().document();
+            append_child(&document, &Rc::new(RefCell::new(Node::new(doctype))))
+              .expect("node should be insertable");
+            self.mode = InsertionMode::BeforeHtml;
+          }
+          return;
+        }
+        _ => {}
+      }
+    }
+
+    match mode {
+      InsertionMode::Initial => {
+        if let HtmlToken::Char(c) = token {
+          if c.is_ascii_whitespace() {
+            return;
+          }
+        }
+
+        self.mode = InsertionMode::BeforeHtml;
+        self.process_token(self.mode, token)
+      }
+      InsertionMode::BeforeHtml => {
+        match token {
+          HtmlToken::Char(c) => {
+            if c.is_ascii_whitespace() {
+              return;
             }
-            Some(HtmlToken::Eof) | None => {
-              return self.window.clone();
+          }
+          HtmlToken::StartTag {
+            ref tag,
+            self_closing: _,
+            ref attributes,
+          } => {
+            if tag == "html" {
+              self.insert_element(tag, attributes.to_vec());
+              self.mode = InsertionMode::BeforeHead;
+              return;
             }
-            _ => {}
           }
-          self.insert_element("html", Vec::new());
-          self.mode = InsertionMode::BeforeHead;
-          continue;
+          _ => {}
         }
-        InsertionMode::BeforeHead => {
-          match token {
-            Some(HtmlToken::Char(c)) => {
-              if c == ' ' || c == '\n' {
-                token = self.t.next();
-                continue;
-              }
-            }
-            Some(HtmlToken::StartTag {
-              ref tag,
-              self_closing: _,
-              ref attributes,
-            }) => {
-              if tag == "head" {
-                self.insert_element(tag, attributes.to_vec());
-                self.mode = InsertionMode::InHead;
-                token = self.t.next();
-                continue;
-              }
+        self.insert_element("html", Vec::new());
+        self.mode = InsertionMode::BeforeHead;
+        self.process_token(self.mode, token)
+      }
+      InsertionMode::BeforeHead => {
+        match token {
+          HtmlToken::Char(c) => {
+            if c.is_ascii_whitespace() {
+              return;
             }
-            Some(HtmlToken::Eof) | None => {
-              return self.window.clone();
+          }
+          HtmlToken::StartTag {
+            ref tag,
+            self_closing: _,
+            ref attributes,
+          } => {
+            if tag == "head" {
+              let head = self.insert_element(tag, attributes.to_vec());
+              self.head_element = Some(head);
+              self.mode = InsertionMode::InHead;
+              return;
             }
-            _ => {}
           }
+          _ => {}
         }
-        InsertionMode::InHead => {
-          match token {
-            Some(HtmlToken::Char(c)) => {
-              if c == ' ' || c == '\n' {
-                token = self.t.next();
-                continue;
-              }
+        let head = self.insert_element("head", Vec::new());
+        self.head_element = Some(head);
+        self.mode = InsertionMode::InHead;
+        self.process_token(self.mode, token)
+      }
+      InsertionMode::InHead => {
+        match token {
+          HtmlToken::Char(c) => {
+            if c.is_ascii_whitespace() {
+              self.insert_char(*c);
+              return;
             }
-            Some(HtmlToken::StartTag {
-              ref tag,
-              self_closing: _,
-              ref attributes,
-            }) => {
-              if tag == "style" || tag == "script" {
-                self.insert_element(tag, attributes.to_vec());
-                self.original_insertion_mode = self.mode;
-                self.mode = InsertionMode::Text;
-                token = self.t.next();
-                continue;
-              }
-              if tag == "body" {
-                self.pop_until(ElementKind::Head);
-                self.mode = InsertionMode::AfterHead;
-                continue;
-              }
-              if let Ok(_element_kind) = ElementKind::from_str(tag) {
-                self.pop_until(ElementKind::Head);
-                self.mode = InsertionMode::AfterHead;
-                continue;
-              }
+          }
+          HtmlToken::StartTag {
+            ref tag,
+            self_closing: _,
+            ref attributes,
+          } => {
+            if tag == "html" {
+              return self.process_token(InsertionMode::InBody, token);
+            }
+            if tag == "title" {
+              self.parse_text_element(tag, attributes.to_vec(), State::Rcdata);
+              return;
+            }
+            if tag == "style" || tag == "noframes" {
+              self.parse_text_element(tag, attributes.to_vec(), State::Rawtext);
+              return;
+            }
+            if tag == "script" {
+              self.parse_text_element(tag, attributes.to_vec(), State::ScriptData);
+              return;
+            }
+            if tag == "base" || tag == "basefont" || tag == "bgsound" || tag == "link" || tag == "meta" {
+              self.insert_element(tag, attributes.to_vec());
+              self.stack_of_open_elements.pop();
+              return;
             }
-            Some(HtmlToken::EndTag { ref tag }) => {
-              if tag == "head" {
-                self.mode = InsertionMode::AfterHead;
-                token = self.t.next();
-                self.pop_until(ElementKind::Head);
-                continue;
+            if tag == "noscript" {
+              self.insert_element(tag, attributes.to_vec());
+              self.mode = InsertionMode::InHeadNoscript;
+              return;
+            }
+            if tag == "template" {
+              self.insert_element(tag, attributes.to_vec());
+              self.active_formatting_elements.push(ActiveFormattingElement::Marker);
+              self.mode = InsertionMode::InTemplate;
+              self.template_insertion_modes.push(InsertionMode::InTemplate);
+              return;
+            }
+            if tag == "head" {
+              return;
+            }
+          }
+          HtmlToken::EndTag { ref tag } => {
+            if tag == "head" {
+              self.mode = InsertionMode::AfterHead;
+              self.pop_until(&["head"]);
+              return;
+            }
+            if tag == "template" {
+              if self.contain_in_stack(&["template"]) {
+                self.generate_implied_end_tags_thoroughly();
+                self.pop_until(&["template"]);
+                self.clear_active_formatting_elements_to_last_marker();
+                self.template_insertion_modes.pop();
+                self.reset_insertion_mode_appropriately();
               }
+              return;
             }
-            Some(HtmlToken::Eof) | None => {
-              return self.window.clone();
+            if tag != "body" && tag != "html" && tag != "br" {
+              return;
             }
           }
-          token = self.t.next();
-          continue;
+          HtmlToken::Eof => {}
+          HtmlToken::Comment(_) | HtmlToken::Doctype { .. } => unreachable!(),
         }
-        InsertionMode::AfterHead => {
-          match token {
-            Some(HtmlToken::Char(c)) => {
-              if c == ' ' || c == '\n' {
-                token = self.t.next();
-                continue;
-              }
+        self.pop_until(&["head"]);
+        self.mode = InsertionMode::AfterHead;
+        self.process_token(self.mode, token)
+      }
+      InsertionMode::InHeadNoscript => {
+        match token {
+          HtmlToken::Char(c) => {
+            if c.is_ascii_whitespace() {
+              return self.process_token(InsertionMode::InHead, token);
             }
-            Some(HtmlToken::StartTag {
-              ref tag,
-              self_closing: _,
-              ref attributes,
-            }) => {
-              if tag == "body" {
-                self.insert_element(tag, attributes.to_vec());
-                self.mode = InsertionMode::InBody;
-                token = self.t.next();
-                continue;
+          }
+          HtmlToken::StartTag { ref tag, .. } => match tag.as_str() {
+            "html" => return self.process_token(InsertionMode::InBody, token),
+            "basefont" | "bgsound" | "link" | "meta" | "noframes" | "style" => {
+              return self.process_token(InsertionMode::InHead, token);
+            }
+            "head" | "noscript" => return,
+            _ => {}
+          },
+          HtmlToken::EndTag { ref tag } => match tag.as_str() {
+            "noscript" => {
+              self.stack_of_open_elements.pop();
+              self.mode = InsertionMode::InHead;
+              return;
+            }
+            "br" => {}
+            _ => return,
+          },
+          HtmlToken::Eof => {}
+          HtmlToken::Comment(_) | HtmlToken::Doctype { .. } => unreachable!(),
+        }
+        self.stack_of_open_elements.pop();
+        self.mode = InsertionMode::InHead;
+        self.process_token(self.mode, token)
+      }
+      InsertionMode::AfterHead => {
+        match token {
+          HtmlToken::Char(c) => {
+            if c.is_ascii_whitespace() {
+              self.insert_char(*c);
+              return;
+            }
+          }
+          HtmlToken::StartTag {
+            ref tag,
+            self_closing: _,
+            ref attributes,
+          } => {
+            if tag == "body" {
+              self.insert_element(tag, attributes.to_vec());
+              self.mode = InsertionMode::InBody;
+              return;
+            }
+            if is_head_content(tag) {
+              if let Some(head) = self.head_element.clone() {
+                self.stack_of_open_elements.push(head.clone());
+                self.process_token(InsertionMode::InHead, token);
+                self.remove_from_stack(&head);
               }
+              return;
             }
-            Some(HtmlToken::Eof) | None => {
-              return self.window.clone();
+            if tag == "head" {
+              return;
             }
-            _ => {}
           }
-          self.insert_element("body", Vec::new());
-          self.mode = InsertionMode::InBody;
-          continue;
-        }
-        InsertionMode::InBody => {
-          match token {
-            Some(HtmlToken::StartTag { ref tag, self_closing: _, ref attributes }) => {
-              match tag.as_str() {
-                "p" => {
-                  self.insert_element(tag, attributes.to_vec());
-                  token = self.t.next();
-                  continue;
+          HtmlToken::EndTag { ref tag } if tag == "template" => {
+            return self.process_token(InsertionMode::InHead, token);
+          }
+          _ => {}
+        }
+        self.insert_element("body", Vec::new());
+        self.mode = InsertionMode::InBody;
+        self.process_token(self.mode, token)
+      }
+      InsertionMode::InBody => {
+        match token {
+          HtmlToken::StartTag { ref tag, self_closing: _, ref attributes } => {
+            match tag.as_str() {
+              "html" | "body" => {}
+              "base" | "basefont" | "bgsound" | "link" | "meta" | "noframes" | "script"
+              | "style" | "template" | "title" => {
+                self.process_token(InsertionMode::InHead, token);
+              }
+              "address" | "article" | "aside" | "blockquote" | "center" | "details"
+              | "dialog" | "dir" | "div" | "dl" | "fieldset" | "figcaption" | "figure"
+              | "footer" | "header" | "hgroup" | "main" | "menu" | "nav" | "ol" | "p"
+              | "search" | "section" | "summary" | "ul" => {
+                self.close_p_element_in_button_scope();
+                self.insert_element(tag, attributes.to_vec());
+              }
+              "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.close_p_element_in_button_scope();
+                if is_element_named(&self.current_node(), HEADINGS) {
+                  self.stack_of_open_elements.pop();
                 }
-                "h1" | "h2" => {
-                  self.insert_element(tag, attributes.to_vec());
-                  token = self.t.next();
-                  continue;
+                self.insert_element(tag, attributes.to_vec());
+              }
+              "pre" | "listing" => {
+                self.close_p_element_in_button_scope();
+                self.insert_element(tag, attributes.to_vec());
+                self.ignore_line_feed = true;
+              }
+              "plaintext" => {
+                self.close_p_element_in_button_scope();
+                self.insert_element(tag, attributes.to_vec());
+                self.t.switch_to(State::Plaintext);
+              }
+              "textarea" => {
+                self.parse_text_element(tag, attributes.to_vec(), State::Rcdata);
+                self.ignore_line_feed = true;
+              }
+              "xmp" => {
+                self.close_p_element_in_button_scope();
+                self.reconstruct_active_formatting_elements();
+                self.parse_text_element(tag, attributes.to_vec(), State::Rawtext);
+              }
+              "iframe" | "noembed" => {
+                self.parse_text_element(tag, attributes.to_vec(), State::Rawtext);
+              }
+              "form" => {
+                if self.form_element.is_none() {
+                  self.close_p_element_in_button_scope();
+                  let form = self.insert_element(tag, attributes.to_vec());
+                  self.form_element = Some(form);
                 }
-                "a" => {
-                  self.insert_element(tag, attributes.to_vec());
-                  token = self.t.next();
-                  continue;
+              }
+              "li" => {
+                self.close_list_item(&["li"]);
+                self.close_p_element_in_button_scope();
+                self.insert_element(tag, attributes.to_vec());
+              }
+              "dd" | "dt" => {
+                self.close_list_item(&["dd", "dt"]);
+                self.close_p_element_in_button_scope();
+                self.insert_element(tag, attributes.to_vec());
+              }
+              "button" => {
+                if self.has_element_in_scope(&["button"]) {
+                  self.generate_implied_end_tags(None);
+                  self.pop_until(&["button"]);
                 }
-                _ => {
-                  token = self.t.next();
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(tag, attributes.to_vec());
+              }
+              "a" => {
+                if let Some(a) = self.find_active_formatting_element("a") {
+                  self.run_adoption_agency("a");
+                  self.remove_from_active_formatting_elements(&a);
+                  self.remove_from_stack(&a);
+                }
+                self.reconstruct_active_formatting_elements();
+                let node = self.insert_element(tag, attributes.to_vec());
+                self.push_active_formatting_element(node);
+              }
+              "b" | "big" | "code" | "em" | "font" | "i" | "s" | "small" | "strike"
+              | "strong" | "tt" | "u" => {
+                self.reconstruct_active_formatting_elements();
+                let node = self.insert_element(tag, attributes.to_vec());
+                self.push_active_formatting_element(node);
+              }
+              "nobr" => {
+                self.reconstruct_active_formatting_elements();
+                if self.has_element_in_scope(&["nobr"]) {
+                  self.run_adoption_agency("nobr");
+                  self.reconstruct_active_formatting_elements();
+                }
+                let node = self.insert_element(tag, attributes.to_vec());
+                self.push_active_formatting_element(node);
+              }
+              "applet" | "marquee" | "object" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(tag, attributes.to_vec());
+                self.active_formatting_elements.push(ActiveFormattingElement::Marker);
+              }
+              "table" => {
+                self.close_p_element_in_button_scope();
+                self.insert_element(tag, attributes.to_vec());
+                self.mode = InsertionMode::InTable;
+              }
+              "area" | "br" | "embed" | "img" | "keygen" | "wbr" | "input" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(tag, attributes.to_vec());
+                self.stack_of_open_elements.pop();
+              }
+              "image" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_element("img", attributes.to_vec());
+                self.stack_of_open_elements.pop();
+              }
+              "param" | "source" | "track" => {
+                self.insert_element(tag, attributes.to_vec());
+                self.stack_of_open_elements.pop();
+              }
+              "hr" => {
+                self.close_p_element_in_button_scope();
+                self.insert_element(tag, attributes.to_vec());
+                self.stack_of_open_elements.pop();
+              }
+              "optgroup" | "option" => {
+                if is_element_named(&self.current_node(), &["option"]) {
+                  self.stack_of_open_elements.pop();
+                }
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(tag, attributes.to_vec());
+              }
+              "math" | "svg" => {
+                self.reconstruct_active_formatting_elements();
+                let namespace = if tag == "svg" { Namespace::Svg } else { Namespace::MathMl };
+                self.insert_foreign_element(tag, attributes.to_vec(), namespace);
+                if let HtmlToken::StartTag { self_closing: true, .. } = token {
+                  self.stack_of_open_elements.pop();
                 }
               }
+              "caption" | "col" | "colgroup" | "frame" | "head" | "tbody" | "td" | "tfoot"
+              | "th" | "thead" | "tr" => {}
+              _ => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(tag, attributes.to_vec());
+              }
             }
-            Some(HtmlToken::EndTag { ref tag }) => {
-              match tag.as_str() {
-                "body" => {
+          }
+          HtmlToken::EndTag { ref tag } => {
+            match tag.as_str() {
+              "template" => {
+                self.process_token(InsertionMode::InHead, token);
+              }
+              "body" => {
+                if self.has_element_in_scope(&["body"]) {
                   self.mode = InsertionMode::AfterBody;
-                  token = self.t.next();
-                  if !self.contain_in_stack(ElementKind::Body) {
-                    continue;
-                  }
-                  self.pop_until(ElementKind::Body);
-                  continue;
                 }
-                "html" => {
-                  if self.pop_current_node(ElementKind::Body) {
-                    self.mode = InsertionMode::AfterBody;
-                    assert!(self.pop_current_node(ElementKind::Html));
-                  } else {
-                    token = self.t.next();
+              }
+              "html" => {
+                if self.has_element_in_scope(&["body"]) {
+                  self.mode = InsertionMode::AfterBody;
+                  self.process_token(self.mode, token);
+                }
+              }
+              "address" | "article" | "aside" | "blockquote" | "button" | "center"
+              | "details" | "dialog" | "dir" | "div" | "dl" | "fieldset" | "figcaption"
+              | "figure" | "footer" | "header" | "hgroup" | "listing" | "main" | "menu"
+              | "nav" | "ol" | "pre" | "search" | "section" | "summary" | "ul" => {
+                if self.has_element_in_scope(&[tag]) {
+                  self.generate_implied_end_tags(None);
+                  self.pop_until(&[tag]);
+                }
+              }
+              "form" => {
+                if let Some(form) = self.form_element.take() {
+                  if self.has_node_in_scope(&form) {
+                    self.generate_implied_end_tags(None);
+                    self.remove_from_stack(&form);
                   }
-                  continue;
                 }
-                "p" => {
-                  let element_kind = ElementKind::from_str(tag).expect("failed to parse ElementKind");
-                  token = self.t.next();
-                  self.pop_until(element_kind);
-                  continue;
+              }
+              "p" => {
+                if !self.has_element_in_specific_scope(&["p"], BUTTON_SCOPE) {
+                  self.insert_element("p", Vec::new());
                 }
-                "h1" | "h2" => {
-                  let element_kind = ElementKind::from_str(tag).expect("failed to parse ElementKind");
-                  token = self.t.next();
-                  self.pop_until(element_kind);
-                  continue;
+                self.close_p_element();
+              }
+              "li" => {
+                if self.has_element_in_specific_scope(&["li"], LIST_ITEM_SCOPE) {
+                  self.generate_implied_end_tags(Some("li"));
+                  self.pop_until(&["li"]);
                 }
-                "a" => {
-                  let element_kind = ElementKind::from_str(tag).expect("failed to parse ElementKind");
-                  token = self.t.next();
-                  self.pop_until(element_kind);
-                  continue;
+              }
+              "dd" | "dt" => {
+                if self.has_element_in_scope(&[tag]) {
+                  self.generate_implied_end_tags(Some(tag));
+                  self.pop_until(&[tag]);
                 }
-                _ => {
-                  token = self.t.next();
+              }
+              "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                if self.has_element_in_scope(HEADINGS) {
+                  self.generate_implied_end_tags(None);
+                  self.pop_until(HEADINGS);
                 }
               }
+              "a" | "b" | "big" | "code" | "em" | "font" | "i" | "nobr" | "s" | "small"
+              | "strike" | "strong" | "tt" | "u" => {
+                if !self.run_adoption_agency(tag) {
+                  self.close_any_other_end_tag(tag);
+                }
+              }
+              "applet" | "marquee" | "object" => {
+                if self.has_element_in_scope(&[tag]) {
+                  self.generate_implied_end_tags(None);
+                  self.pop_until(&[tag]);
+                  self.clear_active_formatting_elements_to_last_marker();
+                }
+              }
+              "br" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_element("br", Vec::new());
+                self.stack_of_open_elements.pop();
+              }
+              _ => {
+                self.close_any_other_end_tag(tag);
+              }
             }
-            Some(HtmlToken::Eof) | None => {
-              return self.window.clone();
-            }
-            Some(HtmlToken::Char(c)) => {
-              self.insert_char(c);
-              token = self.t.next();
-              continue;
+          }
+          HtmlToken::Eof => {
+            if !self.template_insertion_modes.is_empty() {
+              self.process_token(InsertionMode::InTemplate, token);
             }
           }
+          HtmlToken::Char(c) => {
+            self.reconstruct_active_formatting_elements();
+            self.insert_char(*c);
+          }
+          HtmlToken::Comment(_) | HtmlToken::Doctype { .. } => unreachable!(),
         }
-        InsertionMode::Text => {
-          match token {
-            Some(HtmlToken::Eof) | None => {
-              return self.window.clone();
+      }
+      InsertionMode::Text => {
+        match token {
+          HtmlToken::Char(c) => {
+            self.insert_char(*c);
+          }
+          HtmlToken::EndTag { .. } => {
+            self.stack_of_open_elements.pop();
+            self.mode = self.original_insertion_mode;
+          }
+          _ => {
+            self.stack_of_open_elements.pop();
+            self.mode = self.original_insertion_mode;
+            self.process_token(self.mode, token);
+          }
+        }
+      }
+      InsertionMode::InTable => {
+        match token {
+          HtmlToken::Char(_) => {
+            if is_element_named(&self.current_node(), &["table", "tbody", "template", "tfoot", "thead", "tr"]) {
+              self.pending_table_characters = String::new();
+              self.original_insertion_mode = self.mode;
+              self.mode = InsertionMode::InTableText;
+              return self.process_token(self.mode, token);
+            }
+          }
+          HtmlToken::StartTag { ref tag, self_closing: _, ref attributes } => match tag.as_str() {
+            "caption" => {
+              self.clear_stack_back_to(&["table", "template", "html"]);
+              self.active_formatting_elements.push(ActiveFormattingElement::Marker);
+              self.insert_element(tag, attributes.to_vec());
+              self.mode = InsertionMode::InCaption;
+              return;
+            }
+            "colgroup" => {
+              self.clear_stack_back_to(&["table", "template", "html"]);
+              self.insert_element(tag, attributes.to_vec());
+              self.mode = InsertionMode::InColumnGroup;
+              return;
             }
-            Some(HtmlToken::EndTag { ref tag }) => {
-              if tag == "style" {
-                self.pop_until(ElementKind::Style);
-                self.mode = self.original_insertion_mode;
-                token = self.t.next();
-                continue;
+            "col" => {
+              self.clear_stack_back_to(&["table", "template", "html"]);
+              self.insert_element("colgroup", Vec::new());
+              self.mode = InsertionMode::InColumnGroup;
+              return self.process_token(self.mode, token);
+            }
+            "tbody" | "tfoot" | "thead" => {
+              self.clear_stack_back_to(&["table", "template", "html"]);
+              self.insert_element(tag, attributes.to_vec());
+              self.mode = InsertionMode::InTableBody;
+              return;
+            }
+            "td" | "th" | "tr" => {
+              self.clear_stack_back_to(&["table", "template", "html"]);
+              self.insert_element("tbody", Vec::new());
+              self.mode = InsertionMode::InTableBody;
+              return self.process_token(self.mode, token);
+            }
+            "table" => {
+              if self.has_element_in_specific_scope(&["table"], TABLE_SCOPE) {
+                self.pop_until(&["table"]);
+                self.reset_insertion_mode_appropriately();
+                return self.process_token(self.mode, token);
               }
-              if tag == "script" {
-                self.pop_until(ElementKind::Script);
-                self.mode = self.original_insertion_mode;
-                token = self.t.next();
-                continue;
+              return;
+            }
+            "style" | "script" | "template" => {
+              return self.process_token(InsertionMode::InHead, token);
+            }
+            "input" => {
+              let hidden = attributes
+                .iter()
+                .any(|attr| attr.name() == "type" && attr.value().eq_ignore_ascii_case("hidden"));
+              if hidden {
+                self.insert_element(tag, attributes.to_vec());
+                self.stack_of_open_elements.pop();
+                return;
               }
             }
-            Some(HtmlToken::Char(c)) => {
-              self.insert_char(c);
-              token = self.t.next();
-              continue;
+            "form" => {
+              if self.form_element.is_none() {
+                let form = self.insert_element(tag, attributes.to_vec());
+                self.form_element = Some(form);
+                self.stack_of_open_elements.pop();
+              }
+              return;
             }
             _ => {}
-          }
-          self.mode = self.original_insertion_mode;
+          },
+          HtmlToken::EndTag { ref tag } => match tag.as_str() {
+            "table" => {
+              if self.has_element_in_specific_scope(&["table"], TABLE_SCOPE) {
+                self.pop_until(&["table"]);
+                self.reset_insertion_mode_appropriately();
+              }
+              return;
+            }
+            "body" | "caption" | "col" | "colgroup" | "html" | "tbody" | "td" | "tfoot"
+            | "th" | "thead" | "tr" => return,
+            "template" => return self.process_token(InsertionMode::InHead, token),
+            _ => {}
+          },
+          HtmlToken::Eof => return self.process_token(InsertionMode::InBody, token),
+          HtmlToken::Comment(_) | HtmlToken::Doctype { .. } => unreachable!(),
         }
-        InsertionMode::AfterBody => {
-          match token {
-            Some(HtmlToken::Char(_c)) => {
-              token = self.t.next();
-              continue;
+        self.foster_parenting = true;
+        self.process_token(InsertionMode::InBody, token);
+        self.foster_parenting = false;
+      }
+      InsertionMode::InTableText => {
+        match token {
+          HtmlToken::Char('\0') => {}
+          HtmlToken::Char(c) => self.pending_table_characters.push(*c),
+          _ => {
+            let pending = core::mem::take(&mut self.pending_table_characters);
+            if pending.chars().any(|c| !c.is_ascii_whitespace()) {
+              self.foster_parenting = true;
+              for c in pending.chars() {
+                self.process_token(InsertionMode::InBody, &HtmlToken::Char(c));
+              }
+              self.foster_parenting = false;
+            } else {
+              for c in pending.chars() {
+                self.insert_char(c);
+              }
             }
-            Some(HtmlToken::EndTag { ref tag }) => {
-              if tag == "html" {
-                self.mode = InsertionMode::AfterAfterBody;
-                token = self.t.next();
-                continue;
+            self.mode = self.original_insertion_mode;
+            self.process_token(self.mode, token);
+          }
+        }
+      }
+      InsertionMode::InCaption => {
+        match token {
+          HtmlToken::StartTag { ref tag, .. } => match tag.as_str() {
+            "caption" | "col" | "colgroup" | "tbody" | "td" | "tfoot" | "th" | "thead" | "tr" => {
+              if self.close_caption() {
+                self.process_token(self.mode, token);
               }
+              return;
             }
-            Some(HtmlToken::Eof) | None => {
-              return self.window.clone();
+            _ => {}
+          },
+          HtmlToken::EndTag { ref tag } => match tag.as_str() {
+            "caption" => {
+              self.close_caption();
+              return;
             }
+            "table" => {
+              if self.close_caption() {
+                self.process_token(self.mode, token);
+              }
+              return;
+            }
+            "body" | "col" | "colgroup" | "html" | "tbody" | "td" | "tfoot" | "th" | "thead"
+            | "tr" => return,
             _ => {}
+          },
+          _ => {}
+        }
+        self.process_token(InsertionMode::InBody, token)
+      }
+      InsertionMode::InColumnGroup => {
+        match token {
+          HtmlToken::Char(c) => {
+            if c.is_ascii_whitespace() {
+              self.insert_char(*c);
+              return;
+            }
           }
-          self.mode = InsertionMode::InBody;
+          HtmlToken::StartTag { ref tag, self_closing: _, ref attributes } => match tag.as_str() {
+            "html" => return self.process_token(InsertionMode::InBody, token),
+            "col" => {
+              self.insert_element(tag, attributes.to_vec());
+              self.stack_of_open_elements.pop();
+              return;
+            }
+            "template" => return self.process_token(InsertionMode::InHead, token),
+            _ => {}
+          },
+          HtmlToken::EndTag { ref tag } => match tag.as_str() {
+            "template" => return self.process_token(InsertionMode::InHead, token),
+            "colgroup" => {
+              if is_element_named(&self.current_node(), &["colgroup"]) {
+                self.stack_of_open_elements.pop();
+                self.mode = InsertionMode::InTable;
+              }
+              return;
+            }
+            "col" => return,
+            _ => {}
+          },
+          HtmlToken::Eof => return self.process_token(InsertionMode::InBody, token),
+          HtmlToken::Comment(_) | HtmlToken::Doctype { .. } => unreachable!(),
+        }
+        if is_element_named(&self.current_node(), &["colgroup"]) {
+          self.stack_of_open_elements.pop();
+          self.mode = InsertionMode::InTable;
+          self.process_token(self.mode, token);
         }
-        InsertionMode::AfterAfterBody => {
-          match token {
-            Some(HtmlToken::Char(_c)) => {
-              token = self.t.next();
-              continue;
+      }
+      InsertionMode::InTableBody => {
+        match token {
+          HtmlToken::StartTag { ref tag, self_closing: _, ref attributes } => match tag.as_str() {
+            "tr" => {
+              self.clear_stack_back_to(&["tbody", "tfoot", "thead", "template", "html"]);
+              self.insert_element(tag, attributes.to_vec());
+              self.mode = InsertionMode::InRow;
+              return;
+            }
+            "th" | "td" => {
+              self.clear_stack_back_to(&["tbody", "tfoot", "thead", "template", "html"]);
+              self.insert_element("tr", Vec::new());
+              self.mode = InsertionMode::InRow;
+              return self.process_token(self.mode, token);
             }
-            Some(HtmlToken::Eof) | None => {
-              return self.window.clone();
+            "caption" | "col" | "colgroup" | "tbody" | "tfoot" | "thead" => {
+              if self.close_table_body() {
+                self.process_token(self.mode, token);
+              }
+              return;
             }
             _ => {}
+          },
+          HtmlToken::EndTag { ref tag } => match tag.as_str() {
+            "tbody" | "tfoot" | "thead" => {
+              if self.has_element_in_specific_scope(&[tag], TABLE_SCOPE) {
+                self.clear_stack_back_to(&["tbody", "tfoot", "thead", "template", "html"]);
+                self.stack_of_open_elements.pop();
+                self.mode = InsertionMode::InTable;
+              }
+              return;
+            }
+            "table" => {
+              if self.close_table_body() {
+                self.process_token(self.mode, token);
+              }
+              return;
+            }
+            "body" | "caption" | "col" | "colgroup" | "html" | "td" | "th" | "tr" => return,
+            _ => {}
+          },
+          _ => {}
+        }
+        self.process_token(InsertionMode::InTable, token)
+      }
+      InsertionMode::InRow => {
+        match token {
+          HtmlToken::StartTag { ref tag, self_closing: _, ref attributes } => match tag.as_str() {
+            "th" | "td" => {
+              self.clear_stack_back_to(&["tr", "template", "html"]);
+              self.insert_element(tag, attributes.to_vec());
+              self.mode = InsertionMode::InCell;
+              self.active_formatting_elements.push(ActiveFormattingElement::Marker);
+              return;
+            }
+            "caption" | "col" | "colgroup" | "tbody" | "tfoot" | "thead" | "tr" => {
+              if self.close_table_row() {
+                self.process_token(self.mode, token);
+              }
+              return;
+            }
+            _ => {}
+          },
+          HtmlToken::EndTag { ref tag } => match tag.as_str() {
+            "tr" => {
+              self.close_table_row();
+              return;
+            }
+            "table" => {
+              if self.close_table_row() {
+                self.process_token(self.mode, token);
+              }
+              return;
+            }
+            "tbody" | "tfoot" | "thead" => {
+              if self.has_element_in_specific_scope(&[tag], TABLE_SCOPE) && self.close_table_row() {
+                self.process_token(self.mode, token);
+              }
+              return;
+            }
+            "body" | "caption" | "col" | "colgroup" | "html" | "td" | "th" => return,
+            _ => {}
+          },
+          _ => {}
+        }
+        self.process_token(InsertionMode::InTable, token)
+      }
+      InsertionMode::InCell => {
+        match token {
+          HtmlToken::StartTag { ref tag, .. } => match tag.as_str() {
+            "caption" | "col" | "colgroup" | "tbody" | "td" | "tfoot" | "th" | "thead" | "tr" => {
+              if self.has_element_in_specific_scope(&["td", "th"], TABLE_SCOPE) {
+                self.close_cell();
+                self.process_token(self.mode, token);
+              }
+              return;
+            }
+            _ => {}
+          },
+          HtmlToken::EndTag { ref tag } => match tag.as_str() {
+            "td" | "th" => {
+              if self.has_element_in_specific_scope(&[tag], TABLE_SCOPE) {
+                self.generate_implied_end_tags(None);
+                self.pop_until(&[tag]);
+                self.clear_active_formatting_elements_to_last_marker();
+                self.mode = InsertionMode::InRow;
+              }
+              return;
+            }
+            "body" | "caption" | "col" | "colgroup" | "html" => return,
+            "table" | "tbody" | "tfoot" | "thead" | "tr" => {
+              if self.has_element_in_specific_scope(&[tag], TABLE_SCOPE) {
+                self.close_cell();
+                self.process_token(self.mode, token);
+              }
+              return;
+            }
+            _ => {}
+          },
+          _ => {}
+        }
+        self.process_token(InsertionMode::InBody, token)
+      }
+      InsertionMode::InTemplate => {
+        match token {
+          HtmlToken::Char(_) => self.process_token(InsertionMode::InBody, token),
+          HtmlToken::StartTag { ref tag, .. } => {
+            let mode = match tag.as_str() {
+              "base" | "basefont" | "bgsound" | "link" | "meta" | "noframes" | "script"
+              | "style" | "template" | "title" => {
+                return self.process_token(InsertionMode::InHead, token);
+              }
+              "caption" | "colgroup" | "tbody" | "tfoot" | "thead" => InsertionMode::InTable,
+              "col" => InsertionMode::InColumnGroup,
+              "tr" => InsertionMode::InTableBody,
+              "td" | "th" => InsertionMode::InRow,
+              _ => InsertionMode::InBody,
+            };
+            self.template_insertion_modes.pop();
+            self.template_insertion_modes.push(mode);
+            self.mode = mode;
+            self.process_token(self.mode, token);
+          }
+          HtmlToken::EndTag { ref tag } => {
+            if tag == "template" {
+              self.process_token(InsertionMode::InHead, token);
+            }
           }
-          self.mode = InsertionMode::InBody;
+          HtmlToken::Eof => {
+            if !self.contain_in_stack(&["template"]) {
+              return;
+            }
+            self.pop_until(&["template"]);
+            self.clear_active_formatting_elements_to_last_marker();
+            self.template_insertion_modes.pop();
+            self.reset_insertion_mode_appropriately();
+            self.process_token(self.mode, token);
+          }
+          HtmlToken::Comment(_) | HtmlToken::Doctype { .. } => unreachable!(),
         }
       }
+      InsertionMode::AfterBody => {
+        match token {
+          HtmlToken::Char(c) => {
+            if c.is_ascii_whitespace() {
+              return self.process_token(InsertionMode::InBody, token);
+            }
+          }
+          HtmlToken::StartTag { ref tag, .. } => {
+            if tag == "html" {
+              return self.process_token(InsertionMode::InBody, token);
+            }
+          }
+          HtmlToken::EndTag { ref tag } => {
+            if tag == "html" {
+              self.mode = InsertionMode::AfterAfterBody;
+              return;
+            }
+          }
+          HtmlToken::Eof => return,
+          HtmlToken::Comment(_) | HtmlToken::Doctype { .. } => unreachable!(),
+        }
+        self.mode = InsertionMode::InBody;
+        self.process_token(self.mode, token)
+      }
+      InsertionMode::AfterAfterBody => {
+        match token {
+          HtmlToken::Char(c) => {
+            if c.is_ascii_whitespace() {
+              return self.process_token(InsertionMode::InBody, token);
+            }
+          }
+          HtmlToken::StartTag { ref tag, .. } => {
+            if tag == "html" {
+              return self.process_token(InsertionMode::InBody, token);
+            }
+          }
+          HtmlToken::Eof => return,
+          _ => {}
+        }
+        self.mode = InsertionMode::InBody;
+        self.process_token(self.mode, token)
+      }
     }
-    self.window.clone()
   }
 
   fn create_element(&self, tag: &str, attributes: Vec<Attribute>) -> Node {
     Node::new(NodeKind::Element(Element::new(tag, attributes)))
   }
 
-  fn insert_element(&mut self, tag: &str, attributes: Vec<Attribute>) {
-    let window = self.window.borrow();
-    let current = match self.stack_of_open_elements.last() {
+  fn adjusted_current_node(&self) -> Option<Rc<RefCell<Node>>> {
+    if self.stack_of_open_elements.len() == 1 && self.context_element.is_some() {
+      return self.context_element.clone();
+    }
+    self.stack_of_open_elements.last().cloned()
+  }
+
+  fn current_node(&self) -> Rc<RefCell<Node>> {
+    match self.stack_of_open_elements.last() {
       Some(node) => node.clone(),
-      None => window.document(),
-    };
-    let node = Rc::new(RefCell::new(self.create_element(tag, attributes)));
+      None => self.window.borrow().document(),
+    }
+  }
 
-    if current.borrow().first_child().is_some() {
-      let mut last_sibling = current.borrow().first_child();
-      loop {
-        last_sibling = match last_sibling {
-          Some(ref node) => {
-            if node.borrow().next_sibling().is_some() {
-              node.borrow().next_sibling()
-            } else {
-              break;
-            }
-          }
-          None => unimplemented!("last_sibling is None"),
-        };
+  fn appropriate_place_for_inserting_node(
+    &self,
+    override_target: Option<Rc<RefCell<Node>>>,
+  ) -> (Rc<RefCell<Node>>, Option<Rc<RefCell<Node>>>) {
+    let target = override_target.unwrap_or_else(|| self.current_node());
+    let (parent, before) =
+      if !self.foster_parenting || !is_element_named(&target, &["table", "tbody", "tfoot", "thead", "tr"]) {
+        (target, None)
+      } else {
+        self.foster_parenting_place()
+      };
+
+    if is_element_named(&parent, &["template"]) {
+      if let Some(contents) = parent.borrow().template_contents() {
+        return (contents, None);
+      }
+    }
+    (parent, before)
+  }
+
+  fn foster_parenting_place(&self) -> (Rc<RefCell<Node>>, Option<Rc<RefCell<Node>>>) {
+    let last_template = self
+      .stack_of_open_elements
+      .iter()
+      .rposition(|node| is_element_named(node, &["template"]));
+    let last_table = self
+      .stack_of_open_elements
+      .iter()
+      .rposition(|node| is_element_named(node, &["table"]));
+    if let Some(i) = last_template {
+      let template_is_lower = match last_table {
+        Some(j) => i > j,
+        None => true,
+      };
+      if template_is_lower {
+        return (self.stack_of_open_elements[i].clone(), None);
+      }
+    }
+
+    match last_table {
+      Some(i) => {
+        let table = self.stack_of_open_elements[i].clone();
+        let parent = table.borrow().parent().upgrade();
+        match parent {
+          Some(parent) => (parent, Some(table)),
+          None => (self.stack_of_open_elements[i - 1].clone(), None),
+        }
       }
+      None => (self.stack_of_open_elements[0].clone(), None),
+    }
+  }
 
-      last_sibling.unwrap().borrow_mut().set_next_sibling(Some(node.clone()));
-      node.borrow_mut().set_previous_sibling(
-        Rc::downgrade(&current.borrow().first_child().expect("first_child is None")),
-      );
-    } else {
-      current.borrow_mut().set_first_child(Some(node.clone()));
+  fn insert_element(&mut self, tag: &str, attributes: Vec<Attribute>) -> Rc<RefCell<Node>> {
+    self.discard_whitespace_text();
+    let (parent, before) = self.appropriate_place_for_inserting_node(None);
+    let node = Rc::new(RefCell::new(self.create_element(tag, attributes)));
+    if tag == "template" {
+      node.borrow_mut().set_template_contents(Some(create_document_fragment()));
     }
 
-    current.borrow_mut().set_last_child(Rc::downgrade(&node));
-    node.borrow_mut().set_parent(Rc::downgrade(&current));
+    insert_before(&parent, &node, before.as_ref()).expect("node should be insertable");
 
-    self.stack_of_open_elements.push(node);
+    self.stack_of_open_elements.push(node.clone());
+    node
   }
 
-  fn pop_current_node(&mut self, element_kind: ElementKind) -> bool {
-    let current = match self.stack_of_open_elements.last() {
-      Some(node) => node,
-      None => return false,
+  fn insert_foreign_element(
+    &mut self,
+    tag: &str,
+    mut attributes: Vec<Attribute>,
+    namespace: Namespace,
+  ) -> Rc<RefCell<Node>> {
+    let tag = match namespace {
+      Namespace::Svg => {
+        adjust_svg_attributes(&mut attributes);
+        adjust_foreign_attributes(&mut attributes);
+        adjust_svg_tag_name(tag)
+      }
+      Namespace::MathMl => {
+        adjust_mathml_attributes(&mut attributes);
+        adjust_foreign_attributes(&mut attributes);
+        String::from(tag)
+      }
+      Namespace::Html => String::from(tag),
     };
 
-    if current.borrow().element_kind() == Some(element_kind) {
+    self.discard_whitespace_text();
+    let (parent, before) = self.appropriate_place_for_inserting_node(None);
+    let node = Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new_with_namespace(
+      &tag, attributes, namespace,
+    )))));
+
+    insert_before(&parent, &node, before.as_ref()).expect("node should be insertable");
+
+    self.stack_of_open_elements.push(node.clone());
+    node
+  }
+
+  fn pop_until_html_content(&mut self) {
+    while let Some(node) = self.stack_of_open_elements.last() {
+      if is_in_namespace(node, Namespace::Html)
+        || is_mathml_text_integration_point(node)
+        || is_html_integration_point(node)
+      {
+        return;
+      }
       self.stack_of_open_elements.pop();
-      return true;
     }
+  }
 
-    false
+  fn parse_text_element(&mut self, tag: &str, attributes: Vec<Attribute>, state: State) {
+    self.insert_element(tag, attributes);
+    self.t.switch_to(state);
+    self.original_insertion_mode = self.mode;
+    self.mode = InsertionMode::Text;
   }
 
-  fn pop_until(&mut self, element_kind: ElementKind) {
-    assert!(self.contain_in_stack(element_kind), "element_kind is not in stack");
+  fn pop_until(&mut self, tags: &[&str]) {
+    if !self.contain_in_stack(tags) {
+      return;
+    }
     loop {
       let current = match self.stack_of_open_elements.pop() {
         Some(node) => node,
         None => return,
       };
 
-      if current.borrow().element_kind() == Some(element_kind) {
+      if is_element_named(&current, tags) {
+        return;
+      }
+    }
+  }
+
+  fn contain_in_stack(&self, tags: &[&str]) -> bool {
+    self.stack_of_open_elements.iter().any(|node| is_element_named(node, tags))
+  }
+
+  fn position_in_stack(&self, node: &Rc<RefCell<Node>>) -> Option<usize> {
+    self.stack_of_open_elements.iter().position(|n| Rc::ptr_eq(n, node))
+  }
+
+  fn remove_from_stack(&mut self, node: &Rc<RefCell<Node>>) {
+    if let Some(i) = self.position_in_stack(node) {
+      self.stack_of_open_elements.remove(i);
+    }
+  }
+
+  fn has_element_in_specific_scope(&self, tags: &[&str], scope: &[&str]) -> bool {
+    for node in self.stack_of_open_elements.iter().rev() {
+      if is_element_named(node, tags) {
+        return true;
+      }
+      if is_element_named(node, scope) || (scope != TABLE_SCOPE && is_foreign_scope_element(node)) {
+        return false;
+      }
+    }
+    false
+  }
+
+  fn has_element_in_scope(&self, tags: &[&str]) -> bool {
+    self.has_element_in_specific_scope(tags, DEFAULT_SCOPE)
+  }
+
+  fn has_node_in_scope(&self, target: &Rc<RefCell<Node>>) -> bool {
+    for node in self.stack_of_open_elements.iter().rev() {
+      if Rc::ptr_eq(node, target) {
+        return true;
+      }
+      if is_element_named(node, DEFAULT_SCOPE) || is_foreign_scope_element(node) {
+        return false;
+      }
+    }
+    false
+  }
+
+  fn generate_implied_end_tags(&mut self, exception: Option<&str>) {
+    loop {
+      let current = match self.stack_of_open_elements.last() {
+        Some(node) => node.clone(),
+        None => return,
+      };
+      if !is_element_named(&current, IMPLIED_END_TAGS) {
+        return;
+      }
+      if let Some(tag) = exception {
+        if is_element_named(&current, &[tag]) {
+          return;
+        }
+      }
+      self.stack_of_open_elements.pop();
+    }
+  }
+
+  fn generate_implied_end_tags_thoroughly(&mut self) {
+    while is_element_named(&self.current_node(), THOROUGHLY_IMPLIED_END_TAGS) {
+      self.stack_of_open_elements.pop();
+    }
+  }
+
+  fn close_p_element(&mut self) {
+    self.generate_implied_end_tags(Some("p"));
+    self.pop_until(&["p"]);
+  }
+
+  fn close_p_element_in_button_scope(&mut self) {
+    if self.has_element_in_specific_scope(&["p"], BUTTON_SCOPE) {
+      self.close_p_element();
+    }
+  }
+
+  fn close_list_item(&mut self, tags: &[&str]) {
+    for i in (0..self.stack_of_open_elements.len()).rev() {
+      let node = self.stack_of_open_elements[i].clone();
+      if is_element_named(&node, tags) {
+        let tag = match node.borrow().get_element() {
+          Some(element) => element.tag_name(),
+          None => return,
+        };
+        self.generate_implied_end_tags(Some(&tag));
+        self.pop_until(&[&tag]);
+        return;
+      }
+      if is_special(&node) && !is_element_named(&node, &["address", "div", "p"]) {
+        return;
+      }
+    }
+  }
+
+  fn close_any_other_end_tag(&mut self, tag: &str) {
+    for i in (0..self.stack_of_open_elements.len()).rev() {
+      let node = self.stack_of_open_elements[i].clone();
+      if is_element_named(&node, &[tag]) {
+        self.generate_implied_end_tags(Some(tag));
+        self.stack_of_open_elements.truncate(i);
+        return;
+      }
+      if is_special(&node) {
+        return;
+      }
+    }
+  }
+
+  fn clear_stack_back_to(&mut self, tags: &[&str]) {
+    while !is_element_named(&self.current_node(), tags) {
+      if self.stack_of_open_elements.pop().is_none() {
+        return;
+      }
+    }
+  }
+
+  fn close_caption(&mut self) -> bool {
+    if !self.has_element_in_specific_scope(&["caption"], TABLE_SCOPE) {
+      return false;
+    }
+    self.generate_implied_end_tags(None);
+    self.pop_until(&["caption"]);
+    self.clear_active_formatting_elements_to_last_marker();
+    self.mode = InsertionMode::InTable;
+    true
+  }
+
+  fn close_table_body(&mut self) -> bool {
+    if !self.has_element_in_specific_scope(&["tbody", "thead", "tfoot"], TABLE_SCOPE) {
+      return false;
+    }
+    self.clear_stack_back_to(&["tbody", "tfoot", "thead", "template", "html"]);
+    self.stack_of_open_elements.pop();
+    self.mode = InsertionMode::InTable;
+    true
+  }
+
+  fn close_table_row(&mut self) -> bool {
+    if !self.has_element_in_specific_scope(&["tr"], TABLE_SCOPE) {
+      return false;
+    }
+    self.clear_stack_back_to(&["tr", "template", "html"]);
+    self.stack_of_open_elements.pop();
+    self.mode = InsertionMode::InTableBody;
+    true
+  }
+
+  fn close_cell(&mut self) {
+    self.generate_implied_end_tags(None);
+    self.pop_until(&["td", "th"]);
+    self.clear_active_formatting_elements_to_last_marker();
+    self.mode = InsertionMode::InRow;
+  }
+
+  fn reset_insertion_mode_appropriately(&mut self) {
+    for (i, node) in self.stack_of_open_elements.iter().enumerate().rev() {
+      let last = i == 0;
+      let node = match self.context_element {
+        Some(ref context) if last => context,
+        _ => node,
+      };
+      let tag = match node.borrow().get_element() {
+        Some(element) if element.namespace() == Namespace::Html => element.tag_name(),
+        _ if last => String::new(),
+        _ => continue,
+      };
+      self.mode = match tag.as_str() {
+        "td" | "th" if !last => InsertionMode::InCell,
+        "tr" => InsertionMode::InRow,
+        "tbody" | "thead" | "tfoot" => InsertionMode::InTableBody,
+        "caption" => InsertionMode::InCaption,
+        "colgroup" => InsertionMode::InColumnGroup,
+        "table" => InsertionMode::InTable,
+        "template" => self.template_insertion_modes.last().copied().unwrap_or(InsertionMode::InTemplate),
+        "head" if !last => InsertionMode::InHead,
+        "body" => InsertionMode::InBody,
+        "html" => {
+          if self.head_element.is_none() {
+            InsertionMode::BeforeHead
+          } else {
+            InsertionMode::AfterHead
+          }
+        }
+        _ if last => InsertionMode::InBody,
+        _ => continue,
+      };
+      return;
+    }
+  }
+
+  fn position_in_active_formatting_elements(&self, node: &Rc<RefCell<Node>>) -> Option<usize> {
+    self.active_formatting_elements.iter().position(|entry| match entry {
+      ActiveFormattingElement::Element(n) => Rc::ptr_eq(n, node),
+      ActiveFormattingElement::Marker => false,
+    })
+  }
+
+  fn remove_from_active_formatting_elements(&mut self, node: &Rc<RefCell<Node>>) {
+    if let Some(i) = self.position_in_active_formatting_elements(node) {
+      self.active_formatting_elements.remove(i);
+    }
+  }
+
+  fn find_active_formatting_element(&self, tag: &str) -> Option<Rc<RefCell<Node>>> {
+    for entry in self.active_formatting_elements.iter().rev() {
+      match entry {
+        ActiveFormattingElement::Marker => return None,
+        ActiveFormattingElement::Element(node) => {
+          if is_element_named(node, &[tag]) {
+            return Some(node.clone());
+          }
+        }
+      }
+    }
+    None
+  }
+
+  fn push_active_formatting_element(&mut self, node: Rc<RefCell<Node>>) {
+    let mut count = 0;
+    let mut earliest = None;
+    for (i, entry) in self.active_formatting_elements.iter().enumerate().rev() {
+      match entry {
+        ActiveFormattingElement::Marker => break,
+        ActiveFormattingElement::Element(n) => {
+          if n.borrow().kind() == node.borrow().kind() {
+            count += 1;
+            earliest = Some(i);
+          }
+        }
+      }
+    }
+    if count >= 3 {
+      if let Some(i) = earliest {
+        self.active_formatting_elements.remove(i);
+      }
+    }
+    self.active_formatting_elements.push(ActiveFormattingElement::Element(node));
+  }
+
+  fn clear_active_formatting_elements_to_last_marker(&mut self) {
+    while let Some(entry) = self.active_formatting_elements.pop() {
+      if let ActiveFormattingElement::Marker = entry {
         return;
       }
     }
   }
 
-  fn contain_in_stack(&self, element_kind: ElementKind) -> bool {
-    for i in 0..self.stack_of_open_elements.len() {
-      if self.stack_of_open_elements[i].borrow().element_kind() == Some(element_kind) {
-        return true;
+  fn is_marker_or_open(&self, index: usize) -> bool {
+    match &self.active_formatting_elements[index] {
+      ActiveFormattingElement::Marker => true,
+      ActiveFormattingElement::Element(node) => self.position_in_stack(node).is_some(),
+    }
+  }
+
+  fn reconstruct_active_formatting_elements(&mut self) {
+    let len = self.active_formatting_elements.len();
+    if len == 0 || self.is_marker_or_open(len - 1) {
+      return;
+    }
+
+    let mut i = len - 1;
+    while i > 0 && !self.is_marker_or_open(i - 1) {
+      i -= 1;
+    }
+
+    for j in i..len {
+      let element = match &self.active_formatting_elements[j] {
+        ActiveFormattingElement::Element(node) => node.borrow().get_element(),
+        ActiveFormattingElement::Marker => None,
+      };
+      if let Some(element) = element {
+        let node = self.insert_element(&element.tag_name(), element.attributes());
+        self.active_formatting_elements[j] = ActiveFormattingElement::Element(node);
+      }
+    }
+  }
+
+  /// Runs the adoption agency algorithm for the end tag `subject`. Returns false when the
+  /// caller should fall back to the "any other end tag" steps.
+  fn run_adoption_agency(&mut self, subject: &str) -> bool {
+    let current = self.current_node();
+    if is_element_named(&current, &[subject])
+      && self.position_in_active_formatting_elements(&current).is_none()
+    {
+      self.stack_of_open_elements.pop();
+      return true;
+    }
+
+    for _ in 0..8 {
+      let formatting_element = match self.find_active_formatting_element(subject) {
+        Some(node) => node,
+        None => return false,
+      };
+
+      let formatting_element_index = match self.position_in_stack(&formatting_element) {
+        Some(i) => i,
+        None => {
+          self.remove_from_active_formatting_elements(&formatting_element);
+          return true;
+        }
+      };
+
+      if !self.has_node_in_scope(&formatting_element) {
+        return true;
+      }
+
+      let furthest_block = match self.stack_of_open_elements[formatting_element_index + 1..]
+        .iter()
+        .find(|node| is_special(node))
+      {
+        Some(node) => node.clone(),
+        None => {
+          self.stack_of_open_elements.truncate(formatting_element_index);
+          self.remove_from_active_formatting_elements(&formatting_element);
+          return true;
+        }
+      };
+
+      let common_ancestor = match formatting_element_index
+        .checked_sub(1)
+        .and_then(|i| self.stack_of_open_elements.get(i))
+      {
+        Some(node) => node.clone(),
+        None => return true,
+      };
+      let mut bookmark = self
+        .position_in_active_formatting_elements(&formatting_element)
+        .expect("formatting element should be in the list");
+
+      let mut node_index = self.position_in_stack(&furthest_block).expect("furthest block should be in stack");
+      let mut last_node = furthest_block.clone();
+      let mut inner_loop_counter = 0;
+      loop {
+        inner_loop_counter += 1;
+        node_index -= 1;
+        let node = self.stack_of_open_elements[node_index].clone();
+        if Rc::ptr_eq(&node, &formatting_element) {
+          break;
+        }
+
+        let mut list_index = self.position_in_active_formatting_elements(&node);
+        if inner_loop_counter > 3 {
+          if let Some(i) = list_index {
+            self.active_formatting_elements.remove(i);
+            if i < bookmark {
+              bookmark -= 1;
+            }
+            list_index = None;
+          }
+        }
+
+        let list_index = match list_index {
+          Some(i) => i,
+          None => {
+            self.stack_of_open_elements.remove(node_index);
+            continue;
+          }
+        };
+
+        let new_node = Rc::new(RefCell::new(Node::new(node.borrow().kind())));
+        self.active_formatting_elements[list_index] = ActiveFormattingElement::Element(new_node.clone());
+        self.stack_of_open_elements[node_index] = new_node.clone();
+
+        if Rc::ptr_eq(&last_node, &furthest_block) {
+          bookmark = list_index + 1;
+        }
+
+        detach(&last_node);
+        append_child(&new_node, &last_node).expect("node should be insertable");
+        last_node = new_node;
+      }
+
+      detach(&last_node);
+      let (parent, before) = self.appropriate_place_for_inserting_node(Some(common_ancestor));
+      insert_before(&parent, &last_node, before.as_ref()).expect("node should be insertable");
+
+      let new_element = Rc::new(RefCell::new(Node::new(formatting_element.borrow().kind())));
+      loop {
+        let child = match furthest_block.borrow().first_child() {
+          Some(child) => child,
+          None => break,
+        };
+        detach(&child);
+        append_child(&new_element, &child).expect("node should be insertable");
+      }
+      append_child(&furthest_block, &new_element).expect("node should be insertable");
+
+      if let Some(i) = self.position_in_active_formatting_elements(&formatting_element) {
+        self.active_formatting_elements.remove(i);
+        if i < bookmark {
+          bookmark -= 1;
+        }
       }
+      self.active_formatting_elements.insert(bookmark, ActiveFormattingElement::Element(new_element.clone()));
+
+      self.remove_from_stack(&formatting_element);
+      let furthest_block_index = self.position_in_stack(&furthest_block).expect("furthest block should be in stack");
+      self.stack_of_open_elements.insert(furthest_block_index + 1, new_element);
     }
-    false
+
+    true
   }
 
   fn create_char(&self, c: char) -> Node {
@@ -404,33 +1771,126 @@ impl HtmlParser {
   }
 
   fn insert_char(&mut self, c: char) {
-    let current = match self.stack_of_open_elements.last() {
-      Some(node) => node.clone(),
-      None => return,
-    };
-
-    if let NodeKind::Text(ref mut s) = current.borrow_mut().kind {
-      s.push(c);
+    if self.stack_of_open_elements.is_empty() {
       return;
     }
 
-    if c == ' ' || c == '\n' {
+    let (parent, before) = self.appropriate_place_for_inserting_node(None);
+    if parent.borrow().kind == NodeKind::Document {
       return;
     }
 
+    let previous = match before {
+      Some(ref node) => node.borrow().previous_sibling().upgrade(),
+      None => parent.borrow().last_child().upgrade(),
+    };
+    if let Some(previous) = previous {
+      let appended = match previous.borrow_mut().kind {
+        NodeKind::Text(ref mut s) => {
+          s.push(c);
+          true
+        }
+        _ => false,
+      };
+      if appended {
+        // Appending to a text node queues no mutation record, but the layout still has to see
+        // the new data. The ancestors are only walked once until the flags are cleared.
+        if !previous.borrow().dirty_flags().layout {
+          mark_layout_dirty(&previous);
+        }
+        if !c.is_ascii_whitespace()
+          && self.whitespace_text.as_ref().is_some_and(|node| Rc::ptr_eq(node, &previous))
+        {
+          self.whitespace_text = None;
+        }
+        return;
+      }
+    }
+
+    self.discard_whitespace_text();
     let node = Rc::new(RefCell::new(self.create_char(c)));
+    insert_before(&parent, &node, before.as_ref()).expect("node should be insertable");
+    if self.whitespace_mode == WhitespaceMode::StripInterElement
+      && c.is_ascii_whitespace()
+      && !is_element_named(&parent, WHITESPACE_SIGNIFICANT_ELEMENTS)
+    {
+      self.whitespace_text = Some(node);
+    }
+  }
+
+  // Inserts a comment as the last child of `parent`, or at the appropriate place when it is None.
+  fn insert_comment(&mut self, data: &str, parent: Option<Rc<RefCell<Node>>>) {
+    self.discard_whitespace_text();
+    let (parent, before) = match parent {
+      Some(parent) => (parent, None),
+      None => self.appropriate_place_for_inserting_node(None),
+    };
+    let node = Rc::new(RefCell::new(Node::new(NodeKind::Comment(String::from(data)))));
+    insert_before(&parent, &node, before.as_ref()).expect("node should be insertable");
+  }
+
+  // Removes the most recent text node if it is still whitespace-only. It can no longer be merged
+  // with the text that follows it once an element or another text node has been inserted.
+  fn discard_whitespace_text(&mut self) {
+    if let Some(node) = self.whitespace_text.take() {
+      detach(&node);
+    }
+  }
+}
+
+fn is_head_content(tag: &str) -> bool {
+  matches!(
+    tag,
+    "base" | "basefont" | "bgsound" | "link" | "meta" | "noframes" | "script" | "style" | "title"
+  )
+}
 
-    if current.borrow().first_child().is_some() {
-      current.borrow().first_child().unwrap().borrow_mut().set_next_sibling(Some(node.clone()));
-      node.borrow_mut().set_previous_sibling(Rc::downgrade(&current.borrow().first_child().expect("first_child is None")));
-    } else {
-      current.borrow_mut().set_first_child(Some(node.clone()));
+fn is_element_in_namespace(node: &Rc<RefCell<Node>>, namespace: Namespace, tags: &[&str]) -> bool {
+  match node.borrow().kind {
+    NodeKind::Element(ref element) => {
+      element.namespace() == namespace && tags.contains(&element.tag_name().as_str())
     }
+    _ => false,
+  }
+}
+
+fn is_element_named(node: &Rc<RefCell<Node>>, tags: &[&str]) -> bool {
+  is_element_in_namespace(node, Namespace::Html, tags)
+}
+
+fn is_in_namespace(node: &Rc<RefCell<Node>>, namespace: Namespace) -> bool {
+  match node.borrow().kind {
+    NodeKind::Element(ref element) => element.namespace() == namespace,
+    _ => false,
+  }
+}
+
+fn is_special(node: &Rc<RefCell<Node>>) -> bool {
+  is_element_named(node, SPECIAL_ELEMENTS) || is_foreign_scope_element(node)
+}
+
+fn is_foreign_scope_element(node: &Rc<RefCell<Node>>) -> bool {
+  is_element_in_namespace(node, Namespace::MathMl, MATHML_SCOPE)
+    || is_element_in_namespace(node, Namespace::Svg, SVG_SCOPE)
+}
 
-    current.borrow_mut().set_last_child(Rc::downgrade(&node));
-    node.borrow_mut().set_parent(Rc::downgrade(&current));
+fn is_mathml_text_integration_point(node: &Rc<RefCell<Node>>) -> bool {
+  is_element_in_namespace(node, Namespace::MathMl, MATHML_TEXT_INTEGRATION_POINTS)
+}
 
-    self.stack_of_open_elements.push(node);
+fn is_html_integration_point(node: &Rc<RefCell<Node>>) -> bool {
+  if is_element_in_namespace(node, Namespace::Svg, SVG_SCOPE) {
+    return true;
+  }
+  if !is_element_in_namespace(node, Namespace::MathMl, &["annotation-xml"]) {
+    return false;
+  }
+  match node.borrow().get_element() {
+    Some(element) => element.get_attribute("encoding").is_some_and(|encoding| {
+      encoding.eq_ignore_ascii_case("text/html")
+        || encoding.eq_ignore_ascii_case("application/xhtml+xml")
+    }),
+    None => false,
   }
 }
 
@@ -440,9 +1900,18 @@ pub enum InsertionMode {
   BeforeHtml,
   BeforeHead,
   InHead,
+  InHeadNoscript,
   AfterHead,
   InBody,
   Text,
+  InTable,
+  InTableText,
+  InCaption,
+  InColumnGroup,
+  InTableBody,
+  InRow,
+  InCell,
+  InTemplate,
   AfterBody,
   AfterAfterBody,
 }
@@ -451,6 +1920,7 @@ pub enum InsertionMode {
 mod tests {
   use alloc::string::ToString;
   use super::*;
+  use crate::renderer::dom::node::ElementKind;
 
   #[test]
   fn test_empty() {
@@ -461,7 +1931,15 @@ mod tests {
     assert_eq!(
       Rc::new(RefCell::new(Node::new(NodeKind::Document))),
       document,
-    )
+    );
+
+    // html, head and body are still created at the end of the input.
+    let html = document.borrow().first_child().expect("first_child is None");
+    assert_eq!(Some(ElementKind::Html), html.borrow().element_kind());
+    let head = html.borrow().first_child().expect("first_child is None");
+    assert_eq!(Some(ElementKind::Head), head.borrow().element_kind());
+    let body = head.borrow().next_sibling().expect("next_sibling is None");
+    assert_eq!(Some(ElementKind::Body), body.borrow().element_kind());
   }
 
   #[test]
@@ -529,4 +2007,438 @@ mod tests {
       text,
     );
   }
-}
\ No newline at end of file
+
+  fn dump(node: &Rc<RefCell<Node>>) -> String {
+    let mut result = String::new();
+    let kind = node.borrow().kind();
+    match kind {
+      NodeKind::Element(ref e) => {
+        result.push('<');
+        result.push_str(&e.tag_name());
+        result.push('>');
+      }
+      NodeKind::Text(ref s) => result.push_str(s),
+      NodeKind::Comment(ref s) => {
+        result.push_str("<!--");
+        result.push_str(s);
+        result.push_str("-->");
+      }
+      NodeKind::DocumentType { ref name, .. } => {
+        result.push_str("<!DOCTYPE ");
+        result.push_str(name);
+        result.push('>');
+      }
+      NodeKind::Document | NodeKind::DocumentFragment => {}
+    }
+    let mut child = match node.borrow().template_contents() {
+      Some(contents) => contents.borrow().first_child(),
+      None => node.borrow().first_child(),
+    };
+    while let Some(c) = child {
+      result.push_str(&dump(&c));
+      child = c.borrow().next_sibling();
+    }
+    if let NodeKind::Element(ref e) = kind {
+      result.push_str("</");
+      result.push_str(&e.tag_name());
+      result.push('>');
+    }
+    result
+  }
+
+  fn parse(html: &str) -> String {
+    let t = HtmlTokenizer::new(html.to_string());
+    let window = super::HtmlParser::new(t).construct_tree();
+    let document = window.borrow().document();
+    dump(&document)
+  }
+
+  #[test]
+  fn test_implied_p_end_tag() {
+    assert_eq!(
+      "<html><head></head><body><p>one</p><p>two</p></body></html>",
+      parse("<p>one<p>two"),
+    );
+  }
+
+  #[test]
+  fn test_implied_li_end_tag() {
+    assert_eq!(
+      "<html><head></head><body><ul><li>a</li><li>b</li></ul></body></html>",
+      parse("<ul><li>a<li>b</ul>"),
+    );
+  }
+
+  #[test]
+  fn test_stray_p_end_tag() {
+    assert_eq!(
+      "<html><head></head><body><div>x<p></p></div></body></html>",
+      parse("<div>x</p></div>"),
+    );
+  }
+
+  #[test]
+  fn test_heading_closes_heading() {
+    assert_eq!(
+      "<html><head></head><body><h1>a</h1><h2>b</h2></body></html>",
+      parse("<h1>a<h2>b</h1>"),
+    );
+  }
+
+  #[test]
+  fn test_adoption_agency() {
+    assert_eq!(
+      "<html><head></head><body><b></b><p><b>x</b>y</p></body></html>",
+      parse("<b><p>x</b>y</p>"),
+    );
+    assert_eq!(
+      "<html><head></head><body><b>1</b><p><b>2</b>3</p></body></html>",
+      parse("<b>1<p>2</b>3</p>"),
+    );
+  }
+
+  #[test]
+  fn test_reconstruct_active_formatting_elements() {
+    assert_eq!(
+      "<html><head></head><body><p><b>bold<i>both</i></b></p><b><i>text</i></b></body></html>",
+      parse("<p><b>bold<i>both</p>text"),
+    );
+  }
+
+  #[test]
+  fn test_nested_anchor() {
+    assert_eq!(
+      "<html><head></head><body><a>1</a><a>2</a></body></html>",
+      parse("<a>1<a>2"),
+    );
+  }
+
+  #[test]
+  fn test_sibling_links() {
+    let t = HtmlTokenizer::new("<p>a</p><p>b</p><p>c</p>".to_string());
+    let window = super::HtmlParser::new(t).construct_tree();
+    let document = window.borrow().document();
+    let html = document.borrow().first_child().expect("first_child is None");
+    let body = html.borrow().last_child().upgrade().expect("last_child is None");
+    let last = body.borrow().last_child().upgrade().expect("last_child is None");
+    let middle = last.borrow().previous_sibling().upgrade().expect("previous_sibling is None");
+    let first = middle.borrow().previous_sibling().upgrade().expect("previous_sibling is None");
+    assert!(Rc::ptr_eq(&first, &body.borrow().first_child().expect("first_child is None")));
+    assert!(first.borrow().previous_sibling().upgrade().is_none());
+    assert_eq!("<p>c</p>", dump(&last));
+  }
+
+  #[test]
+  fn test_table() {
+    assert_eq!(
+      "<html><head></head><body><table><tbody><tr><td>a</td><td>b</td></tr></tbody></table></body></html>",
+      parse("<table><tr><td>a<td>b</table>"),
+    );
+  }
+
+  #[test]
+  fn test_table_caption_and_colgroup() {
+    assert_eq!(
+      "<html><head></head><body><table><caption>c</caption><colgroup><col></col></colgroup><tbody><tr><td>x</td></tr></tbody></table></body></html>",
+      parse("<table><caption>c<col><tr><td>x</table>"),
+    );
+  }
+
+  #[test]
+  fn test_foster_parenting() {
+    assert_eq!(
+      "<html><head></head><body>y<table><tbody><tr><td>x</td></tr></tbody></table></body></html>",
+      parse("<table><tr><td>x</td></tr>y</table>"),
+    );
+    assert_eq!(
+      "<html><head></head><body><b>bold</b><table><tbody><tr><td>x</td></tr></tbody></table></body></html>",
+      parse("<table><b>bold</b><tr><td>x</table>"),
+    );
+  }
+
+  #[test]
+  fn test_nested_table() {
+    assert_eq!(
+      "<html><head></head><body><table><tbody><tr><td><table><tbody><tr><td>in</td></tr></tbody></table></td><td>out</td></tr></tbody></table></body></html>",
+      parse("<table><tr><td><table><tr><td>in</table><td>out</table>"),
+    );
+  }
+
+  #[test]
+  fn test_after_body() {
+    assert_eq!(
+      "<html><head></head><body>ab<p>c</p></body></html>",
+      parse("<body>a</body>b</html><p>c"),
+    );
+  }
+
+  #[test]
+  fn test_comments_and_doctype() {
+    assert_eq!(
+      "<!DOCTYPE html><!--a--><html><head><!--b--></head><body><p><!--c--></p><!--d--></body><!--e--></html><!--f-->",
+      parse("<!DOCTYPE html><!--a--><html><head><!--b--></head><p><!--c--></p><!--d--></body><!--e--></html><!--f-->"),
+    );
+    // A doctype after the initial insertion mode is ignored.
+    assert_eq!("<html><head></head><body>a</body></html>", parse("a<!DOCTYPE html>"));
+    assert_eq!(
+      "<html><head></head><body><table><!--a--><tbody><tr><!--b--></tr></tbody></table></body></html>",
+      parse("<table><!--a--><tr><!--b--></table>"),
+    );
+  }
+
+  #[test]
+  fn test_noscript_in_head() {
+    assert_eq!(
+      "<html><head><noscript><style>s</style></noscript></head><body><p>x</p></body></html>",
+      parse("<head><noscript><style>s</style></noscript></head><p>x"),
+    );
+  }
+
+  #[test]
+  fn test_head_metadata_elements() {
+    assert_eq!(
+      "<html><head><title>Test</title><meta></meta><link></link><base></base></head><body><p>x</p></body></html>",
+      parse("<html><head><title>Test</title><meta charset=utf-8><link rel=stylesheet href=a.css><base href=/></head><body><p>x</p></body></html>"),
+    );
+  }
+
+  #[test]
+  fn test_html_start_tag_in_head() {
+    assert_eq!(
+      "<html><head><title>t</title></head><body><p>x</p></body></html>",
+      parse("<head><html lang=x><title>t</title><p>x"),
+    );
+  }
+
+  #[test]
+  fn test_head_metadata_after_head() {
+    assert_eq!(
+      "<html><head><title>a</title><meta></meta></head><body><p>x</p></body></html>",
+      parse("<head><title>a</title></head><meta name=viewport><p>x"),
+    );
+  }
+
+  #[test]
+  fn test_raw_text_elements() {
+    assert_eq!(
+      "<html><head><title>a<b></title><script>if (a<b) c</script></head><body><textarea></p></textarea></body></html>",
+      parse("<title>a<b></title><script>if (a<b) c</script><textarea></p></textarea>"),
+    );
+  }
+
+  fn fragment(context: &str, html: &str) -> Vec<String> {
+    let context = Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(context, Vec::new())))));
+    HtmlParser::parse_fragment(&context, html.to_string())
+      .iter()
+      .map(|node| {
+        assert!(node.borrow().parent().upgrade().is_none());
+        dump(node)
+      })
+      .collect()
+  }
+
+  #[test]
+  fn test_parse_fragment() {
+    assert_eq!(alloc::vec!["<p>a</p>", "<p>b</p>"], fragment("div", "<p>a<p>b"));
+    assert_eq!(alloc::vec!["<li>a</li>", "<li>b</li>"], fragment("ul", "<li>a<li>b"));
+    assert_eq!(alloc::vec!["x", "<b>y</b>"], fragment("span", "x<b>y"));
+  }
+
+  #[test]
+  fn test_parse_fragment_table_context() {
+    assert_eq!(alloc::vec!["<td>x</td>", "<td>y</td>"], fragment("tr", "<td>x<td>y"));
+    assert_eq!(alloc::vec!["<tr><td>x</td></tr>"], fragment("tbody", "<td>x"));
+  }
+
+  #[test]
+  fn test_parse_fragment_raw_text_context() {
+    assert_eq!(alloc::vec!["a<b>c</style>"], fragment("style", "a<b>c</style>"));
+    assert_eq!(alloc::vec!["1 < 2"], fragment("textarea", "1 < 2"));
+  }
+
+  #[test]
+  fn test_svg() {
+    assert_eq!(
+      "<html><head></head><body><p><svg><linearGradient></linearGradient><path></path></svg>x</p></body></html>",
+      parse("<p><svg viewbox=\"0 0 10 10\"><lineargradient/><path></svg>x"),
+    );
+
+    let t = HtmlTokenizer::new("<svg viewbox=\"0 0 10 10\"></svg>".to_string());
+    let window = super::HtmlParser::new(t).construct_tree();
+    let document = window.borrow().document();
+    let body = document
+      .borrow()
+      .first_child()
+      .and_then(|html| html.borrow().first_child())
+      .and_then(|head| head.borrow().next_sibling())
+      .expect("body should exist");
+    let svg = body.borrow().first_child().expect("svg should exist");
+    let element = svg.borrow().get_element().expect("svg should be an element");
+    assert_eq!(Namespace::Svg, element.namespace());
+    assert_eq!(ElementKind::Svg, element.kind());
+    assert_eq!("viewBox", element.attributes()[0].name());
+  }
+
+  #[test]
+  fn test_foreign_breakout() {
+    assert_eq!(
+      "<html><head></head><body><svg><g></g></svg><p>x</p></body></html>",
+      parse("<svg><g><p>x"),
+    );
+    assert_eq!(
+      "<html><head></head><body><div><svg><g></g></svg></div>y</body></html>",
+      parse("<div><svg><g></div>y"),
+    );
+  }
+
+  #[test]
+  fn test_html_integration_point() {
+    assert_eq!(
+      "<html><head></head><body><svg><foreignObject><p>x</p></foreignObject><rect></rect></svg></body></html>",
+      parse("<svg><foreignobject><p>x</p></foreignobject><rect/></svg>"),
+    );
+    assert_eq!(
+      "<html><head></head><body><math><mi><b>x</b></mi></math></body></html>",
+      parse("<math><mi><b>x</b></mi></math>"),
+    );
+  }
+
+  #[test]
+  fn test_cdata_in_foreign_content() {
+    assert_eq!(
+      "<html><head></head><body><svg><text>a<b</text></svg></body></html>",
+      parse("<svg><text><![CDATA[a<b]]></text></svg>"),
+    );
+  }
+
+  #[test]
+  fn test_template() {
+    assert_eq!(
+      "<html><head><template><p>x</p></template></head><body></body></html>",
+      parse("<template><p>x</p></template>"),
+    );
+    assert_eq!(
+      "<html><head></head><body><template><tr><td>a</td></tr></template><p>b</p></body></html>",
+      parse("<body><template><tr><td>a</td></tr></template><p>b"),
+    );
+    assert_eq!(
+      "<html><head></head><body><div><template><div>x</div></template></div></body></html>",
+      parse("<div><template><div>x"),
+    );
+  }
+
+  #[test]
+  fn test_template_contents_are_inert() {
+    let t = HtmlTokenizer::new("<template><b>x</b></template>".to_string());
+    let window = super::HtmlParser::new(t).construct_tree();
+    let document = window.borrow().document();
+    let head = document
+      .borrow()
+      .first_child()
+      .and_then(|html| html.borrow().first_child())
+      .expect("head should exist");
+    let template = head.borrow().first_child().expect("template should exist");
+    assert_eq!(Some(ElementKind::Template), template.borrow().element_kind());
+    assert!(template.borrow().first_child().is_none());
+
+    let contents = template.borrow().template_contents().expect("template should have contents");
+    assert_eq!(NodeKind::DocumentFragment, contents.borrow().kind());
+    let b = contents.borrow().first_child().expect("contents should have a child");
+    assert!(Rc::ptr_eq(&contents, &b.borrow().parent().upgrade().unwrap()));
+  }
+
+  #[test]
+  fn test_parse_fragment_template_context() {
+    assert_eq!(alloc::vec!["<tr><td>x</td></tr>"], fragment("template", "<tr><td>x"));
+  }
+
+  #[test]
+  fn test_feed() {
+    let mut parser = HtmlParser::new(HtmlTokenizer::new_incremental());
+    parser.feed("<p>hel");
+    let document = parser.window().borrow().document();
+    assert_eq!("<html><head></head><body><p>hel</p></body></html>", dump(&document));
+
+    parser.feed("lo</p><ul><li");
+    assert_eq!("<html><head></head><body><p>hello</p><ul></ul></body></html>", dump(&document));
+
+    parser.feed(">x");
+    let window = parser.finish();
+    assert_eq!(
+      "<html><head></head><body><p>hello</p><ul><li>x</li></ul></body></html>",
+      dump(&window.borrow().document()),
+    );
+  }
+
+  #[test]
+  fn test_feed_at_every_boundary() {
+    let html = "<title>a&b</title><table><tr><td>x<b>y</td></tr></table><svg><path/></svg><p>z";
+    let expected = parse(html);
+    for i in 0..=html.len() {
+      let mut parser = HtmlParser::new(HtmlTokenizer::new_incremental());
+      parser.feed(&html[..i]);
+      parser.feed(&html[i..]);
+      let window = parser.finish();
+      assert_eq!(expected, dump(&window.borrow().document()), "split at {}", i);
+    }
+  }
+
+  #[test]
+  fn test_leading_newline_in_pre_and_textarea() {
+    assert_eq!("<html><head></head><body><pre>x</pre></body></html>", parse("<pre>\nx</pre>"));
+    assert_eq!("<html><head></head><body><pre>\nx</pre></body></html>", parse("<pre>\n\nx</pre>"));
+    assert_eq!("<html><head></head><body><listing>x</listing></body></html>", parse("<listing>\r\nx</listing>"));
+    assert_eq!(
+      "<html><head></head><body><textarea>x\n</textarea></body></html>",
+      parse("<textarea>\nx\n</textarea>"),
+    );
+    assert_eq!("<html><head></head><body><div>\nx</div></body></html>", parse("<div>\nx</div>"));
+  }
+
+  #[test]
+  fn test_whitespace_is_coalesced() {
+    let t = HtmlTokenizer::new("<p> a\t\x0C\r\nb </p>".to_string());
+    let window = HtmlParser::new(t).construct_tree();
+    let document = window.borrow().document();
+    let body = document.borrow().first_child().unwrap().borrow().last_child().upgrade().unwrap();
+    let p = body.borrow().first_child().unwrap();
+    let text = p.borrow().first_child().unwrap();
+    assert_eq!(NodeKind::Text(" a\t\x0C\nb ".to_string()), text.borrow().kind());
+    assert!(text.borrow().next_sibling().is_none());
+  }
+
+  fn parse_with_whitespace_mode(html: &str, mode: WhitespaceMode) -> String {
+    let mut parser = HtmlParser::new(HtmlTokenizer::new(html.to_string()));
+    parser.set_whitespace_mode(mode);
+    let window = parser.construct_tree();
+    let document = window.borrow().document();
+    dump(&document)
+  }
+
+  #[test]
+  fn test_whitespace_mode() {
+    let html = "<div>\n  <p> a </p>\t<b>b</b> <i>c</i>\n</div>\n<pre> </pre>";
+    assert_eq!(
+      "<html><head></head><body><div>\n  <p> a </p>\t<b>b</b> <i>c</i>\n</div>\n<pre> </pre></body></html>",
+      parse_with_whitespace_mode(html, WhitespaceMode::Preserve),
+    );
+    assert_eq!(
+      "<html><head></head><body><div><p> a </p><b>b</b><i>c</i></div><pre> </pre></body></html>",
+      parse_with_whitespace_mode(html, WhitespaceMode::StripInterElement),
+    );
+  }
+
+  #[test]
+  fn test_whitespace_mode_in_fragment() {
+    let context = Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new("ul", Vec::new())))));
+    let nodes = HtmlParser::parse_fragment_with_whitespace_mode(
+      &context,
+      "\n  <li>a</li>\n  <li>b</li>\n".to_string(),
+      WhitespaceMode::StripInterElement,
+    );
+    assert_eq!(
+      alloc::vec!["<li>a</li>", "<li>b</li>"],
+      nodes.iter().map(dump).collect::<Vec<_>>(),
+    );
+  }
+}
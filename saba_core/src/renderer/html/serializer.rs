@@ -0,0 +1,262 @@
+use core::cell::RefCell;
+
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec;
+
+use crate::renderer::dom::node::{Element, Namespace, Node, NodeKind};
+
+// https://html.spec.whatwg.org/multipage/syntax.html#void-elements
+pub const VOID_ELEMENTS: &[&str] = &[
+  "area", "base", "basefont", "bgsound", "br", "col", "embed", "frame", "hr", "img", "input",
+  "keygen", "link", "meta", "param", "source", "track", "wbr",
+];
+
+// Elements whose text children are serialized without escaping. `noscript` is not included
+// because scripting is never enabled.
+const RAW_TEXT_ELEMENTS: &[&str] =
+  &["iframe", "noembed", "noframes", "plaintext", "script", "style", "xmp"];
+
+enum Step {
+  Node(Rc<RefCell<Node>>, bool),
+  EndTag(String),
+}
+
+/// Serializes `node` and its descendants, like `outerHTML`. For a document or a fragment, this
+/// is the serialization of its children.
+pub fn outer_html(node: &Rc<RefCell<Node>>) -> String {
+  let mut out = String::new();
+  match node.borrow().kind {
+    NodeKind::Document | NodeKind::DocumentFragment => serialize_children(node, &mut out),
+    _ => serialize(Step::Node(node.clone(), false), &mut out),
+  }
+  out
+}
+
+/// Serializes the children of `node`, like `innerHTML`. The children of a template element are
+/// its template contents.
+pub fn inner_html(node: &Rc<RefCell<Node>>) -> String {
+  let mut out = String::new();
+  serialize_children(node, &mut out);
+  out
+}
+
+fn children_root(node: &Rc<RefCell<Node>>) -> Rc<RefCell<Node>> {
+  node.borrow().template_contents().unwrap_or_else(|| node.clone())
+}
+
+fn serialize_children(node: &Rc<RefCell<Node>>, out: &mut String) {
+  if let Some(child) = children_root(node).borrow().first_child() {
+    serialize(Step::Node(child, true), out);
+  }
+}
+
+// Walks the tree with an explicit stack. A node is pushed together with whether its following
+// siblings are serialized too, and the end tag of an element is pushed below its children.
+fn serialize(first: Step, out: &mut String) {
+  let mut stack = vec![first];
+  while let Some(step) = stack.pop() {
+    let (node, siblings) = match step {
+      Step::Node(node, siblings) => (node, siblings),
+      Step::EndTag(name) => {
+        out.push_str("</");
+        out.push_str(&name);
+        out.push('>');
+        continue;
+      }
+    };
+    if siblings {
+      if let Some(next) = node.borrow().next_sibling() {
+        stack.push(Step::Node(next, true));
+      }
+    }
+
+    let borrowed = node.borrow();
+    match borrowed.kind {
+      NodeKind::Element(ref element) => {
+        serialize_start_tag(element, out);
+        if element.namespace() == Namespace::Html
+          && VOID_ELEMENTS.contains(&element.tag_name().as_str())
+        {
+          continue;
+        }
+        stack.push(Step::EndTag(element.tag_name()));
+        if let Some(child) = children_root(&node).borrow().first_child() {
+          stack.push(Step::Node(child, true));
+        }
+      }
+      NodeKind::Text(ref data) => {
+        if is_raw_text_parent(&node) {
+          out.push_str(data);
+        } else {
+          escape(data, false, out);
+        }
+      }
+      NodeKind::Comment(ref data) => {
+        out.push_str("<!--");
+        out.push_str(data);
+        out.push_str("-->");
+      }
+      NodeKind::DocumentType { ref name, .. } => {
+        out.push_str("<!DOCTYPE ");
+        out.push_str(name);
+        out.push('>');
+      }
+      NodeKind::Document | NodeKind::DocumentFragment => {
+        if let Some(child) = borrowed.first_child() {
+          stack.push(Step::Node(child, true));
+        }
+      }
+    }
+  }
+}
+
+fn serialize_start_tag(element: &Element, out: &mut String) {
+  out.push('<');
+  out.push_str(&element.tag_name());
+  for attribute in element.attribute_map().iter() {
+    out.push(' ');
+    out.push_str(&attribute.qualified_name());
+    out.push_str("=\"");
+    escape(&attribute.value(), true, out);
+    out.push('"');
+  }
+  out.push('>');
+}
+
+fn is_raw_text_parent(node: &Rc<RefCell<Node>>) -> bool {
+  let parent = match node.borrow().parent().upgrade() {
+    Some(parent) => parent,
+    None => return false,
+  };
+  let parent = parent.borrow();
+  match parent.kind {
+    NodeKind::Element(ref element) => {
+      element.namespace() == Namespace::Html
+        && RAW_TEXT_ELEMENTS.contains(&element.tag_name().as_str())
+    }
+    _ => false,
+  }
+}
+
+// https://html.spec.whatwg.org/multipage/parsing.html#escapingString
+fn escape(s: &str, attribute_mode: bool, out: &mut String) {
+  for c in s.chars() {
+    match c {
+      '&' => out.push_str("&amp;"),
+      '\u{A0}' => out.push_str("&nbsp;"),
+      '"' if attribute_mode => out.push_str("&quot;"),
+      '<' => out.push_str("&lt;"),
+      '>' => out.push_str("&gt;"),
+      _ => out.push(c),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use alloc::string::ToString;
+  use alloc::vec::Vec;
+  use crate::renderer::dom::api::{append_child, create_document_fragment};
+  use crate::renderer::dom::query::get_elements_by_tag_name;
+  use crate::renderer::html::parser::HtmlParser;
+  use crate::renderer::html::token::HtmlTokenizer;
+
+  fn parse(html: &str) -> Rc<RefCell<Node>> {
+    let window = HtmlParser::new(HtmlTokenizer::new(html.to_string())).construct_tree();
+    let document = window.borrow().document();
+    document
+  }
+
+  fn first(document: &Rc<RefCell<Node>>, tag: &str) -> Rc<RefCell<Node>> {
+    get_elements_by_tag_name(document, tag).next().expect("element should exist")
+  }
+
+  #[test]
+  fn test_document() {
+    let document = parse("<!DOCTYPE html><!-- a --><title>t</title><p class=x>b");
+    assert_eq!(
+      "<!DOCTYPE html><!-- a --><html><head><title>t</title></head><body><p class=\"x\">b</p></body></html>",
+      outer_html(&document),
+    );
+  }
+
+  #[test]
+  fn test_inner_and_outer_html() {
+    let document = parse("<div id=a><span>b</span>c</div>");
+    let div = first(&document, "div");
+    assert_eq!("<span>b</span>c", inner_html(&div));
+    assert_eq!("<div id=\"a\"><span>b</span>c</div>", outer_html(&div));
+    assert_eq!("<span>b</span>", outer_html(&first(&document, "span")));
+  }
+
+  #[test]
+  fn test_escaping() {
+    let mut element = Element::new("p", Vec::new());
+    element.set_attribute("title", "a&\"<>\u{A0}'");
+    let p = Rc::new(RefCell::new(Node::new(NodeKind::Element(element))));
+    let text = Rc::new(RefCell::new(Node::new(NodeKind::Text("<b> & \"c\"\u{A0}'".to_string()))));
+    append_child(&p, &text).unwrap();
+    assert_eq!(
+      "<p title=\"a&amp;&quot;&lt;&gt;&nbsp;'\">&lt;b&gt; &amp; \"c\"&nbsp;'</p>",
+      outer_html(&p),
+    );
+  }
+
+  #[test]
+  fn test_void_elements() {
+    let document = parse("<p>a<br>b<img src=x><input></p>");
+    assert_eq!("a<br>b<img src=\"x\"><input>", inner_html(&first(&document, "p")));
+  }
+
+  #[test]
+  fn test_raw_text_elements() {
+    let document = parse("<style>a > b { }</style><script>if (a < b && c) {}</script><textarea><b></textarea>");
+    assert_eq!("<style>a > b { }</style>", outer_html(&first(&document, "style")));
+    assert_eq!("<script>if (a < b && c) {}</script>", outer_html(&first(&document, "script")));
+    assert_eq!("<textarea>&lt;b&gt;</textarea>", outer_html(&first(&document, "textarea")));
+  }
+
+  #[test]
+  fn test_template_and_foreign_content() {
+    let document = parse("<template><p>a</p></template><svg viewBox='0 0 1 1'><path/></svg>");
+    assert_eq!("<p>a</p>", inner_html(&first(&document, "template")));
+    assert_eq!("<template><p>a</p></template>", outer_html(&first(&document, "template")));
+    assert_eq!(
+      "<svg viewBox=\"0 0 1 1\"><path></path></svg>",
+      outer_html(&first(&document, "svg")),
+    );
+  }
+
+  #[test]
+  fn test_foreign_attribute_prefixes() {
+    let html = "<svg xmlns=\"http://www.w3.org/2000/svg\" xmlns:xlink=\"x\"><a xlink:href=\"#a\" xml:lang=\"en\"></a></svg>";
+    assert_eq!(html, outer_html(&first(&parse(html), "svg")));
+  }
+
+  #[test]
+  fn test_fragment() {
+    let fragment = create_document_fragment();
+    let comment = Rc::new(RefCell::new(Node::new(NodeKind::Comment("x".to_string()))));
+    let text = Rc::new(RefCell::new(Node::new(NodeKind::Text("<y>".to_string()))));
+    append_child(&fragment, &comment).unwrap();
+    append_child(&fragment, &text).unwrap();
+    assert_eq!("<!--x-->&lt;y&gt;", outer_html(&fragment));
+    assert_eq!("<!--x-->", outer_html(&comment));
+  }
+
+  #[test]
+  fn test_round_trip() {
+    let html = "<!DOCTYPE html><html><head><title>a</title></head><body><ul><li class=\"x\">1</li><li>2<br></li></ul><!-- end --></body></html>";
+    let serialized = outer_html(&parse(html));
+    assert_eq!(html, serialized);
+    assert_eq!(serialized, outer_html(&parse(&serialized)));
+  }
+
+  #[test]
+  fn test_long_sibling_list() {
+    let document = parse(&"<p>x</p>".repeat(50_000));
+    assert_eq!(8 * 50_000, inner_html(&first(&document, "body")).len());
+  }
+}
@@ -1,6 +1,10 @@
 use alloc::string::String;
 use alloc::vec::Vec;
 use crate::renderer::html::attribute::Attribute;
+use crate::renderer::html::attribute::AttributeMap;
+
+// Once this many characters have been consumed, `feed` drops them from the input buffer.
+const COMPACTION_THRESHOLD: usize = 4096;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct HtmlTokenizer {
@@ -8,28 +12,119 @@ pub struct HtmlTokenizer {
   pos: usize,
   reconsume: bool,
   latest_token: Option<HtmlToken>,
+  last_start_tag: String,
+  return_state: State,
+  cdata_allowed: bool,
+  finished: bool,
+  after_cr: bool,
   input: Vec<char>,
   buf: String,
 }
 
 impl HtmlTokenizer {
   pub fn new(html: String) -> Self {
+    let mut t = Self::new_incremental();
+    t.feed(&html);
+    t.finish();
+    t
+  }
+
+  pub fn new_incremental() -> Self {
     Self {
       state: State::Data,
       pos: 0,
       reconsume: false,
       latest_token: None,
-      input: html.chars().collect(),
+      last_start_tag: String::new(),
+      return_state: State::Data,
+      cdata_allowed: false,
+      finished: false,
+      after_cr: false,
+      input: Vec::new(),
       buf: String::new(),
     }
   }
 
+  pub fn feed(&mut self, chunk: &str) {
+    assert!(!self.finished, "input was already finished");
+    self.compact();
+    // CR and CRLF are normalized to LF before tokenization. A CRLF pair may be split across
+    // chunks, so whether the last character was CR is remembered between calls.
+    for c in chunk.chars() {
+      if self.after_cr && c == '\n' {
+        self.after_cr = false;
+        continue;
+      }
+      self.after_cr = c == '\r';
+      self.input.push(if c == '\r' { '\n' } else { c });
+    }
+  }
+
+  // Drops the consumed prefix of the input. The last consumed character is kept so that it
+  // can still be reconsumed.
+  fn compact(&mut self) {
+    if self.pos <= COMPACTION_THRESHOLD {
+      return;
+    }
+    self.input.drain(..self.pos - 1);
+    self.pos = 1;
+  }
+
+  pub fn finish(&mut self) {
+    self.finished = true;
+  }
+
+  pub fn is_finished(&self) -> bool {
+    self.finished
+  }
+
+  fn needs_more_input(&self) -> bool {
+    !self.finished && !self.reconsume && self.pos >= self.input.len()
+  }
+
+  pub fn switch_to(&mut self, state: State) {
+    self.state = state;
+  }
+
+  pub fn set_last_start_tag(&mut self, tag: String) {
+    self.last_start_tag = tag;
+  }
+
+  pub fn set_cdata_allowed(&mut self, allowed: bool) {
+    self.cdata_allowed = allowed;
+  }
+
+  // Returns None when more input has to be fed before it can be decided.
+  fn starts_with(&self, s: &str) -> Option<bool> {
+    for (i, c) in s.chars().enumerate() {
+      match self.input.get(self.pos + i) {
+        Some(d) if *d == c => {}
+        Some(_) => return Some(false),
+        None if self.finished => return Some(false),
+        None => return None,
+      }
+    }
+    Some(true)
+  }
+
+  fn starts_with_ignore_case(&self, s: &str) -> Option<bool> {
+    for (i, c) in s.chars().enumerate() {
+      match self.input.get(self.pos + i) {
+        Some(d) if d.eq_ignore_ascii_case(&c) => {}
+        Some(_) => return Some(false),
+        None if self.finished => return Some(false),
+        None => return None,
+      }
+    }
+    Some(true)
+  }
+
   fn is_eof(&self) -> bool {
     self.pos > self.input.len()
   }
 
   fn consume_next_input(&mut self) -> char {
-    let c = self.input[self.pos];
+    let c = self.input.get(self.pos).copied().unwrap_or('\0');
     self.pos += 1;
     c
   }
@@ -50,7 +145,7 @@ impl HtmlTokenizer {
 
   fn reconsume_input(&mut self) -> char {
     self.reconsume = false;
-    self.input[self.pos - 1]
+    self.input.get(self.pos - 1).copied().unwrap_or('\0')
   }
 
   fn append_tag_name(&mut self, c: char) {
@@ -71,13 +166,34 @@ impl HtmlTokenizer {
   fn take_latest_token(&mut self) -> Option<HtmlToken> {
     assert!(self.latest_token.is_some());
 
-    let t = self.latest_token.as_ref().cloned();
+    let mut t = self.latest_token.as_ref().cloned();
     self.latest_token = None;
     assert!(self.latest_token.is_none());
 
+    if let Some(HtmlToken::StartTag { ref tag, ref mut attributes, .. }) = t {
+      self.last_start_tag = tag.clone();
+      // An attribute whose name is already on the tag is a parse error and is dropped.
+      *attributes = AttributeMap::from_attributes(core::mem::take(attributes)).to_vec();
+    }
+
     t
   }
 
+  fn is_appropriate_end_tag(&self) -> bool {
+    match self.latest_token {
+      Some(HtmlToken::EndTag { ref tag }) => *tag == self.last_start_tag,
+      _ => false,
+    }
+  }
+
+  fn text_state(&self) -> State {
+    match self.state {
+      State::RcdataLessThanSign | State::RcdataEndTagOpen | State::RcdataEndTagName => State::Rcdata,
+      State::RawtextLessThanSign | State::RawtextEndTagOpen | State::RawtextEndTagName => State::Rawtext,
+      _ => State::ScriptData,
+    }
+  }
+
   fn start_new_attribute(&mut self) {
     assert!(self.latest_token.is_some());
 
@@ -88,6 +204,7 @@ impl HtmlTokenizer {
           self_closing: _,
           ref mut attributes,
         } => attributes.push(Attribute::new()),
+        HtmlToken::EndTag { .. } => {}
         _ => panic!("unexpected token"),
       }
     }
@@ -108,11 +225,74 @@ impl HtmlTokenizer {
 
           attributes[len - 1].add_char(c, is_name);
         }
+        HtmlToken::EndTag { .. } => {}
         _ => panic!("unexpected token"),
       }
     }
   }
 
+  fn append_comment(&mut self, c: char) {
+    match self.latest_token.as_mut() {
+      Some(HtmlToken::Comment(ref mut data)) => data.push(c),
+      _ => panic!("unexpected token"),
+    }
+  }
+
+  fn doctype_field(&mut self, field: DoctypeField) -> &mut Option<String> {
+    match self.latest_token.as_mut() {
+      Some(HtmlToken::Doctype {
+        ref mut name,
+        ref mut public_id,
+        ref mut system_id,
+        ..
+      }) => match field {
+        DoctypeField::Name => name,
+        DoctypeField::PublicId => public_id,
+        DoctypeField::SystemId => system_id,
+      },
+      _ => panic!("unexpected token"),
+    }
+  }
+
+  fn append_doctype(&mut self, field: DoctypeField, c: char) {
+    self.doctype_field(field).get_or_insert_with(String::new).push(c);
+  }
+
+  fn set_force_quirks_flag(&mut self) {
+    match self.latest_token.as_mut() {
+      Some(HtmlToken::Doctype { ref mut force_quirks, .. }) => *force_quirks = true,
+      _ => panic!("unexpected token"),
+    }
+  }
+
+  // Handles a character where a quoted public or system identifier may start. Switches to the
+  // data state when the doctype token has to be emitted.
+  fn consume_doctype_identifier_start(&mut self, c: char, field: DoctypeField) {
+    if c.is_ascii_whitespace() {
+      return;
+    }
+
+    let public = field == DoctypeField::PublicId;
+    self.state = match c {
+      '"' if public => State::DoctypePublicIdentifierDoubleQuoted,
+      '\'' if public => State::DoctypePublicIdentifierSingleQuoted,
+      '"' => State::DoctypeSystemIdentifierDoubleQuoted,
+      '\'' => State::DoctypeSystemIdentifierSingleQuoted,
+      _ => {
+        self.set_force_quirks_flag();
+        if c == '>' || self.is_eof() {
+          State::Data
+        } else {
+          self.reconsume = true;
+          State::BogusDoctype
+        }
+      }
+    };
+    if c == '"' || c == '\'' {
+      *self.doctype_field(field) = Some(String::new());
+    }
+  }
+
   fn set_self_closing_flag(&mut self) {
     assert!(self.latest_token.is_some());
 
@@ -123,6 +303,7 @@ impl HtmlTokenizer {
           ref mut self_closing,
           attributes: _,
         } => *self_closing = true,
+        HtmlToken::EndTag { .. } => {}
         _ => panic!("unexpected token"),
       }
     }
@@ -140,9 +321,23 @@ pub enum HtmlToken {
     tag: String,
   },
   Char(char),
+  Comment(String),
+  Doctype {
+    name: Option<String>,
+    public_id: Option<String>,
+    system_id: Option<String>,
+    force_quirks: bool,
+  },
   Eof,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DoctypeField {
+  Name,
+  PublicId,
+  SystemId,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum State {
   Data,
@@ -158,10 +353,43 @@ pub enum State {
   AttributeValueUnquoted,
   AfterAttributeValueQuoted,
   SelfClosingStartTag,
+  Rcdata,
+  RcdataLessThanSign,
+  RcdataEndTagOpen,
+  RcdataEndTagName,
+  Rawtext,
+  RawtextLessThanSign,
+  RawtextEndTagOpen,
+  RawtextEndTagName,
   ScriptData,
   ScriptDataLessThanSign,
   ScriptDataEndTagOpen,
   ScriptDataEndTagName,
+  Plaintext,
+  MarkupDeclarationOpen,
+  CommentStart,
+  CommentStartDash,
+  Comment,
+  CommentEndDash,
+  CommentEnd,
+  CommentEndBang,
+  BogusComment,
+  Doctype,
+  BeforeDoctypeName,
+  DoctypeName,
+  AfterDoctypeName,
+  BeforeDoctypePublicIdentifier,
+  DoctypePublicIdentifierDoubleQuoted,
+  DoctypePublicIdentifierSingleQuoted,
+  AfterDoctypePublicIdentifier,
+  BeforeDoctypeSystemIdentifier,
+  DoctypeSystemIdentifierDoubleQuoted,
+  DoctypeSystemIdentifierSingleQuoted,
+  AfterDoctypeSystemIdentifier,
+  BogusDoctype,
+  CdataSection,
+  CdataSectionBracket,
+  CdataSectionEnd,
   TemporaryBuffer,
 }
 
@@ -169,11 +397,15 @@ impl Iterator for HtmlTokenizer {
   type Item = HtmlToken;
 
   fn next(&mut self) -> Option<Self::Item> {
-    if self.pos >= self.input.len() {
+    if self.pos >= self.input.len() && !self.reconsume {
       return None;
     }
 
     loop {
+      if self.needs_more_input() {
+        return None;
+      }
+
       let c = match self.reconsume {
         true => self.reconsume_input(),
         false => self.consume_next_input(),
@@ -198,6 +430,18 @@ impl Iterator for HtmlTokenizer {
             continue;
           }
 
+          if c == '!' {
+            self.state = State::MarkupDeclarationOpen;
+            continue;
+          }
+
+          if c == '?' {
+            self.latest_token = Some(HtmlToken::Comment(String::new()));
+            self.reconsume = true;
+            self.state = State::BogusComment;
+            continue;
+          }
+
           if c.is_alphabetic() {
             self.reconsume = true;
             self.state = State::TagName;
@@ -223,10 +467,19 @@ impl Iterator for HtmlTokenizer {
             self.create_tag(false);
             continue;
           }
+
+          if c == '>' {
+            self.state = State::Data;
+            continue;
+          }
+
+          self.latest_token = Some(HtmlToken::Comment(String::new()));
+          self.reconsume = true;
+          self.state = State::BogusComment;
         }
         
         State::TagName => {
-          if c == ' ' {
+          if c.is_ascii_whitespace() {
             self.state = State::BeforeAttributeName;
             continue;
           }
@@ -254,6 +507,10 @@ impl Iterator for HtmlTokenizer {
         }
         
         State::BeforeAttributeName => {
+          if c.is_ascii_whitespace() {
+            continue;
+          }
+
           if c == '/' || c == '>' || self.is_eof() {
             self.reconsume = true;
             self.state = State::AfterAttributeName;
@@ -266,6 +523,11 @@ impl Iterator for HtmlTokenizer {
         }
         
         State::AttributeName => {
+          if c.is_ascii_whitespace() {
+            self.state = State::AfterAttributeName;
+            continue;
+          }
+
           if c == '=' {
             self.state = State::BeforeAttributeValue;
             continue;
@@ -286,6 +548,10 @@ impl Iterator for HtmlTokenizer {
         }
 
         State::AfterAttributeName => {
+          if c.is_ascii_whitespace() {
+            continue;
+          }
+
           if c == '/' {
             self.state = State::SelfClosingStartTag;
             continue;
@@ -311,7 +577,7 @@ impl Iterator for HtmlTokenizer {
         }
         
         State::BeforeAttributeValue => {
-          if c == ' ' {
+          if c.is_ascii_whitespace() {
             continue;
           }
 
@@ -356,7 +622,7 @@ impl Iterator for HtmlTokenizer {
         }
 
         State::AttributeValueUnquoted => {
-          if c == ' ' {
+          if c.is_ascii_whitespace() {
             self.state = State::BeforeAttributeName;
             continue;
           }
@@ -374,7 +640,7 @@ impl Iterator for HtmlTokenizer {
         }
 
         State::AfterAttributeValueQuoted => {
-          if c == ' ' {
+          if c.is_ascii_whitespace() {
             self.state = State::BeforeAttributeName;
             continue;
           }
@@ -412,9 +678,13 @@ impl Iterator for HtmlTokenizer {
           self.state = State::BeforeAttributeName;
         }
 
-        State::ScriptData => {
+        State::Rcdata | State::Rawtext | State::ScriptData => {
           if c == '<' {
-            self.state = State::ScriptDataLessThanSign;
+            self.state = match self.state {
+              State::Rcdata => State::RcdataLessThanSign,
+              State::Rawtext => State::RawtextLessThanSign,
+              _ => State::ScriptDataLessThanSign,
+            };
             continue;
           }
 
@@ -425,35 +695,57 @@ impl Iterator for HtmlTokenizer {
           return Some(HtmlToken::Char(c));
         }
 
-        State::ScriptDataLessThanSign => {
+        State::RcdataLessThanSign | State::RawtextLessThanSign | State::ScriptDataLessThanSign => {
           if c == '/' {
             self.buf = String::new();
-            self.state = State::ScriptDataEndTagOpen;
+            self.state = match self.state {
+              State::RcdataLessThanSign => State::RcdataEndTagOpen,
+              State::RawtextLessThanSign => State::RawtextEndTagOpen,
+              _ => State::ScriptDataEndTagOpen,
+            };
             continue;
           }
 
           self.reconsume = true;
-          self.state = State::ScriptData;
+          self.state = self.text_state();
           return Some(HtmlToken::Char('<'));
         }
 
-        State::ScriptDataEndTagOpen => {
+        State::RcdataEndTagOpen | State::RawtextEndTagOpen | State::ScriptDataEndTagOpen => {
           if c.is_ascii_alphabetic() {
             self.reconsume = true;
-            self.state = State::ScriptDataEndTagName;
+            self.state = match self.state {
+              State::RcdataEndTagOpen => State::RcdataEndTagName,
+              State::RawtextEndTagOpen => State::RawtextEndTagName,
+              _ => State::ScriptDataEndTagName,
+            };
             self.create_tag(false);
             continue;
           }
 
+          self.return_state = self.text_state();
+          self.buf = String::from("</");
           self.reconsume = true;
-          self.state = State::ScriptData;
-          return Some(HtmlToken::Char('<'));
+          self.state = State::TemporaryBuffer;
+          continue;
         }
 
-        State::ScriptDataEndTagName => {
-          if c == '>' {
-            self.state = State::Data;
-            return self.take_latest_token();
+        State::RcdataEndTagName | State::RawtextEndTagName | State::ScriptDataEndTagName => {
+          if self.is_appropriate_end_tag() {
+            if c.is_ascii_whitespace() {
+              self.state = State::BeforeAttributeName;
+              continue;
+            }
+
+            if c == '/' {
+              self.state = State::SelfClosingStartTag;
+              continue;
+            }
+
+            if c == '>' {
+              self.state = State::Data;
+              return self.take_latest_token();
+            }
           }
 
           if c.is_ascii_alphabetic() {
@@ -462,17 +754,424 @@ impl Iterator for HtmlTokenizer {
             continue;
           }
 
-          self.state = State::TemporaryBuffer;
+          self.return_state = self.text_state();
+          self.latest_token = None;
           self.buf = String::from("</") + &self.buf;
-          self.buf.push(c);
+          self.reconsume = true;
+          self.state = State::TemporaryBuffer;
           continue;
         }
-        
+
+        State::MarkupDeclarationOpen => {
+          if self.cdata_allowed && c == '[' {
+            match self.starts_with("CDATA[") {
+              Some(true) => {
+                self.pos += "CDATA[".len();
+                self.state = State::CdataSection;
+                continue;
+              }
+              Some(false) => {}
+              None => {
+                self.reconsume = true;
+                return None;
+              }
+            }
+          }
+
+          if c == '-' {
+            match self.starts_with("-") {
+              Some(true) => {
+                self.pos += 1;
+                self.latest_token = Some(HtmlToken::Comment(String::new()));
+                self.state = State::CommentStart;
+                continue;
+              }
+              Some(false) => {}
+              None => {
+                self.reconsume = true;
+                return None;
+              }
+            }
+          }
+
+          if c.eq_ignore_ascii_case(&'d') {
+            match self.starts_with_ignore_case("octype") {
+              Some(true) => {
+                self.pos += "octype".len();
+                self.latest_token = Some(HtmlToken::Doctype {
+                  name: None,
+                  public_id: None,
+                  system_id: None,
+                  force_quirks: false,
+                });
+                self.state = State::Doctype;
+                continue;
+              }
+              Some(false) => {}
+              None => {
+                self.reconsume = true;
+                return None;
+              }
+            }
+          }
+
+          self.latest_token = Some(HtmlToken::Comment(String::new()));
+          self.reconsume = true;
+          self.state = State::BogusComment;
+          continue;
+        }
+
+        State::CommentStart => {
+          if c == '-' {
+            self.state = State::CommentStartDash;
+            continue;
+          }
+
+          if c == '>' {
+            self.state = State::Data;
+            return self.take_latest_token();
+          }
+
+          self.reconsume = true;
+          self.state = State::Comment;
+        }
+
+        State::CommentStartDash => {
+          if c == '-' {
+            self.state = State::CommentEnd;
+            continue;
+          }
+
+          if c == '>' {
+            self.state = State::Data;
+            return self.take_latest_token();
+          }
+
+          if self.is_eof() {
+            return self.take_latest_token();
+          }
+
+          self.append_comment('-');
+          self.reconsume = true;
+          self.state = State::Comment;
+        }
+
+        State::Comment => {
+          if c == '-' {
+            self.state = State::CommentEndDash;
+            continue;
+          }
+
+          if self.is_eof() {
+            return self.take_latest_token();
+          }
+
+          self.append_comment(if c == '\0' { '\u{FFFD}' } else { c });
+        }
+
+        State::CommentEndDash => {
+          if c == '-' {
+            self.state = State::CommentEnd;
+            continue;
+          }
+
+          if self.is_eof() {
+            return self.take_latest_token();
+          }
+
+          self.append_comment('-');
+          self.reconsume = true;
+          self.state = State::Comment;
+        }
+
+        State::CommentEnd => {
+          if c == '>' {
+            self.state = State::Data;
+            return self.take_latest_token();
+          }
+
+          if c == '!' {
+            self.state = State::CommentEndBang;
+            continue;
+          }
+
+          if c == '-' {
+            self.append_comment('-');
+            continue;
+          }
+
+          if self.is_eof() {
+            return self.take_latest_token();
+          }
+
+          self.append_comment('-');
+          self.append_comment('-');
+          self.reconsume = true;
+          self.state = State::Comment;
+        }
+
+        State::CommentEndBang => {
+          if c == '>' {
+            self.state = State::Data;
+            return self.take_latest_token();
+          }
+
+          if self.is_eof() {
+            return self.take_latest_token();
+          }
+
+          self.append_comment('-');
+          self.append_comment('-');
+          self.append_comment('!');
+          if c == '-' {
+            self.state = State::CommentEndDash;
+            continue;
+          }
+
+          self.reconsume = true;
+          self.state = State::Comment;
+        }
+
+        State::BogusComment => {
+          if c == '>' {
+            self.state = State::Data;
+            return self.take_latest_token();
+          }
+
+          if self.is_eof() {
+            return self.take_latest_token();
+          }
+
+          self.append_comment(if c == '\0' { '\u{FFFD}' } else { c });
+        }
+
+        State::Doctype => {
+          if self.is_eof() {
+            self.set_force_quirks_flag();
+            return self.take_latest_token();
+          }
+
+          if !c.is_ascii_whitespace() {
+            self.reconsume = true;
+          }
+          self.state = State::BeforeDoctypeName;
+        }
+
+        State::BeforeDoctypeName => {
+          if c.is_ascii_whitespace() {
+            continue;
+          }
+
+          if c == '>' || self.is_eof() {
+            self.state = State::Data;
+            self.set_force_quirks_flag();
+            return self.take_latest_token();
+          }
+
+          self.reconsume = true;
+          self.state = State::DoctypeName;
+          self.doctype_field(DoctypeField::Name).get_or_insert_with(String::new);
+        }
+
+        State::DoctypeName => {
+          if c.is_ascii_whitespace() {
+            self.state = State::AfterDoctypeName;
+            continue;
+          }
+
+          if c == '>' {
+            self.state = State::Data;
+            return self.take_latest_token();
+          }
+
+          if self.is_eof() {
+            self.set_force_quirks_flag();
+            return self.take_latest_token();
+          }
+
+          self.append_doctype(DoctypeField::Name, c.to_ascii_lowercase());
+        }
+
+        State::AfterDoctypeName => {
+          if c.is_ascii_whitespace() {
+            continue;
+          }
+
+          if c == '>' {
+            self.state = State::Data;
+            return self.take_latest_token();
+          }
+
+          if self.is_eof() {
+            self.set_force_quirks_flag();
+            return self.take_latest_token();
+          }
+
+          // The rest of the "PUBLIC" or "SYSTEM" keyword.
+          let (rest, next) = match c.to_ascii_lowercase() {
+            'p' => ("ublic", State::BeforeDoctypePublicIdentifier),
+            's' => ("ystem", State::BeforeDoctypeSystemIdentifier),
+            _ => ("", State::BogusDoctype),
+          };
+          match self.starts_with_ignore_case(rest) {
+            Some(true) if !rest.is_empty() => {
+              self.pos += rest.len();
+              self.state = next;
+            }
+            Some(_) => {
+              self.set_force_quirks_flag();
+              self.reconsume = true;
+              self.state = State::BogusDoctype;
+            }
+            None => {
+              self.reconsume = true;
+              return None;
+            }
+          }
+        }
+
+        State::BeforeDoctypePublicIdentifier | State::BeforeDoctypeSystemIdentifier => {
+          let field = match self.state {
+            State::BeforeDoctypePublicIdentifier => DoctypeField::PublicId,
+            _ => DoctypeField::SystemId,
+          };
+          self.consume_doctype_identifier_start(c, field);
+          if self.state == State::Data {
+            return self.take_latest_token();
+          }
+        }
+
+        State::DoctypePublicIdentifierDoubleQuoted
+        | State::DoctypePublicIdentifierSingleQuoted
+        | State::DoctypeSystemIdentifierDoubleQuoted
+        | State::DoctypeSystemIdentifierSingleQuoted => {
+          let (field, quote, next) = match self.state {
+            State::DoctypePublicIdentifierDoubleQuoted => {
+              (DoctypeField::PublicId, '"', State::AfterDoctypePublicIdentifier)
+            }
+            State::DoctypePublicIdentifierSingleQuoted => {
+              (DoctypeField::PublicId, '\'', State::AfterDoctypePublicIdentifier)
+            }
+            State::DoctypeSystemIdentifierDoubleQuoted => {
+              (DoctypeField::SystemId, '"', State::AfterDoctypeSystemIdentifier)
+            }
+            _ => (DoctypeField::SystemId, '\'', State::AfterDoctypeSystemIdentifier),
+          };
+
+          if c == quote {
+            self.state = next;
+            continue;
+          }
+
+          if c == '>' || self.is_eof() {
+            self.state = State::Data;
+            self.set_force_quirks_flag();
+            return self.take_latest_token();
+          }
+
+          self.append_doctype(field, if c == '\0' { '\u{FFFD}' } else { c });
+        }
+
+        State::AfterDoctypePublicIdentifier => {
+          // The whitespace between the public and the system identifier is optional, so this
+          // state also covers "between doctype public and system identifiers".
+          if c.is_ascii_whitespace() {
+            continue;
+          }
+
+          if c == '>' {
+            self.state = State::Data;
+            return self.take_latest_token();
+          }
+
+          self.consume_doctype_identifier_start(c, DoctypeField::SystemId);
+          if self.state == State::Data {
+            return self.take_latest_token();
+          }
+        }
+
+        State::AfterDoctypeSystemIdentifier => {
+          if c.is_ascii_whitespace() {
+            continue;
+          }
+
+          if c == '>' {
+            self.state = State::Data;
+            return self.take_latest_token();
+          }
+
+          if self.is_eof() {
+            self.set_force_quirks_flag();
+            return self.take_latest_token();
+          }
+
+          self.reconsume = true;
+          self.state = State::BogusDoctype;
+        }
+
+        State::BogusDoctype => {
+          if c == '>' || self.is_eof() {
+            self.state = State::Data;
+            return self.take_latest_token();
+          }
+        }
+
+        State::CdataSection => {
+          if c == ']' {
+            self.state = State::CdataSectionBracket;
+            continue;
+          }
+
+          if self.is_eof() {
+            return Some(HtmlToken::Eof);
+          }
+
+          return Some(HtmlToken::Char(c));
+        }
+
+        State::CdataSectionBracket => {
+          if c == ']' {
+            self.state = State::CdataSectionEnd;
+            continue;
+          }
+
+          self.return_state = State::CdataSection;
+          self.buf = String::from("]");
+          self.reconsume = true;
+          self.state = State::TemporaryBuffer;
+          continue;
+        }
+
+        State::CdataSectionEnd => {
+          if c == ']' {
+            return Some(HtmlToken::Char(c));
+          }
+
+          if c == '>' {
+            self.state = State::Data;
+            continue;
+          }
+
+          self.return_state = State::CdataSection;
+          self.buf = String::from("]]");
+          self.reconsume = true;
+          self.state = State::TemporaryBuffer;
+          continue;
+        }
+
+        State::Plaintext => {
+          if self.is_eof() {
+            return Some(HtmlToken::Eof);
+          }
+
+          return Some(HtmlToken::Char(c));
+        }
+
         State::TemporaryBuffer => {
           self.reconsume = true;
 
           if self.buf.chars().count() == 0 {
-            self.state = State::ScriptData;
+            self.state = self.return_state.clone();
             continue;
           }
 
@@ -547,6 +1246,23 @@ mod tests {
     assert_eq!(tokenizer.next(), None);
   }
 
+  #[test]
+  fn test_duplicate_attributes() {
+    let html = "<p ID=a class=b id=c>".to_string();
+    let mut tokenizer = HtmlTokenizer::new(html);
+    match tokenizer.next() {
+      Some(HtmlToken::StartTag { attributes, .. }) => {
+        let attributes: Vec<(String, String)> =
+          attributes.iter().map(|a| (a.name(), a.value())).collect();
+        assert_eq!(
+          vec![("id".to_string(), "a".to_string()), ("class".to_string(), "b".to_string())],
+          attributes,
+        );
+      }
+      t => panic!("unexpected token {:?}", t),
+    }
+  }
+
   #[test]
   fn test_self_closing_tag() {
     let html = "<img />".to_string();
@@ -597,4 +1313,230 @@ mod tests {
     }));
     assert_eq!(tokenizer.next(), None);
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn test_rawtext() {
+    let html = "<style>a<b></p></style>".to_string();
+    let mut tokenizer = HtmlTokenizer::new(html);
+    assert_eq!(tokenizer.next(), Some(HtmlToken::StartTag {
+      tag: "style".to_string(),
+      self_closing: false,
+      attributes: Vec::new(),
+    }));
+    tokenizer.switch_to(State::Rawtext);
+    for c in "a<b></p>".chars() {
+      assert_eq!(tokenizer.next(), Some(HtmlToken::Char(c)));
+    }
+    assert_eq!(tokenizer.next(), Some(HtmlToken::EndTag {
+      tag: "style".to_string(),
+    }));
+    assert_eq!(tokenizer.next(), None);
+  }
+
+  #[test]
+  fn test_rcdata_at_eof() {
+    let html = "<title>x</tit".to_string();
+    let mut tokenizer = HtmlTokenizer::new(html);
+    assert_eq!(tokenizer.next(), Some(HtmlToken::StartTag {
+      tag: "title".to_string(),
+      self_closing: false,
+      attributes: Vec::new(),
+    }));
+    tokenizer.switch_to(State::Rcdata);
+    for c in "x</tit".chars() {
+      assert_eq!(tokenizer.next(), Some(HtmlToken::Char(c)));
+    }
+    assert_eq!(tokenizer.next(), Some(HtmlToken::Eof));
+    assert_eq!(tokenizer.next(), None);
+  }
+
+  #[test]
+  fn test_cdata_section() {
+    let html = "<![CDATA[a]]b<]]>".to_string();
+    let mut tokenizer = HtmlTokenizer::new(html);
+    tokenizer.set_cdata_allowed(true);
+    for c in "a]]b<".chars() {
+      assert_eq!(tokenizer.next(), Some(HtmlToken::Char(c)));
+    }
+    assert_eq!(tokenizer.next(), Some(HtmlToken::Eof));
+    assert_eq!(tokenizer.next(), None);
+
+    // Outside of foreign content a CDATA section is a bogus comment.
+    let mut tokenizer = HtmlTokenizer::new("<![CDATA[a]]>".to_string());
+    assert_eq!(tokenizer.next(), Some(HtmlToken::Comment("[CDATA[a]]".to_string())));
+    assert_eq!(tokenizer.next(), None);
+  }
+
+  fn comments(html: &str) -> Vec<HtmlToken> {
+    HtmlTokenizer::new(html.to_string()).filter(|t| matches!(t, HtmlToken::Comment(_))).collect()
+  }
+
+  #[test]
+  fn test_comment() {
+    let mut tokenizer = HtmlTokenizer::new("a<!-- b -->c".to_string());
+    assert_eq!(tokenizer.next(), Some(HtmlToken::Char('a')));
+    assert_eq!(tokenizer.next(), Some(HtmlToken::Comment(" b ".to_string())));
+    assert_eq!(tokenizer.next(), Some(HtmlToken::Char('c')));
+
+    assert_eq!(vec![HtmlToken::Comment(String::new())], comments("<!---->"));
+    assert_eq!(vec![HtmlToken::Comment(String::new())], comments("<!-->"));
+    assert_eq!(vec![HtmlToken::Comment("-a--b-".to_string())], comments("<!---a--b--->"));
+    assert_eq!(vec![HtmlToken::Comment("a--!b".to_string())], comments("<!--a--!b--!>"));
+    assert_eq!(vec![HtmlToken::Comment("a\u{FFFD}".to_string())], comments("<!--a\0"));
+    assert_eq!(vec![HtmlToken::Comment("?xml ".to_string())], comments("<?xml >"));
+    assert_eq!(vec![HtmlToken::Comment("x".to_string())], comments("<!x>"));
+    assert_eq!(vec![HtmlToken::Comment("3a".to_string())], comments("</3a>"));
+  }
+
+  #[test]
+  fn test_doctype() {
+    let doctype = |html: &str| HtmlTokenizer::new(html.to_string()).next();
+    assert_eq!(doctype("<!DOCTYPE html>"), Some(HtmlToken::Doctype {
+      name: Some("html".to_string()),
+      public_id: None,
+      system_id: None,
+      force_quirks: false,
+    }));
+    assert_eq!(
+      doctype("<!doctype HTML PUBLIC \"-//W3C//DTD HTML 4.01//EN\" 'http://www.w3.org/TR/html4/strict.dtd'>"),
+      Some(HtmlToken::Doctype {
+        name: Some("html".to_string()),
+        public_id: Some("-//W3C//DTD HTML 4.01//EN".to_string()),
+        system_id: Some("http://www.w3.org/TR/html4/strict.dtd".to_string()),
+        force_quirks: false,
+      }),
+    );
+    assert_eq!(doctype("<!DOCTYPE html SYSTEM \"about:legacy-compat\">"), Some(HtmlToken::Doctype {
+      name: Some("html".to_string()),
+      public_id: None,
+      system_id: Some("about:legacy-compat".to_string()),
+      force_quirks: false,
+    }));
+    assert_eq!(doctype("<!DOCTYPE>"), Some(HtmlToken::Doctype {
+      name: None,
+      public_id: None,
+      system_id: None,
+      force_quirks: true,
+    }));
+    assert_eq!(doctype("<!DOCTYPE html bogus>"), Some(HtmlToken::Doctype {
+      name: Some("html".to_string()),
+      public_id: None,
+      system_id: None,
+      force_quirks: true,
+    }));
+  }
+
+  #[test]
+  fn test_comment_split_across_chunks() {
+    let mut tokenizer = HtmlTokenizer::new_incremental();
+    tokenizer.feed("<!");
+    assert_eq!(tokenizer.next(), None);
+    tokenizer.feed("-- a -");
+    assert_eq!(tokenizer.next(), None);
+    tokenizer.feed("-><!DOC");
+    assert_eq!(tokenizer.next(), Some(HtmlToken::Comment(" a ".to_string())));
+    assert_eq!(tokenizer.next(), None);
+    tokenizer.feed("TYPE html PUB");
+    assert_eq!(tokenizer.next(), None);
+    tokenizer.feed("LIC \"x\">");
+    assert_eq!(tokenizer.next(), Some(HtmlToken::Doctype {
+      name: Some("html".to_string()),
+      public_id: Some("x".to_string()),
+      system_id: None,
+      force_quirks: false,
+    }));
+  }
+
+  #[test]
+  fn test_feed_in_chunks() {
+    let mut tokenizer = HtmlTokenizer::new_incremental();
+    tokenizer.feed("a<di");
+    assert_eq!(tokenizer.next(), Some(HtmlToken::Char('a')));
+    assert_eq!(tokenizer.next(), None);
+
+    let mut attr = Attribute::new();
+    attr.add_char('c', true);
+    attr.add_char('x', false);
+    tokenizer.feed("v c=x");
+    assert_eq!(tokenizer.next(), None);
+    tokenizer.feed(">b");
+    assert_eq!(tokenizer.next(), Some(HtmlToken::StartTag {
+      tag: "div".to_string(),
+      self_closing: false,
+      attributes: vec![attr],
+    }));
+    assert_eq!(tokenizer.next(), Some(HtmlToken::Char('b')));
+    assert_eq!(tokenizer.next(), None);
+    assert!(!tokenizer.is_finished());
+
+    tokenizer.finish();
+    assert_eq!(tokenizer.next(), None);
+  }
+
+  #[test]
+  fn test_feed_drops_consumed_input() {
+    let mut tokenizer = HtmlTokenizer::new_incremental();
+    let text = "a".repeat(COMPACTION_THRESHOLD);
+    for _ in 0..4 {
+      tokenizer.feed(&text);
+      while tokenizer.next().is_some() {}
+    }
+    tokenizer.feed("<p");
+    assert_eq!(tokenizer.next(), None);
+    assert!(tokenizer.input.len() <= COMPACTION_THRESHOLD + 3);
+
+    tokenizer.feed(">");
+    assert_eq!(tokenizer.next(), Some(HtmlToken::StartTag {
+      tag: "p".to_string(),
+      self_closing: false,
+      attributes: Vec::new(),
+    }));
+  }
+
+  #[test]
+  fn test_cdata_split_across_chunks() {
+    let mut tokenizer = HtmlTokenizer::new_incremental();
+    tokenizer.set_cdata_allowed(true);
+    tokenizer.feed("<![CD");
+    assert_eq!(tokenizer.next(), None);
+    tokenizer.feed("ATA[x]]>");
+    assert_eq!(tokenizer.next(), Some(HtmlToken::Char('x')));
+  }
+
+  #[test]
+  fn test_newline_normalization() {
+    let mut tokenizer = HtmlTokenizer::new_incremental();
+    tokenizer.feed("a\r\nb\r");
+    tokenizer.feed("\nc\rd");
+    tokenizer.finish();
+    let chars: String = tokenizer
+      .filter_map(|t| match t {
+        HtmlToken::Char(c) => Some(c),
+        _ => None,
+      })
+      .collect();
+    assert_eq!("a\nb\nc\nd", chars);
+  }
+
+  #[test]
+  fn test_whitespace_in_tag() {
+    let html = "<p\tclass =\x0Cx\nid\r\n=y>".to_string();
+    let mut tokenizer = HtmlTokenizer::new(html);
+    let mut class = Attribute::new();
+    class.add_char('c', true);
+    class.add_char('l', true);
+    class.add_char('a', true);
+    class.add_char('s', true);
+    class.add_char('s', true);
+    class.add_char('x', false);
+    let mut id = Attribute::new();
+    id.add_char('i', true);
+    id.add_char('d', true);
+    id.add_char('y', false);
+    assert_eq!(tokenizer.next(), Some(HtmlToken::StartTag {
+      tag: "p".to_string(),
+      self_closing: false,
+      attributes: vec![class, id],
+    }));
+  }
+}
@@ -1,8 +1,9 @@
 use core::cell::RefCell;
+use core::str::FromStr;
 
 use alloc::{format, rc::Rc, string::{String, ToString}};
 
-use crate::{error::Error, renderer::dom::node::{ElementKind, Node, NodeKind}};
+use crate::{error::Error, renderer::dom::node::{Node, NodeKind}};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct ComputedStyle {
@@ -110,10 +111,10 @@ impl ComputedStyle {
       self.display = Some(DisplayType::default(node));
     }
     if self.font_size.is_none() {
-      self.font_size = Some(FontSize::default(node));
+      self.font_size = Some(FontSize::Medium);
     }
     if self.text_decoration.is_none() {
-      self.text_decoration = Some(TextDecoration::default(node));
+      self.text_decoration = Some(TextDecoration::None);
     }
     if self.height.is_none() {
       self.height = Some(0.0);
@@ -186,16 +187,15 @@ pub enum FontSize {
   XXLarge,
 }
 
-impl FontSize {
-  fn default(node: &Rc<RefCell<Node>>) -> Self {
-    match node.borrow().kind() {
-      NodeKind::Element(element) => match element.kind() {
-        ElementKind::H1 => Self::XXLarge,
-        ElementKind::H2 => Self::XLarge,
-        ElementKind::P => Self::Medium,
-        _ => Self::Medium,
-      },
-      _ => Self::Medium,
+impl FromStr for FontSize {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self, Error> {
+    match s {
+      "medium" => Ok(Self::Medium),
+      "x-large" => Ok(Self::XLarge),
+      "xx-large" => Ok(Self::XXLarge),
+      _ => Err(Error::UnexpectedInput(format!("unexpected font size: {}", s))),
     }
   }
 }
@@ -210,15 +210,10 @@ pub enum DisplayType {
 impl DisplayType {
   fn default(node: &Rc<RefCell<Node>>) -> Self {
     match node.borrow().kind() {
-      NodeKind::Document => DisplayType::Block,
-      NodeKind::Element(e) => {
-        if e.is_block_element() {
-          DisplayType::Block
-        } else {
-          DisplayType::Inline
-        }
-      }
-      NodeKind::Text(_) => DisplayType::Inline,
+      NodeKind::Document | NodeKind::DocumentFragment => DisplayType::Block,
+      // Elements are inline unless a stylesheet, such as the user agent one, says otherwise.
+      NodeKind::Element(_) | NodeKind::Text(_) => DisplayType::Inline,
+      NodeKind::DocumentType { .. } | NodeKind::Comment(_) => DisplayType::DisplayNone,
     }
   }
 
@@ -238,14 +233,14 @@ pub enum TextDecoration {
   Underline,
 }
 
-impl TextDecoration {
-  fn default(node: &Rc<RefCell<Node>>) -> Self {
-    match node.borrow().kind() {
-      NodeKind::Element(e) => match e.kind() {
-        ElementKind::A => TextDecoration::Underline,
-        _ => TextDecoration::None,
-      }
-      _ => TextDecoration::None,
+impl FromStr for TextDecoration {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self, Error> {
+    match s {
+      "none" => Ok(Self::None),
+      "underline" => Ok(Self::Underline),
+      _ => Err(Error::UnexpectedInput(format!("unexpected text decoration: {}", s))),
     }
   }
 }
\ No newline at end of file
@@ -1,10 +1,10 @@
 use core::cell::RefCell;
 
-use alloc::{rc::{Rc, Weak}, string::{String, ToString}, vec::Vec};
+use alloc::{format, rc::{Rc, Weak}, string::{String, ToString}, vec::Vec};
 
-use crate::{constants::{CHAR_HEIGHT_WITH_PADDING, CHAR_WIDTH, CONTENT_AREA_HEIGHT, CONTENT_AREA_WIDTH, WINDOW_PADDING, WINDOW_WIDTH}, display_items::DisplayItem, renderer::{css::cssom::{ComponentValue, Declaration, Selector, StyleSheet}, dom::node::{Node, NodeKind}}};
+use crate::{constants::{CHAR_HEIGHT_WITH_PADDING, CHAR_WIDTH, CONTENT_AREA_HEIGHT, CONTENT_AREA_WIDTH, WINDOW_PADDING, WINDOW_WIDTH}, display_items::DisplayItem, renderer::{css::{cascade::{cascade, Origin}, cssom::{ComponentValue, Declaration, StyleSheet}, selector::{matches_selector_list, ComplexSelector}, token::CssToken}, dom::node::{Node, NodeKind}}};
 
-use super::computed_style::{Color, ComputedStyle, DisplayType, FontSize};
+use super::computed_style::{Color, ComputedStyle, DisplayType, FontSize, TextDecoration};
 use alloc::vec;
 
 #[derive(Debug, Clone)]
@@ -19,6 +19,23 @@ pub struct LayoutObject {
   size: LayoutSize,
 }
 
+// Drops the first_child/next_sibling chains iteratively, as `Node` does, so that long sibling
+// lists do not overflow the stack.
+impl Drop for LayoutObject {
+  fn drop(&mut self) {
+    let mut stack = Vec::new();
+    stack.extend(self.first_child.take());
+    stack.extend(self.next_sibling.take());
+    while let Some(object) = stack.pop() {
+      if let Ok(object) = Rc::try_unwrap(object) {
+        let mut object = object.into_inner();
+        stack.extend(object.first_child.take());
+        stack.extend(object.next_sibling.take());
+      }
+    }
+  }
+}
+
 impl LayoutObject {
   pub fn new(node: Rc<RefCell<Node>>, parent_obj: &Option<Rc<RefCell<LayoutObject>>>) -> Self {
     let parent = match parent_obj {
@@ -77,42 +94,31 @@ impl LayoutObject {
     self.size
   }
 
-  pub fn is_node_selected(&self, selector: &Selector) -> bool {
-    match &self.node_kind() {
-      NodeKind::Element(e) => match selector {
-        Selector::TypeSelector(type_name) => {
-          if e.kind().to_string() == *type_name {
-            return true;
-          }
-          false
-        }
-        Selector::ClassSelector(class_name) => {
-          for attr in &e.attributes() {
-            if attr.name() == "class" && attr.value() == *class_name {
-              return true;
-            }
-          }
-          false
-        }
-        Selector::IdSelector(id_name) => {
-          for attr in &e.attributes() {
-            if attr.name() == "id" && attr.value() == *id_name {
-              return true;
-            }
-          }
-          false
-        }
-        Selector::UnknownSelector => false,
-      },
-      _ => false,
-    }
+  pub fn node(&self) -> Rc<RefCell<Node>> {
+    self.node.clone()
+  }
+
+  pub fn contains(&self, point: LayoutPoint) -> bool {
+    self.point.x() <= point.x()
+      && point.x() < self.point.x() + self.size.width()
+      && self.point.y() <= point.y()
+      && point.y() < self.point.y() + self.size.height()
+  }
+
+  pub fn is_node_selected(&self, selectors: &[ComplexSelector]) -> bool {
+    matches_selector_list(selectors, &self.node)
   }
 
   pub fn cascading_style(&mut self, declarations: Vec<Declaration>) {
     for declaration in declarations {
+      // Only values made of a single token are understood so far.
+      let token = match declaration.value.as_slice() {
+        [ComponentValue::PreservedToken(token)] => token.clone(),
+        _ => continue,
+      };
       match declaration.property.as_str() {
         "background-color" => {
-          if let ComponentValue::Ident(value) = &declaration.value {
+          if let CssToken::Ident(value) = &token {
             let color = match Color::from_name(&value) {
               Ok(color) => color,
               Err(_) => Color::white(),
@@ -120,8 +126,8 @@ impl LayoutObject {
             self.style.set_background_color(color);
             continue;
           }
-          if let ComponentValue::HashToken(color_code) = &declaration.value {
-            let color = match Color::from_code(&color_code) {
+          if let CssToken::HashToken(color_code, _) = &token {
+            let color = match Color::from_code(&format!("#{}", color_code)) {
               Ok(color) => color,
               Err(_) => Color::white(),
             };
@@ -130,7 +136,7 @@ impl LayoutObject {
           }
         }
         "color" => {
-          if let ComponentValue::Ident(value) = &declaration.value {
+          if let CssToken::Ident(value) = &token {
             let color = match Color::from_name(&value) {
               Ok(color) => color,
               Err(_) => Color::black(),
@@ -139,8 +145,8 @@ impl LayoutObject {
             continue;
           }
 
-          if let ComponentValue::HashToken(color_code) = &declaration.value {
-            let color = match Color::from_code(&color_code) {
+          if let CssToken::HashToken(color_code, _) = &token {
+            let color = match Color::from_code(&format!("#{}", color_code)) {
               Ok(color) => color,
               Err(_) => Color::black(),
             };
@@ -149,7 +155,7 @@ impl LayoutObject {
           }
         }
         "display" => {
-          if let ComponentValue::Ident(value) = &declaration.value {
+          if let CssToken::Ident(value) = &token {
             let display = match DisplayType::from_str(&value) {
               Ok(display) => display,
               Err(_) => DisplayType::DisplayNone,
@@ -158,6 +164,22 @@ impl LayoutObject {
             continue;
           }
         }
+        "font-size" => {
+          if let CssToken::Ident(value) = &token {
+            if let Ok(font_size) = value.parse::<FontSize>() {
+              self.style.set_font_size(font_size);
+            }
+            continue;
+          }
+        }
+        "text-decoration" => {
+          if let CssToken::Ident(value) = &token {
+            if let Ok(text_decoration) = value.parse::<TextDecoration>() {
+              self.style.set_text_decoration(text_decoration);
+            }
+            continue;
+          }
+        }
         _ => {}
       }
     }
@@ -173,7 +195,7 @@ impl LayoutObject {
 
   pub fn update_kind(&mut self) {
     match self.node_kind() {
-      NodeKind::Document => panic!("Document node is not allowed"),
+      NodeKind::Document | NodeKind::DocumentFragment => panic!("Document node is not allowed"),
       NodeKind::Element(_) => {
         let display = self.style.display();
         match display {
@@ -191,6 +213,7 @@ impl LayoutObject {
       NodeKind::Text(_) => {
         self.kind = LayoutObjectKind::Text;
       }
+      NodeKind::DocumentType { .. } | NodeKind::Comment(_) => panic!("{:?} is not rendered", self.node_kind()),
     }
   }
   pub fn compute_size(&mut self, parent_size: LayoutSize) {
@@ -418,15 +441,12 @@ impl LayoutSize {
 pub fn create_layout_object(
   node: &Option<Rc<RefCell<Node>>>,
   parent_obj: &Option<Rc<RefCell<LayoutObject>>>,
-  cssom: &StyleSheet,
+  sheets: &[(Origin, &StyleSheet)],
 ) -> Option<Rc<RefCell<LayoutObject>>> {
   if let Some(n) = node {
     let layout_object = Rc::new(RefCell::new(LayoutObject::new(n.clone(), parent_obj)));
-    for rule in &cssom.rules {
-      if layout_object.borrow().is_node_selected(&rule.selector) {
-        layout_object.borrow_mut().cascading_style(rule.declarations.clone());
-      }
-    }
+    let declarations = cascade(n, sheets);
+    layout_object.borrow_mut().cascading_style(declarations);
 
     let parent_style = if let Some(parent) = parent_obj {
       Some(parent.borrow().style())
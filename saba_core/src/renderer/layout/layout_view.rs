@@ -1,8 +1,8 @@
 use core::cell::RefCell;
 
-use alloc::{rc::Rc, vec::Vec};
+use alloc::{rc::Rc, vec, vec::Vec};
 
-use crate::{constants::CONTENT_AREA_WIDTH, display_items::DisplayItem, renderer::{css::cssom::StyleSheet, dom::{api::get_target_element_node, node::{ElementKind, Node}}}};
+use crate::{constants::CONTENT_AREA_WIDTH, display_items::DisplayItem, renderer::{css::{cascade::Origin, cssom::StyleSheet}, dom::{api::get_target_element_node, mutation::clear_dirty_flags, node::{ElementKind, Node}}}};
 
 use super::layout_object::{create_layout_object, LayoutObject, LayoutObjectKind, LayoutPoint, LayoutSize};
 
@@ -13,16 +13,21 @@ pub struct LayoutView {
 }
 
 impl LayoutView {
+  /// Lays out the DOM under `root` with `sheets`, such as the user agent stylesheet and the
+  /// author stylesheet of the document.
   pub fn new(
     root: Rc<RefCell<Node>>,
-    cssom: &StyleSheet,
+    sheets: &[(Origin, &StyleSheet)],
   ) -> Self {
-    let body_root = get_target_element_node(Some(root), ElementKind::Body);
+    let body_root = get_target_element_node(Some(root.clone()), ElementKind::Body);
+
+    let root_object = build_layout_tree(&body_root, &None, sheets);
 
     let mut tree = Self {
-      root: build_layout_tree(&body_root, &None, cssom),
+      root: root_object,
     };
 
+    clear_dirty_flags(&root);
     tree.update_layout();
 
     tree
@@ -32,6 +37,32 @@ impl LayoutView {
     self.root.clone()
   }
 
+  /// Brings the tree up to date with the dirty flags of the DOM under `root` and clears them.
+  /// Only the layout objects under nodes whose children changed are rebuilt, and the layout is
+  /// recomputed only when something changed.
+  pub fn update(&mut self, root: Rc<RefCell<Node>>, sheets: &[(Origin, &StyleSheet)]) {
+    if !root.borrow().dirty_flags().is_dirty() {
+      return;
+    }
+
+    let body_root = get_target_element_node(Some(root.clone()), ElementKind::Body);
+    let rebuild = match (&self.root, &body_root) {
+      (Some(object), Some(body)) => {
+        !Rc::ptr_eq(&object.borrow().node(), body) || body.borrow().dirty_flags().style
+      }
+      (None, None) => false,
+      _ => true,
+    };
+    if rebuild {
+      self.root = build_layout_tree(&body_root, &None, sheets);
+    } else if let Some(ref object) = self.root {
+      rebuild_dirty_children(object, sheets);
+    }
+
+    clear_dirty_flags(&root);
+    self.update_layout();
+  }
+
   pub fn update_layout(&mut self) {
     Self::calculate_node_size(&self.root, LayoutSize::new(CONTENT_AREA_WIDTH, 0));
 
@@ -45,7 +76,8 @@ impl LayoutView {
   }
 
   fn calculate_node_size(node: &Option<Rc<RefCell<LayoutObject>>>, parent_size: LayoutSize) {
-    if let Some(n) = node {
+    let mut node = node.clone();
+    while let Some(n) = node {
       if n.borrow().kind() == LayoutObjectKind::Block {
         n.borrow_mut().compute_size(parent_size);
       }
@@ -53,10 +85,8 @@ impl LayoutView {
       let first_child = n.borrow().first_child();
       Self::calculate_node_size(&first_child, n.borrow().size());
 
-      let next_sibling = n.borrow().next_sibling();
-      Self::calculate_node_size(&next_sibling, parent_size);
-
       n.borrow_mut().compute_size(parent_size);
+      node = n.borrow().next_sibling();
     }
   }
 
@@ -67,7 +97,11 @@ impl LayoutView {
     previous_sibling_point: Option<LayoutPoint>,
     previous_sibling_size: Option<LayoutSize>,
   ) {
-    if let Some(n) = node {
+    let mut previous_sibling_kind = previous_sibling_kind;
+    let mut previous_sibling_point = previous_sibling_point;
+    let mut previous_sibling_size = previous_sibling_size;
+    let mut node = node.clone();
+    while let Some(n) = node {
       n.borrow_mut().compute_position(
         parent_point,
         previous_sibling_kind,
@@ -84,29 +118,37 @@ impl LayoutView {
         None,
       );
 
-      let next_sibling = n.borrow().next_sibling();
-      Self::calculate_node_position(
-        &next_sibling,
-        parent_point,
-        n.borrow().kind(),
-        Some(n.borrow().point()),
-        Some(n.borrow().size()),
-      );
+      previous_sibling_kind = n.borrow().kind();
+      previous_sibling_point = Some(n.borrow().point());
+      previous_sibling_size = Some(n.borrow().size());
+      node = n.borrow().next_sibling();
     }
   }
 
   fn paint_node(node: &Option<Rc<RefCell<LayoutObject>>>, display_items: &mut Vec<DisplayItem>) {
-    match node {
-      Some(n) => {
-        display_items.extend(n.borrow_mut().paint());
-        let first_child = n.borrow().first_child();
-        Self::paint_node(&first_child, display_items);
-
-        let next_sibling = n.borrow().next_sibling();
-        Self::paint_node(&next_sibling, display_items);
+    let mut node = node.clone();
+    while let Some(n) = node {
+      display_items.extend(n.borrow_mut().paint());
+      let first_child = n.borrow().first_child();
+      Self::paint_node(&first_child, display_items);
+
+      node = n.borrow().next_sibling();
+    }
+  }
+
+  /// Returns the node of the innermost layout object that contains `position`.
+  pub fn find_node_by_position(&self, position: LayoutPoint) -> Option<Rc<RefCell<Node>>> {
+    let mut found = None;
+    let mut object = self.root.clone();
+    while let Some(o) = object {
+      if o.borrow().contains(position) {
+        found = Some(o.borrow().node());
+        object = o.borrow().first_child();
+      } else {
+        object = o.borrow().next_sibling();
       }
-      None => {}
     }
+    found
   }
 
   pub fn paint(&self) -> Vec<DisplayItem> {
@@ -116,64 +158,57 @@ impl LayoutView {
   }
 }
 
+// Builds layout objects for `node` and its following siblings, skipping nodes that do not
+// generate one, and returns the first of them. Recurses only into children, so long sibling lists
+// do not grow the stack.
 fn build_layout_tree(
   node: &Option<Rc<RefCell<Node>>>,
   parent_obj: &Option<Rc<RefCell<LayoutObject>>>,
-  cssom: &StyleSheet,
+  sheets: &[(Origin, &StyleSheet)],
 ) -> Option<Rc<RefCell<LayoutObject>>>
 {
+  let mut first: Option<Rc<RefCell<LayoutObject>>> = None;
+  let mut previous: Option<Rc<RefCell<LayoutObject>>> = None;
   let mut target_node = node.clone();
-  let mut layout_object = create_layout_object(node, parent_obj, cssom);
-  while layout_object.is_none() {
-    if let Some(n) = target_node {
-      target_node = n.borrow().next_sibling().clone();
-      layout_object = create_layout_object(&target_node, parent_obj, cssom);
-    } else {
-      return layout_object;
+  while let Some(n) = target_node {
+    let layout_object = create_layout_object(&Some(n.clone()), parent_obj, sheets);
+    if let Some(ref obj) = layout_object {
+      let first_child = build_layout_tree(&n.borrow().first_child(), &layout_object, sheets);
+      obj.borrow_mut().set_first_child(first_child);
+
+      match previous {
+        Some(ref p) => p.borrow_mut().set_next_sibling(Some(obj.clone())),
+        None => first = Some(obj.clone()),
+      }
+      previous = layout_object;
     }
+    target_node = n.borrow().next_sibling();
   }
 
-  if let Some(n) = target_node {
-    let original_first_child = n.borrow().first_child();
-    let original_next_sibling = n.borrow().next_sibling();
-    let mut first_child = build_layout_tree(&original_first_child, &layout_object, cssom);
-    let mut next_sibling = build_layout_tree(&original_next_sibling, &None, cssom);
-
-    if first_child.is_none() && original_first_child.is_some() {
-      let mut original_dom_node = original_first_child.expect("original_first_child is not None").borrow().next_sibling();
-      loop {
-        first_child = build_layout_tree(&original_dom_node, &layout_object, cssom);
-        if first_child.is_none() && original_dom_node.is_some() {
-          original_dom_node = original_dom_node.expect("original_dom_node is not None").borrow().next_sibling();
-          continue;
-        }
+  first
+}
 
-        break;
-      }
+// Rebuilds the children of the layout objects whose node has `child_style` set, descending only
+// into layout objects whose node has dirty descendants.
+fn rebuild_dirty_children(root: &Rc<RefCell<LayoutObject>>, sheets: &[(Origin, &StyleSheet)]) {
+  let mut stack = vec![root.clone()];
+  while let Some(object) = stack.pop() {
+    let node = object.borrow().node();
+    let flags = node.borrow().dirty_flags();
+    if flags.child_style {
+      let first_child = build_layout_tree(&node.borrow().first_child(), &Some(object.clone()), sheets);
+      object.borrow_mut().set_first_child(first_child);
+      continue;
     }
-
-    if next_sibling.is_none() && original_next_sibling.is_some() {
-      let mut original_dom_node = original_next_sibling.expect("original_next_sibling is not None").borrow().next_sibling();
-      loop {
-        next_sibling = build_layout_tree(&original_dom_node, &None, cssom);
-        if next_sibling.is_none() && original_dom_node.is_some() {
-          original_dom_node = original_dom_node.expect("original_dom_node is not None").borrow().next_sibling();
-          continue;
-        }
-
-        break;
-      }
+    if !flags.descendant_style {
+      continue;
+    }
+    let mut child = object.borrow().first_child();
+    while let Some(c) = child {
+      child = c.borrow().next_sibling();
+      stack.push(c);
     }
-
-    let obj = match layout_object {
-      Some(ref obj) => obj,
-      None => panic!("layout_object is None"),
-    };
-    obj.borrow_mut().set_first_child(first_child);
-    obj.borrow_mut().set_next_sibling(next_sibling);
   }
-
-  layout_object
 }
 
 #[cfg(test)]
@@ -182,25 +217,144 @@ mod texts {
 
   use super::*;
 
+  use crate::renderer::css::cascade::user_agent_stylesheet;
   use crate::renderer::css::cssom::CssParser;
   use crate::renderer::css::token::CssTokenizer;
   use crate::renderer::dom::api::get_style_content;
+  use crate::renderer::dom::api::{append_child, set_attribute, set_text_content};
+  use crate::renderer::dom::node::{Element, NodeKind};
   use crate::renderer::html::parser::HtmlParser;
   use crate::renderer::html::token::HtmlTokenizer;
 
-  fn create_layout_view(html: String) -> LayoutView {
+  fn parse(html: String) -> (Rc<RefCell<Node>>, StyleSheet) {
     let t = HtmlTokenizer::new(html);
     let window = HtmlParser::new(t).construct_tree();
     let dom = window.borrow().document();
     let style = get_style_content(dom.clone());
     let css_tokenizer = CssTokenizer::new(style);
     let cssom = CssParser::new(css_tokenizer).parse_stylesheet();
-    LayoutView::new(dom, &cssom)
+    (dom, cssom)
+  }
+
+  fn create_layout_view(html: String) -> LayoutView {
+    let (dom, cssom) = parse(html);
+    let user_agent = user_agent_stylesheet();
+    LayoutView::new(dom, &[(Origin::UserAgent, &user_agent), (Origin::Author, &cssom)])
+  }
+
+  fn child_objects(object: &Rc<RefCell<LayoutObject>>) -> Vec<Rc<RefCell<LayoutObject>>> {
+    let mut children = Vec::new();
+    let mut child = object.borrow().first_child();
+    while let Some(c) = child {
+      child = c.borrow().next_sibling();
+      children.push(c);
+    }
+    children
   }
 
   #[test]
   fn test_empty() {
+    // An empty document still gets a body, which lays out as an empty block.
     let layout_view = create_layout_view("".to_string());
-    assert_eq!(None, layout_view.root());
+    let root = layout_view.root().expect("root should exist");
+    assert_eq!(LayoutObjectKind::Block, root.borrow().kind());
+    assert!(root.borrow().first_child().is_none());
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn test_find_node_by_position() {
+    let layout_view = create_layout_view("<p>a</p><p><a href=x>b</a></p>".to_string());
+    let body = layout_view.root().expect("root should exist");
+    let p1 = body.borrow().first_child().expect("first p should exist");
+    let p2 = p1.borrow().next_sibling().expect("second p should exist");
+    let anchor = p2.borrow().first_child().expect("a should exist");
+
+    let inside = |o: &Rc<RefCell<LayoutObject>>| {
+      LayoutPoint::new(o.borrow().point().x() + 1, o.borrow().point().y() + 1)
+    };
+    let found = layout_view.find_node_by_position(inside(&anchor)).expect("a node should be hit");
+    assert_eq!(anchor.borrow().first_child().unwrap().borrow().node(), found);
+    let found = layout_view.find_node_by_position(inside(&p1)).expect("a node should be hit");
+    assert_eq!(NodeKind::Text("a".to_string()), found.borrow().kind());
+    assert_eq!(None, layout_view.find_node_by_position(LayoutPoint::new(-1, -1)));
+  }
+
+  #[test]
+  fn test_update() {
+    let (dom, cssom) =
+      parse("<style>.hidden{display:none;}</style><p>a</p><div><p>b</p></div>".to_string());
+    let user_agent = user_agent_stylesheet();
+    let sheets = [(Origin::UserAgent, &user_agent), (Origin::Author, &cssom)];
+    let mut layout_view = LayoutView::new(dom.clone(), &sheets);
+    assert!(!dom.borrow().dirty_flags().is_dirty());
+    let body = layout_view.root().expect("root should exist");
+    let p = child_objects(&body)[0].clone();
+    let div = child_objects(&body)[1].clone();
+    let height = body.borrow().size().height();
+
+    // Changing text keeps the layout objects and only lays them out again.
+    let text = p.borrow().first_child().unwrap().borrow().node();
+    set_text_content(&text, &"a".repeat(200));
+    layout_view.update(dom.clone(), &sheets);
+    assert!(Rc::ptr_eq(&p, &child_objects(&body)[0]));
+    assert!(body.borrow().size().height() > height);
+    assert!(!dom.borrow().dirty_flags().is_dirty());
+
+    // Restyling an element rebuilds its siblings but not the untouched subtree of its parent.
+    let p_node = p.borrow().node();
+    set_attribute(&p_node, "class", "hidden").unwrap();
+    layout_view.update(dom.clone(), &sheets);
+    let children = child_objects(&body);
+    assert_eq!(1, children.len());
+    assert_eq!(div.borrow().node(), children[0].borrow().node());
+
+    // Appending a node under the div only rebuilds the div's children.
+    let div = children[0].clone();
+    let div_node = div.borrow().node();
+    let new_p = Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new("p", Vec::new())))));
+    append_child(&div_node, &new_p).unwrap();
+    append_child(&new_p, &Rc::new(RefCell::new(Node::new(NodeKind::Text("c".to_string())))))
+      .unwrap();
+    layout_view.update(dom.clone(), &sheets);
+    assert!(Rc::ptr_eq(&div, &child_objects(&layout_view.root().unwrap())[0]));
+    assert_eq!(2, child_objects(&div).len());
+    assert!(!dom.borrow().dirty_flags().is_dirty());
+  }
+
+  #[test]
+  fn test_complex_selectors() {
+    let layout_view = create_layout_view(
+      "<style>div > p.x, h1 + p { display: none }</style><div><p class=x>a</p><p>b</p></div><h1>c</h1><p>d</p>"
+        .to_string(),
+    );
+    let body = layout_view.root().expect("root should exist");
+    let children = child_objects(&body);
+    assert_eq!(2, children.len());
+    assert_eq!(1, child_objects(&children[0]).len());
+    assert_eq!(
+      NodeKind::Element(Element::new("h1", Vec::new())),
+      children[1].borrow().node().borrow().kind(),
+    );
+  }
+
+  #[test]
+  fn test_user_agent_style() {
+    use crate::renderer::layout::computed_style::{FontSize, TextDecoration};
+
+    let layout_view = create_layout_view(
+      "<style>h2 { font-size: medium }</style><h1>a</h1><h2>b</h2><script>c</script><p><a href=x>d</a><a>e</a></p>"
+        .to_string(),
+    );
+    let body = layout_view.root().expect("root should exist");
+    let children = child_objects(&body);
+    assert_eq!(3, children.len());
+    assert_eq!(LayoutObjectKind::Block, children[0].borrow().kind());
+    assert_eq!(FontSize::XXLarge, children[0].borrow().style().font_size());
+    // Author styles override the user agent stylesheet.
+    assert_eq!(FontSize::Medium, children[1].borrow().style().font_size());
+    let anchors = child_objects(&children[2]);
+    assert_eq!(LayoutObjectKind::Inline, anchors[0].borrow().kind());
+    assert_eq!(TextDecoration::Underline, anchors[0].borrow().style().text_decoration());
+    assert_eq!(TextDecoration::None, anchors[1].borrow().style().text_decoration());
+  }
+}
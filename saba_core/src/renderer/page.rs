@@ -8,19 +8,28 @@ use alloc::string::String;
 use alloc::vec::Vec;
 use core::cell::RefCell;
 
+use super::css::cascade::user_agent_stylesheet;
+use super::css::cascade::Origin;
 use super::css::cssom::CssParser;
 use super::css::cssom::StyleSheet;
 use super::css::token::CssTokenizer;
 use super::dom::api::get_style_content;
+use super::dom::event::click;
+use super::dom::event::DefaultAction;
+use super::dom::metadata::DocumentMetadata;
 use super::html::parser::HtmlParser;
 use super::html::token::HtmlTokenizer;
+use super::layout::layout_object::LayoutPoint;
 use super::layout::layout_view::LayoutView;
 
 #[derive(Debug, Clone)]
 pub struct Page {
   browser: Weak<RefCell<Browser>>,
   frame: Option<Rc<RefCell<Window>>>,
+  parser: Option<HtmlParser>,
+  metadata: Option<DocumentMetadata>,
   style: Option<StyleSheet>,
+  user_agent: StyleSheet,
   layout_view: Option<LayoutView>,
   display_items: Vec<DisplayItem>,
 }
@@ -30,7 +39,10 @@ impl Page {
     Self {
       browser: Weak::new(),
       frame: None,
+      parser: None,
+      metadata: None,
       style: None,
+      user_agent: user_agent_stylesheet(),
       layout_view: None,
       display_items: Vec::new(),
     }
@@ -47,16 +59,49 @@ impl Page {
     self.paint_tree();
   }
 
+  pub fn receive_chunk(&mut self, chunk: &str) {
+    let parser = self
+      .parser
+      .get_or_insert_with(|| HtmlParser::new(HtmlTokenizer::new_incremental()));
+    parser.feed(chunk);
+    let frame = parser.window();
+    self.set_frame(frame);
+
+    self.set_layout_view();
+    self.paint_tree();
+  }
+
+  pub fn finish_response(&mut self) {
+    let frame = match self.parser.take() {
+      Some(mut parser) => parser.finish(),
+      None => return,
+    };
+    self.set_frame(frame);
+
+    self.set_layout_view();
+    self.paint_tree();
+  }
+
   fn create_frame(&mut self, html: String) {
     let html_tokenizer = HtmlTokenizer::new(html);
     let frame = HtmlParser::new(html_tokenizer).construct_tree();
+    self.set_frame(frame);
+  }
+
+  fn set_frame(&mut self, frame: Rc<RefCell<Window>>) {
     let dom = frame.borrow().document();
+    let metadata = DocumentMetadata::new(&dom);
 
     let style = get_style_content(dom);
     let css_tokenizer = CssTokenizer::new(style);
     let cssom = CssParser::new(css_tokenizer).parse_stylesheet();
 
+    // A new stylesheet may restyle any node, so the layout view is built again.
+    if self.style.as_ref() != Some(&cssom) {
+      self.layout_view = None;
+    }
     self.frame = Some(frame);
+    self.metadata = Some(metadata);
     self.style = Some(cssom);
   }
 
@@ -71,9 +116,12 @@ impl Page {
       None => return,
     };
 
-    let layout_view = LayoutView::new(dom, style);
-
-    self.layout_view = Some(layout_view);
+    let sheets = [(Origin::UserAgent, &self.user_agent), (Origin::Author, style)];
+    // Only the parts of the tree that changed since the last call are laid out again.
+    match self.layout_view {
+      Some(ref mut layout_view) => layout_view.update(dom, &sheets),
+      None => self.layout_view = Some(LayoutView::new(dom, &sheets)),
+    }
   }
 
   fn paint_tree(&mut self) {
@@ -82,6 +130,21 @@ impl Page {
     }
   }
 
+  /// Dispatches a click on the node at `position` and returns the default action to perform, such
+  /// as following a link, unless a listener canceled it.
+  pub fn clicked(&self, position: LayoutPoint) -> Option<DefaultAction> {
+    let node = self.layout_view.as_ref()?.find_node_by_position(position)?;
+    click(&node)
+  }
+
+  pub fn metadata(&self) -> Option<DocumentMetadata> {
+    self.metadata.clone()
+  }
+
+  pub fn title(&self) -> Option<String> {
+    self.metadata.as_ref().and_then(|m| m.title())
+  }
+
   pub fn display_items(&self) -> Vec<DisplayItem> {
     self.display_items.clone()
   }
@@ -1,24 +1,22 @@
 use core::cell::RefCell;
 
-use alloc::{format, rc::Rc, string::String};
+use alloc::{format, rc::Rc, string::String, vec::Vec};
 
 use crate::renderer::dom::node::Node;
 
 pub fn convert_dom_to_string(root: &Option<Rc<RefCell<Node>>>) -> String {
   let mut result = String::from("\n");
-  convert_dom_to_string_internal(root, 0, &mut result);
-  result
-}
-
-fn convert_dom_to_string_internal(node: &Option<Rc<RefCell<Node>>>, depth: usize, result: &mut String) {
-  match node {
-    Some(n) => {
-      result.push_str(&"  ".repeat(depth));
-      result.push_str(&format!("{:?}\n", n.borrow().kind));
-      result.push('\n');
-      convert_dom_to_string_internal(&n.borrow().first_child(), depth + 1, result);
-      convert_dom_to_string_internal(&n.borrow().next_sibling(), depth, result);
+  let mut stack: Vec<(Rc<RefCell<Node>>, usize)> = root.iter().map(|n| (n.clone(), 0)).collect();
+  while let Some((n, depth)) = stack.pop() {
+    result.push_str(&"  ".repeat(depth));
+    result.push_str(&format!("{:?}\n", n.borrow().kind));
+    result.push('\n');
+    if let Some(next) = n.borrow().next_sibling() {
+      stack.push((next, depth));
+    }
+    if let Some(child) = n.borrow().first_child() {
+      stack.push((child, depth + 1));
     }
-    None => (),
   }
-}
\ No newline at end of file
+  result
+}
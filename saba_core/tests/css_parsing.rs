@@ -0,0 +1,133 @@
+use std::fs;
+use std::path::Path;
+
+use saba_core::renderer::css::cssom::{ComponentValue, CssParser};
+use saba_core::renderer::css::token::{CssToken, CssTokenizer, HashType, NumericType};
+use serde_json::{json, Value};
+
+// Reads a fixture as pairs of an input and its expected result.
+fn fixture(name: &str) -> Vec<(String, Value)> {
+  let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/css-parsing-tests").join(name);
+  let source = fs::read_to_string(&path)
+    .unwrap_or_else(|e| panic!("failed to read {}: {}", path.display(), e));
+  let items: Vec<Value> = serde_json::from_str(&source).expect("failed to parse fixture");
+  items
+    .chunks(2)
+    .map(|pair| {
+      let input = pair[0].as_str().expect("input should be a string").to_string();
+      (input, normalize(&pair[1]))
+    })
+    .collect()
+}
+
+// Drops the representation of numeric values, which tokens do not keep, and compares every
+// number as a float.
+fn normalize(value: &Value) -> Value {
+  match value {
+    Value::Array(items) => {
+      let mut items: Vec<Value> = items.iter().map(normalize).collect();
+      let numeric = matches!(
+        items.first().and_then(|v| v.as_str()),
+        Some("number" | "percentage" | "dimension")
+      );
+      if numeric && items.len() > 1 {
+        items.remove(1);
+      }
+      Value::Array(items)
+    }
+    Value::Number(n) => json!(n.as_f64().expect("number should be finite")),
+    _ => value.clone(),
+  }
+}
+
+fn numeric_type(numeric_type: NumericType) -> &'static str {
+  match numeric_type {
+    NumericType::Integer => "integer",
+    NumericType::Number => "number",
+  }
+}
+
+fn token_value(token: &CssToken) -> Value {
+  match token {
+    CssToken::CloseParenthesis => json!(["error", ")"]),
+    CssToken::CloseSquare => json!(["error", "]"]),
+    CssToken::CloseCurly => json!(["error", "}"]),
+    CssToken::Ident(s) => json!(["ident", s]),
+    CssToken::Function(s) => json!(["function", s]),
+    CssToken::AtKeyword(s) => json!(["at-keyword", s]),
+    CssToken::HashToken(s, HashType::Id) => json!(["hash", s, "id"]),
+    CssToken::HashToken(s, HashType::Unrestricted) => json!(["hash", s, "unrestricted"]),
+    CssToken::StringToken(s) => json!(["string", s]),
+    CssToken::BadString => json!(["error", "bad-string"]),
+    CssToken::Url(s) => json!(["url", s]),
+    CssToken::BadUrl => json!(["error", "bad-url"]),
+    CssToken::Delim(c) => json!(c.to_string()),
+    CssToken::Number(v, t, _) => json!(["number", v, numeric_type(*t)]),
+    CssToken::Percentage(v) => json!(["percentage", v, "integer"]),
+    CssToken::Dimension(v, t, unit) => json!(["dimension", v, numeric_type(*t), unit]),
+    CssToken::UnicodeRange(start, end) => json!(["unicode-range", *start as f64, *end as f64]),
+    CssToken::Whitespace => json!(" "),
+    CssToken::Cdo => json!("<!--"),
+    CssToken::Cdc => json!("-->"),
+    CssToken::Colon => json!(":"),
+    CssToken::SemiColon => json!(";"),
+    CssToken::Comma => json!(","),
+    CssToken::OpenParenthesis => json!("("),
+    CssToken::OpenSquare => json!("["),
+    CssToken::OpenCurly => json!("{"),
+  }
+}
+
+// Converts a component value to the fixture format.
+fn component_value(value: &ComponentValue) -> Value {
+  match value {
+    ComponentValue::PreservedToken(token) => token_value(token),
+    ComponentValue::Function(name, values) => {
+      let mut function = vec![json!("function"), json!(name)];
+      function.extend(values.iter().map(component_value));
+      Value::Array(function)
+    }
+    ComponentValue::SimpleBlock(open, values) => {
+      let name = match open {
+        CssToken::OpenParenthesis => "()",
+        CssToken::OpenSquare => "[]",
+        _ => "{}",
+      };
+      let mut block = vec![json!(name)];
+      block.extend(values.iter().map(component_value));
+      Value::Array(block)
+    }
+  }
+}
+
+// Percentages carry no numeric type, so only their value is compared.
+fn without_percentage_type(value: &Value) -> Value {
+  match value {
+    Value::Array(items) if items.first() == Some(&json!("percentage")) => {
+      Value::Array(items.iter().take(2).cloned().collect())
+    }
+    Value::Array(items) => Value::Array(items.iter().map(without_percentage_type).collect()),
+    _ => value.clone(),
+  }
+}
+
+#[test]
+fn component_value_list() {
+  let mut failures = Vec::new();
+  let cases = fixture("component_value_list.json");
+  for (input, expected) in &cases {
+    let values = CssParser::new(CssTokenizer::new(input.clone())).parse_list_of_component_values();
+    let actual = Value::Array(values.iter().map(component_value).collect());
+    if without_percentage_type(&actual) != without_percentage_type(expected) {
+      failures.push(format!("{:?}\n  expected: {}\n  actual:   {}", input, expected, actual));
+    }
+  }
+
+  if std::env::var_os("CSS_PARSING_VERBOSE").is_some() {
+    for failure in &failures {
+      println!("FAIL {}", failure);
+    }
+  }
+  println!("component_value_list: {} passed, {} failed", cases.len() - failures.len(), failures.len());
+  assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
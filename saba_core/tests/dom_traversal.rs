@@ -0,0 +1,30 @@
+use core::cell::RefCell;
+use std::rc::Rc;
+
+use saba_core::renderer::css::cascade::{user_agent_stylesheet, Origin};
+use saba_core::renderer::dom::node::Node;
+use saba_core::renderer::dom::query::descendants;
+use saba_core::renderer::html::parser::HtmlParser;
+use saba_core::renderer::html::token::HtmlTokenizer;
+use saba_core::renderer::layout::layout_view::LayoutView;
+use saba_core::utils::convert_dom_to_string;
+
+fn parse(html: String) -> Rc<RefCell<Node>> {
+  let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+  let document = window.borrow().document();
+  document
+}
+
+// Every walk over the DOM and layout tree used to recurse on next_sibling, which overflowed the
+// stack of a test thread with a few thousand siblings. The walks are loops now; nodes are still
+// stored as `Rc<RefCell<Node>>`, there is no arena storage with stable node IDs.
+#[test]
+fn long_sibling_list() {
+  let document = parse("<p>x</p>".repeat(50_000));
+  assert_eq!(100_003, descendants(&document).count());
+
+  let user_agent = user_agent_stylesheet();
+  let layout_view = LayoutView::new(document.clone(), &[(Origin::UserAgent, &user_agent)]);
+  assert!(!layout_view.paint().is_empty());
+  assert!(convert_dom_to_string(&Some(document.clone())).len() > 100_000);
+}
@@ -0,0 +1,369 @@
+use core::cell::RefCell;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use saba_core::renderer::dom::dump::{dump_json, dump_tree};
+use saba_core::renderer::dom::node::{Element, Namespace, Node, NodeKind};
+use saba_core::renderer::html::parser::HtmlParser;
+use saba_core::renderer::html::token::{HtmlToken, HtmlTokenizer, State};
+use serde_json::{json, Map, Value};
+
+fn fixtures(dir: &str, extension: &str) -> Vec<PathBuf> {
+  let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/html5lib").join(dir);
+  let mut paths: Vec<PathBuf> = fs::read_dir(&dir)
+    .unwrap_or_else(|e| panic!("failed to read {}: {}", dir.display(), e))
+    .map(|entry| entry.expect("failed to read directory entry").path())
+    .filter(|path| path.extension().is_some_and(|e| e == extension))
+    .collect();
+  paths.sort();
+  paths
+}
+
+#[derive(Default)]
+struct Report {
+  passed: usize,
+  // (name, details) of every failing case.
+  failures: Vec<(String, String)>,
+}
+
+impl Report {
+  fn record(&mut self, passed: bool, name: String, details: impl FnOnce() -> String) {
+    if passed {
+      self.passed += 1;
+    } else {
+      self.failures.push((name, details()));
+    }
+  }
+
+  // Prints the counts and fails unless the failing cases are exactly `known_failures`.
+  fn finish(&self, suite: &str, known_failures: &[&str]) {
+    if std::env::var_os("HTML5LIB_VERBOSE").is_some() {
+      for (_, details) in &self.failures {
+        println!("FAIL {}", details);
+      }
+    }
+    println!("{}: {} passed, {} failed", suite, self.passed, self.failures.len());
+
+    let unexpected: Vec<&str> = self
+      .failures
+      .iter()
+      .filter(|(name, _)| !known_failures.contains(&name.as_str()))
+      .map(|(_, details)| details.as_str())
+      .collect();
+    let fixed: Vec<&&str> = known_failures
+      .iter()
+      .filter(|known| !self.failures.iter().any(|(name, _)| name == *known))
+      .collect();
+    assert!(unexpected.is_empty(), "{}: unexpected failures:\n{}", suite, unexpected.join("\n"));
+    assert!(fixed.is_empty(), "{}: known failures now pass, remove them from the list: {:?}", suite, fixed);
+  }
+}
+
+// Runs `f`, treating a panic as a failed test instead of aborting the whole run.
+fn run<T>(f: impl FnOnce() -> T) -> Option<T> {
+  let hook = panic::take_hook();
+  panic::set_hook(Box::new(|_| {}));
+  let result = panic::catch_unwind(AssertUnwindSafe(f)).ok();
+  panic::set_hook(hook);
+  result
+}
+
+fn unescape(s: &str) -> String {
+  let mut result = String::new();
+  let mut chars = s.chars().peekable();
+  while let Some(c) = chars.next() {
+    if c == '\\' && chars.peek() == Some(&'u') {
+      chars.next();
+      let hex: String = chars.by_ref().take(4).collect();
+      let code = u32::from_str_radix(&hex, 16).expect("invalid \\u escape");
+      result.push(char::from_u32(code).unwrap_or('\u{FFFD}'));
+    } else {
+      result.push(c);
+    }
+  }
+  result
+}
+
+fn unescape_value(value: &Value) -> Value {
+  match value {
+    Value::String(s) => Value::String(unescape(s)),
+    Value::Array(values) => Value::Array(values.iter().map(unescape_value).collect()),
+    Value::Object(map) => Value::Object(
+      map.iter().map(|(k, v)| (unescape(k), unescape_value(v))).collect(),
+    ),
+    _ => value.clone(),
+  }
+}
+
+fn initial_state(name: &str) -> State {
+  match name {
+    "Data state" => State::Data,
+    "PLAINTEXT state" => State::Plaintext,
+    "RCDATA state" => State::Rcdata,
+    "RAWTEXT state" => State::Rawtext,
+    "Script data state" => State::ScriptData,
+    "CDATA section state" => State::CdataSection,
+    _ => panic!("unknown initial state: {}", name),
+  }
+}
+
+fn push_character(output: &mut Vec<Value>, c: char) {
+  if let Some(Value::Array(last)) = output.last_mut() {
+    if last[0] == "Character" {
+      if let Value::String(ref mut s) = last[1] {
+        s.push(c);
+        return;
+      }
+    }
+  }
+  output.push(json!(["Character", c.to_string()]));
+}
+
+fn tokenize(input: &str, state: State, last_start_tag: Option<&str>) -> Vec<Value> {
+  let mut t = HtmlTokenizer::new(input.to_string());
+  t.switch_to(state);
+  if let Some(tag) = last_start_tag {
+    t.set_last_start_tag(tag.to_string());
+  }
+
+  let mut output = Vec::new();
+  for token in t {
+    match token {
+      HtmlToken::StartTag { tag, self_closing, attributes } => {
+        let mut attrs = Map::new();
+        for attr in attributes {
+          attrs.entry(attr.name()).or_insert(Value::String(attr.value()));
+        }
+        if self_closing {
+          output.push(json!(["StartTag", tag, attrs, true]));
+        } else {
+          output.push(json!(["StartTag", tag, attrs]));
+        }
+      }
+      HtmlToken::EndTag { tag } => output.push(json!(["EndTag", tag])),
+      HtmlToken::Char(c) => push_character(&mut output, c),
+      HtmlToken::Comment(data) => output.push(json!(["Comment", data])),
+      HtmlToken::Doctype { name, public_id, system_id, force_quirks } => {
+        output.push(json!(["DOCTYPE", name, public_id, system_id, !force_quirks]))
+      }
+      HtmlToken::Eof => break,
+    }
+  }
+  output
+}
+
+// Cases the tokenizer does not handle yet, as "<file> [<state>] <description>".
+// Character references and the `<` fallback in the tag open state are missing.
+const TOKENIZER_KNOWN_FAILURES: &[&str] = &[
+  "basic.test [Data state] Empty start tag",
+  "basic.test [Data state] Entity with trailing semicolon",
+  "basic.test [Data state] Less-than sign followed by a space",
+  "test2.test [Data state] Numeric entity representing the NUL character",
+  "test2.test [Data state] Hexadecimal entity representing the NUL character",
+  "test2.test [Data state] Numeric entity representing a codepoint after 1114111 (U+10FFFF)",
+  "test2.test [Data state] Hexadecimal entity with mixed uppercase and lowercase",
+  "test2.test [Data state] Unescaped </",
+  "test2.test [Data state] Unescaped <",
+  "test2.test [Data state] Entity + newline",
+  "textModes.test [RAWTEXT state] NULL in RAWTEXT",
+];
+
+#[test]
+fn tokenizer() {
+  let mut report = Report::default();
+  let paths = fixtures("tokenizer", "test");
+  assert!(!paths.is_empty(), "no tokenizer fixtures found");
+
+  for path in paths {
+    let source = fs::read_to_string(&path).expect("failed to read fixture");
+    let fixture: Value = serde_json::from_str(&source).expect("failed to parse fixture");
+    let tests = fixture["tests"].as_array().cloned().unwrap_or_default();
+
+    for test in tests {
+      let double_escaped = test["doubleEscaped"].as_bool().unwrap_or(false);
+      let mut input = test["input"].as_str().expect("input should be a string").to_string();
+      let mut expected = test["output"].clone();
+      if double_escaped {
+        input = unescape(&input);
+        expected = unescape_value(&expected);
+      }
+      let last_start_tag = test["lastStartTag"].as_str();
+      let states = match test["initialStates"].as_array() {
+        Some(states) => states.iter().filter_map(|s| s.as_str()).map(String::from).collect(),
+        None => vec![String::from("Data state")],
+      };
+
+      for state in states {
+        let actual = run(|| tokenize(&input, initial_state(&state), last_start_tag));
+        let passed = actual.as_ref().is_some_and(|a| Value::Array(a.clone()) == expected);
+        let name = format!(
+          "{} [{}] {}",
+          path.file_name().unwrap().to_string_lossy(),
+          state,
+          test["description"].as_str().unwrap_or(""),
+        );
+        report.record(passed, name.clone(), || {
+          format!(
+            "{}\n  expected: {}\n  actual:   {}",
+            name,
+            expected,
+            actual.map_or("panicked".to_string(), |a| Value::Array(a).to_string()),
+          )
+        });
+      }
+    }
+  }
+
+  report.finish("tokenizer", TOKENIZER_KNOWN_FAILURES);
+}
+
+struct TreeTest {
+  data: String,
+  fragment_context: Option<String>,
+  script_on: bool,
+  document: String,
+}
+
+fn parse_dat(source: &str) -> Vec<TreeTest> {
+  let mut tests = Vec::new();
+  let mut section = "";
+  let mut current: Option<TreeTest> = None;
+
+  for line in source.lines() {
+    if line == "#data" {
+      if let Some(test) = current.take() {
+        tests.push(test);
+      }
+      current = Some(TreeTest {
+        data: String::new(),
+        fragment_context: None,
+        script_on: false,
+        document: String::new(),
+      });
+      section = "#data";
+      continue;
+    }
+
+    let test = match current.as_mut() {
+      Some(test) => test,
+      None => continue,
+    };
+    if line.starts_with('#') {
+      section = line;
+      if line == "#script-on" {
+        test.script_on = true;
+      }
+      continue;
+    }
+    match section {
+      "#data" => {
+        test.data.push_str(line);
+        test.data.push('\n');
+      }
+      "#document-fragment" => test.fragment_context = Some(line.trim().to_string()),
+      "#document" => {
+        test.document.push_str(line);
+        test.document.push('\n');
+      }
+      _ => {}
+    }
+  }
+  if let Some(test) = current {
+    tests.push(test);
+  }
+
+  for test in tests.iter_mut() {
+    test.data.pop();
+    // Tests are separated by a blank line, which is not part of the expected tree.
+    while test.document.ends_with("\n\n") {
+      test.document.pop();
+    }
+  }
+  tests
+}
+
+fn context_element(context: &str) -> Rc<RefCell<Node>> {
+  let element = match context.split_once(' ') {
+    Some(("svg", name)) => Element::new_with_namespace(name, Vec::new(), Namespace::Svg),
+    Some(("math", name)) => Element::new_with_namespace(name, Vec::new(), Namespace::MathMl),
+    _ => Element::new(context, Vec::new()),
+  };
+  Rc::new(RefCell::new(Node::new(NodeKind::Element(element))))
+}
+
+fn build_tree(test: &TreeTest) -> String {
+  let mut out = String::new();
+  match test.fragment_context {
+    Some(ref context) => {
+      for node in HtmlParser::parse_fragment(&context_element(context), test.data.clone()) {
+        out.push_str(&dump_tree(&node));
+      }
+    }
+    None => {
+      let t = HtmlTokenizer::new(test.data.clone());
+      let window = HtmlParser::new(t).construct_tree();
+      let document = window.borrow().document();
+      out.push_str(&dump_tree(&document));
+    }
+  }
+  out
+}
+
+// Trees the parser does not build correctly yet, as "<file> <data>" with the data quoted. They
+// all come from the tokenizer dropping a `<` that does not start a tag.
+const TREE_CONSTRUCTION_KNOWN_FAILURES: &[&str] = &[
+  "tests1.dat \"<\"",
+  "tests1.dat \"<#\"",
+  "tests1.dat \"</\"",
+];
+
+#[test]
+fn tree_construction() {
+  let mut report = Report::default();
+  let paths = fixtures("tree-construction", "dat");
+  assert!(!paths.is_empty(), "no tree-construction fixtures found");
+
+  for path in paths {
+    let source = fs::read_to_string(&path).expect("failed to read fixture");
+    for test in parse_dat(&source) {
+      // The parser runs with scripting disabled.
+      if test.script_on {
+        continue;
+      }
+
+      let actual = run(|| build_tree(&test));
+      let passed = actual.as_deref() == Some(test.document.as_str());
+      let name = format!("{} {:?}", path.file_name().unwrap().to_string_lossy(), test.data);
+      report.record(passed, name.clone(), || {
+        format!(
+          "{}\n  expected:\n{}  actual:\n{}",
+          name,
+          test.document,
+          actual.unwrap_or_else(|| "panicked\n".to_string()),
+        )
+      });
+    }
+  }
+
+  report.finish("tree-construction", TREE_CONSTRUCTION_KNOWN_FAILURES);
+}
+
+// The JSON export has to stay valid for every tree the parser can build.
+#[test]
+fn json_dump() {
+  for path in fixtures("tree-construction", "dat") {
+    let source = fs::read_to_string(&path).expect("failed to read fixture");
+    for test in parse_dat(&source).into_iter().filter(|t| t.fragment_context.is_none()) {
+      let document = match run(|| HtmlParser::new(HtmlTokenizer::new(test.data.clone())).construct_tree()) {
+        Some(window) => window.borrow().document(),
+        None => continue,
+      };
+      let json = dump_json(&document);
+      let value: Value = serde_json::from_str(&json)
+        .unwrap_or_else(|e| panic!("invalid JSON for {:?}: {}\n{}", test.data, e, json));
+      assert_eq!(value["type"], "document");
+    }
+  }
+}